@@ -0,0 +1,85 @@
+//! Latency of `capture` with 16 threads capturing at once, while full
+//! batches go out over a transport that takes a few milliseconds per
+//! request. Run with `cargo bench --bench capture_contention`.
+
+use pulsekit::{
+    Config, Level, PulseKit, PulseKitError, Transport, TransportRequest, TransportResponse,
+};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const THREADS: usize = 16;
+const CAPTURES_PER_THREAD: usize = 5_000;
+
+/// Accepts every request after a delay, like a server a few ms away.
+struct SlowTransport;
+
+impl Transport for SlowTransport {
+    fn send(&self, _request: &TransportRequest) -> Result<TransportResponse, PulseKitError> {
+        thread::sleep(Duration::from_millis(2));
+        Ok(TransportResponse {
+            status: 202,
+            headers: Vec::new(),
+            body: Vec::new(),
+        })
+    }
+}
+
+/// Capture from every thread at once, returning each capture's latency
+/// sorted and the wall time the captures took.
+fn run() -> (Vec<Duration>, Duration) {
+    let client = Arc::new(PulseKit::with_transport(
+        Config {
+            batch_size: 100,
+            max_queue_size: THREADS * CAPTURES_PER_THREAD,
+            ..Default::default()
+        },
+        SlowTransport,
+    ));
+
+    let started = Instant::now();
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let client = client.clone();
+            thread::spawn(move || {
+                (0..CAPTURES_PER_THREAD)
+                    .map(|i| {
+                        let started = Instant::now();
+                        client.capture_message(&format!("request {} handled", i), Level::Info);
+                        started.elapsed()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut latencies: Vec<Duration> = threads
+        .into_iter()
+        .flat_map(|thread| thread.join().unwrap())
+        .collect();
+    let elapsed = started.elapsed();
+
+    client.flush_blocking();
+    latencies.sort();
+    (latencies, elapsed)
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    sorted[((sorted.len() - 1) as f64 * p) as usize]
+}
+
+fn main() {
+    // Warm up allocators and caches before measuring.
+    run();
+    let (latencies, elapsed) = run();
+    println!(
+        "{} threads x {} captures in {:.1?}: p50 {:.1?}, p99 {:.1?}, p99.9 {:.1?}, max {:.1?}",
+        THREADS,
+        CAPTURES_PER_THREAD,
+        elapsed,
+        percentile(&latencies, 0.50),
+        percentile(&latencies, 0.99),
+        percentile(&latencies, 0.999),
+        latencies[latencies.len() - 1],
+    );
+}
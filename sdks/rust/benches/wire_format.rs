@@ -0,0 +1,68 @@
+//! Time spent serializing batches in each wire format, and the size of the
+//! bodies. Run with `cargo bench --features msgpack`.
+
+use pulsekit::{Config, Level, MemoryTransport, PulseKit, WireFormat};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const BATCHES: usize = 200;
+const BATCH_SIZE: usize = 100;
+
+/// Flush `BATCHES` batches through a `MemoryTransport`, returning the time
+/// spent flushing and the total body size.
+fn run(wire_format: WireFormat) -> (Duration, usize) {
+    let transport = MemoryTransport::new();
+    let client = PulseKit::with_transport(
+        Config {
+            wire_format,
+            batch_size: BATCH_SIZE + 1,
+            max_events_per_request: Some(BATCH_SIZE),
+            ..Default::default()
+        },
+        transport.clone(),
+    );
+
+    let mut elapsed = Duration::ZERO;
+    for batch in 0..BATCHES {
+        for i in 0..BATCH_SIZE {
+            let tags = HashMap::from([
+                ("region".to_string(), "eu-west-1".to_string()),
+                ("route".to_string(), format!("/api/v1/items/{}", i)),
+            ]);
+            let metadata = HashMap::from([
+                ("batch".to_string(), serde_json::json!(batch)),
+                ("latency_ms".to_string(), serde_json::json!(i as f64 * 1.5)),
+                (
+                    "user".to_string(),
+                    serde_json::json!({ "id": i, "plan": "pro", "flags": [1, 2, 3] }),
+                ),
+            ]);
+            client.capture_message_with_options(
+                "Request handled slowly",
+                Level::Warning,
+                Some(tags),
+                Some(metadata),
+            );
+        }
+        let started = Instant::now();
+        client.flush_blocking();
+        elapsed += started.elapsed();
+    }
+
+    let bytes = transport.requests().iter().map(|r| r.body.len()).sum();
+    (elapsed, bytes)
+}
+
+fn main() {
+    for (name, format) in [("json", WireFormat::Json), ("msgpack", WireFormat::MsgPack)] {
+        // Warm up allocators and caches before measuring.
+        run(format);
+        let (elapsed, bytes) = run(format);
+        println!(
+            "{:<8} {:>8.1} us/batch {:>9} bytes/batch",
+            name,
+            elapsed.as_secs_f64() * 1e6 / BATCHES as f64,
+            bytes / BATCHES
+        );
+    }
+}
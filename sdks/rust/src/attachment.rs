@@ -0,0 +1,195 @@
+//! Files sent along with an event as a `multipart/form-data` request.
+
+use crate::debug_log::debug_log;
+use crate::{Event, PulseKit};
+
+/// A file sent with an event, such as a rendered config or a log excerpt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    /// Name the file is stored under
+    pub filename: String,
+    /// MIME type of the contents, e.g. `text/plain`
+    pub content_type: String,
+    /// The file contents
+    pub bytes: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn new(
+        filename: impl Into<String>,
+        content_type: impl Into<String>,
+        bytes: impl Into<Vec<u8>>,
+    ) -> Self {
+        Self {
+            filename: filename.into(),
+            content_type: content_type.into(),
+            bytes: bytes.into(),
+        }
+    }
+}
+
+/// The event's attachments, if it has any.
+pub(crate) fn attachments(event: &Event) -> Option<&[Attachment]> {
+    event
+        .attachments
+        .as_deref()
+        .filter(|attachments| !attachments.is_empty())
+}
+
+/// A `multipart/form-data` body with the event JSON as the `event` part and
+/// each attachment as an `attachment` file part.
+pub(crate) fn multipart_body(event: &[u8], attachments: &[Attachment], boundary: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    push_part(
+        &mut body,
+        boundary,
+        "form-data; name=\"event\"",
+        "application/json",
+        event,
+    );
+    for attachment in attachments {
+        let disposition = format!(
+            "form-data; name=\"attachment\"; filename=\"{}\"",
+            escape_filename(&attachment.filename)
+        );
+        push_part(
+            &mut body,
+            boundary,
+            &disposition,
+            &attachment.content_type,
+            &attachment.bytes,
+        );
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+fn push_part(
+    body: &mut Vec<u8>,
+    boundary: &str,
+    disposition: &str,
+    content_type: &str,
+    data: &[u8],
+) {
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: {}\r\nContent-Type: {}\r\n\r\n",
+            boundary, disposition, content_type
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(data);
+    body.extend_from_slice(b"\r\n");
+}
+
+/// Percent-encode the characters that would end the quoted filename or
+/// the header line, as browsers do.
+fn escape_filename(filename: &str) -> String {
+    filename
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+impl PulseKit {
+    /// Capture `event` with files attached. It is sent on its own to
+    /// `/api/v1/events/attachments` as `multipart/form-data`.
+    ///
+    /// Attachments that would take the total past `max_attachment_bytes`
+    /// are dropped. Attachments are not kept in `offline_storage`.
+    pub fn capture_with_attachments(&self, event: Event, attachments: Vec<Attachment>) {
+        self.capture(Event {
+            attachments: Some(attachments),
+            ..event
+        });
+    }
+
+    /// Drop the attachments that don't fit in `max_attachment_bytes`,
+    /// keeping the earlier ones.
+    pub(crate) fn limit_attachments(&self, event: &mut Event) {
+        let Some(attachments) = event.attachments.as_mut() else {
+            return;
+        };
+        let mut total = 0;
+        attachments.retain(|attachment| {
+            let fits = total + attachment.bytes.len() <= self.config.max_attachment_bytes;
+            if fits {
+                total += attachment.bytes.len();
+            } else {
+                debug_log!(
+                    self.config,
+                    Warn,
+                    "dropped attachment {} ({} bytes) over max_attachment_bytes",
+                    attachment.filename,
+                    attachment.bytes.len()
+                );
+            }
+            fits
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level, MemoryTransport};
+
+    #[test]
+    fn test_attachments_are_sent_as_multipart_within_the_size_limit() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                max_attachment_bytes: 16,
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        client.capture_message("no attachments", Level::Info);
+        client.capture_with_attachments(
+            Event {
+                event_type: "error".to_string(),
+                level: Some(Level::Error),
+                message: Some("config rejected".to_string()),
+                ..Default::default()
+            },
+            vec![
+                Attachment::new("app.toml", "text/plain", "port = 80\n"),
+                Attachment::new("huge.log", "text/plain", vec![b'x'; 64]),
+                Attachment::new("a\"b.txt", "text/plain", "ok"),
+            ],
+        );
+        client.flush_blocking();
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        let json = requests
+            .iter()
+            .find(|request| request.url.ends_with("/api/v1/events"))
+            .unwrap();
+        assert_eq!(json.header("Content-Type"), Some("application/json"));
+
+        let multipart = requests
+            .iter()
+            .find(|request| request.url.ends_with("/api/v1/events/attachments"))
+            .unwrap();
+        let content_type = multipart.header("Content-Type").unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        let body = String::from_utf8(multipart.body.clone()).unwrap();
+        let parts: Vec<&str> = body
+            .split(&format!("--{}", boundary))
+            .filter(|part| !part.is_empty())
+            .collect();
+        assert_eq!(parts.len(), 4);
+        assert!(parts[0].contains("name=\"event\""));
+        assert!(parts[0].contains("\"config rejected\""));
+        assert!(parts[0].contains("Content-Type: application/json"));
+        assert!(parts[1].contains("filename=\"app.toml\""));
+        assert!(parts[1].ends_with("\r\n\r\nport = 80\n\r\n"));
+        assert!(parts[2].contains("filename=\"a%22b.txt\""));
+        assert_eq!(parts[3], "--\r\n");
+        assert!(!body.contains("huge.log"));
+    }
+}
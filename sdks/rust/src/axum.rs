@@ -0,0 +1,317 @@
+//! Tower middleware that captures server errors and handler panics, for
+//! `axum` and other servers built on `tower` and `http`.
+
+use crate::panic::describe_payload;
+use crate::request::REQUEST_KEY;
+use crate::{ClientOverrides, Event, IsolationScoped, Level, Mechanism, PulseKit, RequestContext};
+use http::{Request, Response, StatusCode};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+type StatusFilter = Arc<dyn Fn(StatusCode) -> bool + Send + Sync>;
+
+/// A `tower` layer that captures responses with a server error status as
+/// `Error` events and handler panics as `Fatal` events.
+///
+/// Add it with `Router::layer(PulseKitLayer::new(&client))`. Events are
+/// tagged with `http.method`, `http.path` and `http.status`, and carry the
+/// request as the `request` metadata object, without credentials as
+/// described on [`RequestContext`]. Handlers run in an isolation scope
+/// holding the request, so events they capture carry it too.
+///
+/// A panic is re-raised once captured. If the client's panic hook is
+/// installed, the hook reports it instead, so it is not reported twice.
+#[derive(Clone)]
+pub struct PulseKitLayer {
+    client: Arc<PulseKit>,
+    capture_status: StatusFilter,
+}
+
+impl PulseKitLayer {
+    /// A layer that captures through a sub-client of `client`, sharing its
+    /// queue. Responses with a `5xx` status are captured.
+    pub fn new(client: &PulseKit) -> Self {
+        Self {
+            client: Arc::new(client.sub_client(ClientOverrides::default())),
+            capture_status: Arc::new(|status: StatusCode| status.is_server_error()),
+        }
+    }
+
+    /// Capture the responses whose status `filter` returns `true` for,
+    /// instead of those with a `5xx` status.
+    pub fn capture_status(
+        mut self,
+        filter: impl Fn(StatusCode) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.capture_status = Arc::new(filter);
+        self
+    }
+}
+
+impl fmt::Debug for PulseKitLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PulseKitLayer").finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for PulseKitLayer {
+    type Service = PulseKitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PulseKitService {
+            inner,
+            client: self.client.clone(),
+            capture_status: self.capture_status.clone(),
+        }
+    }
+}
+
+/// The service [`PulseKitLayer`] wraps around an inner service.
+#[derive(Clone)]
+pub struct PulseKitService<S> {
+    inner: S,
+    client: Arc<PulseKit>,
+    capture_status: StatusFilter,
+}
+
+impl<S: fmt::Debug> fmt::Debug for PulseKitService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PulseKitService")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for PulseKitService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let context = RequestContext::from(&request);
+        let mut scope = self.client.fork_isolation_scope();
+        scope.set_context(REQUEST_KEY, context.to_value());
+        ResponseFuture {
+            future: self.client.isolate(self.inner.call(request), scope),
+            client: self.client.clone(),
+            context,
+            capture_status: self.capture_status.clone(),
+        }
+    }
+}
+
+/// Future returned by [`PulseKitService`].
+pub struct ResponseFuture<F> {
+    future: IsolationScoped<F>,
+    client: Arc<PulseKit>,
+    context: RequestContext,
+    capture_status: StatusFilter,
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let result =
+            match panic::catch_unwind(AssertUnwindSafe(|| Pin::new(&mut this.future).poll(cx))) {
+                Ok(Poll::Ready(result)) => result,
+                Ok(Poll::Pending) => return Poll::Pending,
+                Err(payload) => {
+                    this.capture_panic(&*payload);
+                    panic::resume_unwind(payload)
+                }
+            };
+        if let Ok(response) = &result {
+            let status = response.status();
+            if (this.capture_status)(status) {
+                let message = format!(
+                    "{} {} responded {}",
+                    this.context.method, this.context.path, status
+                );
+                this.capture(
+                    Level::Error,
+                    message,
+                    Some(status),
+                    Mechanism::generic(),
+                    HashMap::new(),
+                );
+            }
+        }
+        Poll::Ready(result)
+    }
+}
+
+impl<F> ResponseFuture<F> {
+    fn capture_panic(&self, payload: &(dyn Any + Send)) {
+        if self
+            .client
+            .delivery
+            .panic_hook_installed
+            .load(Ordering::SeqCst)
+        {
+            return;
+        }
+        let (message, payload_type) = describe_payload(payload);
+        let metadata = HashMap::from([(
+            "panic_payload_type".to_string(),
+            serde_json::Value::from(payload_type),
+        )]);
+        self.capture(Level::Fatal, message, None, Mechanism::panic(), metadata);
+    }
+
+    fn capture(
+        &self,
+        level: Level,
+        message: String,
+        status: Option<StatusCode>,
+        mechanism: Mechanism,
+        mut metadata: HashMap<String, serde_json::Value>,
+    ) {
+        let mut tags = HashMap::from([
+            ("http.method".to_string(), self.context.method.clone()),
+            ("http.path".to_string(), self.context.path.clone()),
+        ]);
+        metadata.insert(REQUEST_KEY.to_string(), self.context.to_value());
+        if let Some(status) = status {
+            tags.insert("http.status".to_string(), status.as_u16().to_string());
+            metadata.insert(
+                "status".to_string(),
+                serde_json::Value::from(status.as_u16()),
+            );
+        }
+        self.client.capture(Event {
+            event_type: "error".to_string(),
+            level: Some(level),
+            message: Some(message),
+            tags: Some(tags),
+            metadata: Some(metadata),
+            mechanism: Some(mechanism),
+            ..Default::default()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::convert::Infallible;
+    use std::task::Waker;
+
+    /// A minimal router: `/fail` responds 500, `/missing` 404 and `/panic`
+    /// panics in the handler.
+    #[derive(Clone)]
+    struct App;
+
+    type Handler = Pin<Box<dyn Future<Output = Result<Response<()>, Infallible>> + Send>>;
+
+    impl Service<Request<()>> for App {
+        type Response = Response<()>;
+        type Error = Infallible;
+        type Future = Handler;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Handler {
+            let path = request.uri().path().to_string();
+            Box::pin(async move {
+                let status = match path.as_str() {
+                    "/fail" => StatusCode::INTERNAL_SERVER_ERROR,
+                    "/missing" => StatusCode::NOT_FOUND,
+                    "/panic" => panic!("handler exploded"),
+                    _ => StatusCode::OK,
+                };
+                Ok(Response::builder().status(status).body(()).unwrap())
+            })
+        }
+    }
+
+    fn get<S>(service: &mut S, uri: &str) -> S::Response
+    where
+        S: Service<Request<()>, Error = Infallible>,
+        S::Future: Unpin,
+    {
+        let request = Request::get(uri)
+            .header("cookie", "sid=s1")
+            .body(())
+            .unwrap();
+        let mut future = service.call(request);
+        match Pin::new(&mut future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(Ok(response)) => response,
+            Poll::Pending => panic!("handler did not complete"),
+        }
+    }
+
+    #[test]
+    fn test_server_errors_and_panics_are_captured_with_the_request() {
+        let client = PulseKit::new(Config::default());
+        let mut app = PulseKitLayer::new(&client).layer(App);
+
+        get(&mut app, "/ok");
+        get(&mut app, "/missing");
+        assert!(client.delivery.take_events().is_empty());
+
+        assert_eq!(get(&mut app, "/fail?id=4").status(), 500);
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].level, Some(Level::Error));
+        assert_eq!(
+            events[0].message.as_deref(),
+            Some("GET /fail responded 500 Internal Server Error")
+        );
+        let tags = events[0].tags.as_ref().unwrap();
+        assert_eq!(tags["http.method"], "GET");
+        assert_eq!(tags["http.path"], "/fail");
+        assert_eq!(tags["http.status"], "500");
+        let metadata = events[0].metadata.as_ref().unwrap();
+        assert_eq!(metadata["status"], 500);
+        assert_eq!(metadata[REQUEST_KEY]["query"], "id=4");
+        assert!(metadata[REQUEST_KEY].get("headers").is_none());
+
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| get(&mut app, "/panic")));
+        assert!(panicked.is_err());
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].level, Some(Level::Fatal));
+        assert_eq!(events[0].message.as_deref(), Some("handler exploded"));
+        assert_eq!(events[0].mechanism, Some(Mechanism::panic()));
+        assert_eq!(events[0].tags.as_ref().unwrap()["http.path"], "/panic");
+    }
+
+    #[test]
+    fn test_capture_status_selects_the_statuses_captured() {
+        let client = PulseKit::new(Config::default());
+        let mut app = PulseKitLayer::new(&client)
+            .capture_status(|status| status == StatusCode::NOT_FOUND)
+            .layer(App);
+
+        get(&mut app, "/fail");
+        get(&mut app, "/missing");
+
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].tags.as_ref().unwrap()["http.status"], "404");
+    }
+}
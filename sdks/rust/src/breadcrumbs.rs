@@ -0,0 +1,123 @@
+//! A rolling log of recent actions, sent along with errors and messages.
+
+use crate::{Event, Level, PulseKit};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Metadata key the breadcrumb list is sent under.
+const BREADCRUMBS_KEY: &str = "breadcrumbs";
+
+/// Something that happened before an event, e.g. a request or a click.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Breadcrumb {
+    /// What kind of action this was, e.g. `"http"` or `"ui.click"`
+    pub category: String,
+    pub message: String,
+    pub level: Level,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<HashMap<String, serde_json::Value>>,
+    /// RFC 3339 time of the action; set by `add_breadcrumb` if empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+}
+
+/// The most recent `max_breadcrumbs` breadcrumbs, oldest first.
+pub(crate) struct Breadcrumbs {
+    capacity: usize,
+    trail: Mutex<VecDeque<Breadcrumb>>,
+}
+
+impl Breadcrumbs {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            trail: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn push(&self, breadcrumb: Breadcrumb) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut trail = self.trail.lock();
+        if trail.len() >= self.capacity {
+            trail.pop_front();
+        }
+        trail.push_back(breadcrumb);
+    }
+
+    /// Add the trail to `error` and `message` events that don't already
+    /// carry one.
+    pub(crate) fn attach(&self, event: &mut Event) {
+        if event.event_type != "error" && event.event_type != "message" {
+            return;
+        }
+        let trail = self.trail.lock();
+        if trail.is_empty() {
+            return;
+        }
+        event
+            .metadata
+            .get_or_insert_with(HashMap::new)
+            .entry(BREADCRUMBS_KEY.to_string())
+            .or_insert_with(|| serde_json::to_value(&*trail).unwrap_or_default());
+    }
+}
+
+impl PulseKit {
+    /// Record a breadcrumb. The last `max_breadcrumbs` are attached to every
+    /// `error` and `message` event captured afterwards, oldest first.
+    pub fn add_breadcrumb(&self, mut breadcrumb: Breadcrumb) {
+        if breadcrumb.timestamp.is_none() {
+            breadcrumb.timestamp = Some(self.delivery.clock.now().to_rfc3339());
+        }
+        self.delivery.breadcrumbs.push(breadcrumb);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_only_the_most_recent_breadcrumbs_are_attached() {
+        let client = PulseKit::new(Config {
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..Default::default()
+        });
+        for i in 0..150 {
+            client.add_breadcrumb(Breadcrumb {
+                category: "http".to_string(),
+                message: format!("GET /items/{}", i),
+                ..Default::default()
+            });
+        }
+        client.capture_error("checkout failed");
+        client.capture(Event {
+            event_type: "performance".to_string(),
+            ..Default::default()
+        });
+
+        let events = client.delivery.take_events();
+        let error = events.iter().find(|e| e.event_type == "error").unwrap();
+        let trail = error.metadata.as_ref().unwrap()[BREADCRUMBS_KEY]
+            .as_array()
+            .unwrap();
+        let messages: Vec<_> = trail
+            .iter()
+            .map(|breadcrumb| breadcrumb["message"].as_str().unwrap())
+            .collect();
+        let expected: Vec<_> = (50..150).map(|i| format!("GET /items/{}", i)).collect();
+        assert_eq!(messages, expected);
+        assert!(trail[0]["timestamp"].is_string());
+
+        let other = events
+            .iter()
+            .find(|e| e.event_type == "performance")
+            .unwrap();
+        assert!(other.metadata.is_none());
+    }
+}
@@ -0,0 +1,92 @@
+//! Global ceiling on the number of events sent per time window.
+
+use crate::clock::Clock;
+use crate::time::{Duration, Instant};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Sliding-window limiter: at most `limit` events in any `window`.
+pub(crate) struct EventBudget {
+    limit: u32,
+    window: Duration,
+    accepted: Mutex<VecDeque<Instant>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl EventBudget {
+    pub(crate) fn new(limit: u32, window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            limit,
+            window,
+            accepted: Mutex::new(VecDeque::new()),
+            clock,
+        }
+    }
+
+    /// Take one event from the budget, returning `false` if it is spent.
+    pub(crate) fn try_acquire(&self) -> bool {
+        let now = self.clock.instant();
+        let mut accepted = self.accepted.lock();
+        self.expire(&mut accepted, now);
+        if accepted.len() >= self.limit as usize {
+            return false;
+        }
+        accepted.push_back(now);
+        true
+    }
+
+    /// Events that may still be sent in the current window.
+    pub(crate) fn remaining(&self) -> u32 {
+        let mut accepted = self.accepted.lock();
+        self.expire(&mut accepted, self.clock.instant());
+        self.limit.saturating_sub(accepted.len() as u32)
+    }
+
+    fn expire(&self, accepted: &mut VecDeque<Instant>, now: Instant) {
+        while accepted
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= self.window)
+        {
+            accepted.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level, PulseKit, TestClock};
+
+    #[test]
+    fn test_budget_refills_as_the_window_slides() {
+        let clock = TestClock::new();
+        let budget = EventBudget::new(2, Duration::from_millis(50), Arc::new(clock.clone()));
+        assert!(budget.try_acquire());
+        assert!(budget.try_acquire());
+        assert!(!budget.try_acquire());
+        assert_eq!(budget.remaining(), 0);
+
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(budget.remaining(), 2);
+        assert!(budget.try_acquire());
+    }
+
+    #[test]
+    fn test_events_over_the_ceiling_are_dropped() {
+        let client = PulseKit::new(Config {
+            max_events_per_window: Some((3, Duration::from_secs(60))),
+            batch_size: 100,
+            ..Default::default()
+        });
+
+        for i in 0..5 {
+            client.capture_message(&format!("event {}", i), Level::Info);
+        }
+
+        assert_eq!(client.delivery.take_events().len(), 3);
+        let stats = client.stats();
+        assert_eq!(stats.dropped_over_budget, 2);
+        assert_eq!(stats.remaining_budget, Some(0));
+    }
+}
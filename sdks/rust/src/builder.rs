@@ -0,0 +1,245 @@
+//! Chainable construction of a validated `Config`.
+
+use crate::transport::check_headers;
+use crate::{Config, PulseKit, PulseKitError, RetryConfig};
+use std::time::Duration;
+
+/// Builds a [`Config`], checking the required settings in
+/// [`build`](ConfigBuilder::build). Settings without a setter can be
+/// changed on the built `Config`.
+///
+/// ```no_run
+/// let client = pulsekit::Config::builder()
+///     .endpoint("https://your-pulsekit-instance.com")
+///     .api_key("pk_your_api_key")
+///     .environment("production")
+///     .build_client()
+///     .expect("invalid PulseKit configuration");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.config.endpoint = endpoint.into();
+        self
+    }
+
+    /// Add an endpoint to `failover_endpoints`.
+    pub fn failover_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.config.failover_endpoints.push(endpoint.into());
+        self
+    }
+
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.config.api_key = api_key.into();
+        self
+    }
+
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.config.environment = Some(environment.into());
+        self
+    }
+
+    pub fn release(mut self, release: impl Into<String>) -> Self {
+        self.config.release = Some(release.into());
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.config.batch_size = batch_size;
+        self
+    }
+
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.config.debug = debug;
+        self
+    }
+
+    /// Add a tag to `default_tags`.
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.default_tags.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.config.flush_interval = Some(flush_interval);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    /// Add a header to `headers`.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.headers.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.config.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Send async requests through `client`; see [`Config::http_client`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.config.http_client = Some(client);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryConfig) -> Self {
+        self.config.retry = retry;
+        self
+    }
+
+    /// The config with its endpoints normalized, or
+    /// [`PulseKitError::InvalidRequest`] if an endpoint is not an absolute
+    /// `http` or `https` URL, the API key is empty, a header is invalid or
+    /// the proxy is not a valid proxy URL.
+    pub fn build(mut self) -> Result<Config, PulseKitError> {
+        self.config.normalize_endpoints()?;
+        if self.config.api_key.trim().is_empty() {
+            return Err(PulseKitError::InvalidRequest(
+                "api_key must not be empty".to_string(),
+            ));
+        }
+        check_headers(&self.config.headers)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(proxy) = &self.config.proxy {
+            reqwest::Proxy::all(proxy).map_err(|e| {
+                PulseKitError::InvalidRequest(format!("proxy {:?} is invalid: {}", proxy, e))
+            })?;
+        }
+        Ok(self.config)
+    }
+
+    /// Build the config and create a client with the HTTP transport.
+    pub fn build_client(self) -> Result<PulseKit, PulseKitError> {
+        self.build().and_then(PulseKit::try_new)
+    }
+}
+
+/// `endpoint` without trailing slashes, so paths can be appended to it, or
+/// an error if it is not an absolute `http` or `https` URL.
+fn normalize_endpoint(endpoint: &str) -> Result<String, PulseKitError> {
+    let endpoint = endpoint.trim();
+    let url = reqwest::Url::parse(endpoint).map_err(|e| {
+        PulseKitError::InvalidRequest(format!("endpoint {:?} is not a valid URL: {}", endpoint, e))
+    })?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(PulseKitError::InvalidRequest(format!(
+            "endpoint {:?} must use http or https",
+            endpoint
+        )));
+    }
+    Ok(endpoint.trim_end_matches('/').to_string())
+}
+
+impl Config {
+    /// Start building a config with validated required settings.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    /// Validate `endpoint`, `error_endpoint`, the route endpoints and
+    /// `failover_endpoints`, stripping their trailing slashes.
+    pub(crate) fn normalize_endpoints(&mut self) -> Result<(), PulseKitError> {
+        self.endpoint = normalize_endpoint(&self.endpoint)?;
+        if let Some(endpoint) = &mut self.error_endpoint {
+            *endpoint = normalize_endpoint(endpoint)?;
+        }
+        let routes = self.routes.iter_mut().map(|route| &mut route.endpoint);
+        for endpoint in routes.chain(&mut self.failover_endpoints) {
+            *endpoint = normalize_endpoint(endpoint)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_validates_endpoint_and_api_key() {
+        let config = Config::builder()
+            .endpoint("https://pulsekit.example.com")
+            .api_key("pk_test")
+            .environment("staging")
+            .batch_size(25)
+            .tag("region", "eu-west-1")
+            .build()
+            .unwrap();
+        assert_eq!(config.endpoint, "https://pulsekit.example.com");
+        assert_eq!(config.environment.as_deref(), Some("staging"));
+        assert_eq!(config.batch_size, 25);
+        assert_eq!(config.default_tags["region"], "eu-west-1");
+
+        let invalid = [
+            Config::builder().endpoint("not a url").api_key("pk_test"),
+            Config::builder()
+                .endpoint("ftp://pulsekit.example.com")
+                .api_key("pk_test"),
+            Config::builder().endpoint("https://pulsekit.example.com"),
+            Config::builder()
+                .endpoint("https://pulsekit.example.com")
+                .api_key("pk_test")
+                .proxy("http://[::1"),
+            Config::builder()
+                .endpoint("https://pulsekit.example.com")
+                .api_key("pk_test")
+                .header("X-Tenant Id", "acme"),
+        ];
+        for builder in invalid {
+            assert!(matches!(
+                builder.build(),
+                Err(PulseKitError::InvalidRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_endpoints_are_normalized_at_construction() {
+        let config = Config::builder()
+            .endpoint("https://pulsekit.example.com/ingest/")
+            .failover_endpoint("https://backup.example.com//")
+            .api_key("pk_test")
+            .build()
+            .unwrap();
+        assert_eq!(config.endpoint, "https://pulsekit.example.com/ingest");
+        assert_eq!(config.failover_endpoints, ["https://backup.example.com"]);
+
+        let client = PulseKit::try_new(Config {
+            endpoint: "http://localhost:8080".to_string(),
+            error_endpoint: Some("http://localhost:8081/".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(client.config.endpoint, "http://localhost:8080");
+        assert_eq!(
+            client.config.error_endpoint.as_deref(),
+            Some("http://localhost:8081")
+        );
+
+        for endpoint in ["pulsekit.example.com", "localhost:8080", "/api"] {
+            let result = PulseKit::try_new(Config {
+                endpoint: endpoint.to_string(),
+                ..Default::default()
+            });
+            assert!(
+                matches!(result, Err(PulseKitError::InvalidRequest(_))),
+                "{} was accepted",
+                endpoint
+            );
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Named checkpoints for measuring time between points in a workflow.
+
+use crate::debug_log::debug_log;
+use crate::time::Instant;
+use crate::{Event, PulseKit};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+/// Checkpoints kept per thread; setting another evicts the oldest.
+const MAX_CHECKPOINTS: usize = 64;
+
+thread_local! {
+    static CHECKPOINTS: RefCell<VecDeque<(String, Instant)>> = const { RefCell::new(VecDeque::new()) };
+}
+
+impl PulseKit {
+    /// Record the current time under `name` for a later
+    /// [`capture_since`](PulseKit::capture_since) on the same thread.
+    /// Setting a name again restarts it.
+    pub fn checkpoint(&self, name: &str) {
+        CHECKPOINTS.with(|checkpoints| {
+            let mut checkpoints = checkpoints.borrow_mut();
+            checkpoints.retain(|(existing, _)| existing != name);
+            if checkpoints.len() >= MAX_CHECKPOINTS {
+                checkpoints.pop_front();
+            }
+            checkpoints.push_back((name.to_string(), self.delivery.clock.instant()));
+        });
+    }
+
+    /// Capture `event` with the time since checkpoint `name` as
+    /// `since_checkpoint_ms` and the name as `checkpoint` in metadata. If
+    /// this thread has no such checkpoint the event is captured unchanged.
+    pub fn capture_since(&self, name: &str, mut event: Event) {
+        let started = CHECKPOINTS.with(|checkpoints| {
+            checkpoints
+                .borrow()
+                .iter()
+                .find(|(existing, _)| existing == name)
+                .map(|(_, started)| *started)
+        });
+        match started {
+            Some(started) => {
+                let metadata = event.metadata.get_or_insert_with(HashMap::new);
+                metadata.insert("checkpoint".to_string(), name.into());
+                metadata.insert(
+                    "since_checkpoint_ms".to_string(),
+                    serde_json::json!(self.elapsed_since(started).as_secs_f64() * 1000.0),
+                );
+            }
+            None => {
+                debug_log!(
+                    self.config,
+                    Warn,
+                    "No checkpoint named {:?} on this thread",
+                    name
+                );
+            }
+        }
+        self.capture(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::time::Duration;
+
+    #[test]
+    fn test_elapsed_time_since_checkpoint_is_attached() {
+        let client = PulseKit::new(Config {
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..Default::default()
+        });
+        for i in 0..=MAX_CHECKPOINTS {
+            client.checkpoint(&format!("step-{}", i));
+        }
+        client.checkpoint("cart_loaded");
+        std::thread::sleep(Duration::from_millis(20));
+        client.capture_since(
+            "cart_loaded",
+            Event {
+                event_type: "checkout".to_string(),
+                ..Default::default()
+            },
+        );
+        client.capture_since("step-0", Event::default());
+
+        let events = client.delivery.take_events();
+        let metadata = events[0].metadata.as_ref().unwrap();
+        assert_eq!(metadata["checkpoint"], "cart_loaded");
+        assert!(metadata["since_checkpoint_ms"].as_f64().unwrap() >= 20.0);
+        assert!(events[1].metadata.is_none());
+    }
+}
@@ -0,0 +1,112 @@
+//! Client reports: a summary of the events dropped since the last flush,
+//! sent as an event when `Config::client_reports` is on.
+
+use crate::stats::{Counters, Stats};
+use crate::{Config, Event, Level};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// `event_type` of client report events.
+pub const CLIENT_REPORT_EVENT_TYPE: &str = "pulsekit.client_report";
+
+/// The drop counts covered by reports already queued.
+#[derive(Default)]
+pub(crate) struct ClientReports {
+    reported: Mutex<Stats>,
+}
+
+impl ClientReports {
+    /// A report of the events dropped since the last one, or `None` if
+    /// there were none. The counts it reports won't be reported again.
+    pub(crate) fn take(
+        &self,
+        counters: &Counters,
+        config: &Config,
+        timestamp: Option<DateTime<Utc>>,
+    ) -> Option<Event> {
+        let current = counters.snapshot();
+        let previous = std::mem::replace(&mut *self.reported.lock(), current.clone());
+        let discarded: Map<String, Value> = drop_counts(&current)
+            .into_iter()
+            .zip(drop_counts(&previous))
+            .filter(|((_, now), (_, before))| now > before)
+            .map(|((reason, now), (_, before))| (reason.to_string(), Value::from(now - before)))
+            .collect();
+        if discarded.is_empty() {
+            return None;
+        }
+        Some(Event {
+            event_type: CLIENT_REPORT_EVENT_TYPE.to_string(),
+            level: Some(Level::Info),
+            metadata: Some(HashMap::from([(
+                "discarded".to_string(),
+                Value::Object(discarded),
+            )])),
+            timestamp,
+            environment: config.environment.clone(),
+            release: config.release.clone(),
+            ..Default::default()
+        })
+    }
+}
+
+/// The drop counters of `stats`, by the reason reported for them.
+fn drop_counts(stats: &Stats) -> [(&'static str, u64); 9] {
+    [
+        ("below_min_level", stats.dropped_below_min_level),
+        ("before_send", stats.dropped_by_before_send),
+        ("startup_grace", stats.suppressed_during_startup),
+        ("over_budget", stats.dropped_over_budget),
+        ("duplicate", stats.dropped_duplicates),
+        ("queue_full", stats.dropped_queue_full),
+        ("sampled", stats.dropped_sampled),
+        ("deadline_exceeded", stats.dropped_over_deadline),
+        ("oversized", stats.dropped_oversized),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryTransport, PulseKit};
+
+    #[test]
+    fn test_flush_reports_drops_since_the_last_report() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                client_reports: true,
+                min_level: Level::Warning,
+                batch_size: 100,
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+        for _ in 0..3 {
+            client.capture_message("chatty", Level::Debug);
+        }
+        client.capture_message("kept", Level::Error);
+        client.flush_blocking();
+
+        let events = transport.take_events();
+        let reports: Vec<_> = events
+            .iter()
+            .filter(|e| e.event_type == CLIENT_REPORT_EVENT_TYPE)
+            .collect();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(
+            reports[0].metadata.as_ref().unwrap()["discarded"],
+            serde_json::json!({"below_min_level": 3})
+        );
+        assert_eq!(events.len(), 2);
+
+        client.capture_message("kept", Level::Error);
+        client.flush_blocking();
+        let events = transport.take_events();
+        assert!(events
+            .iter()
+            .all(|e| e.event_type != CLIENT_REPORT_EVENT_TYPE));
+    }
+}
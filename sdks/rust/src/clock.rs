@@ -0,0 +1,128 @@
+//! The source of the current time, replaceable to control time in tests.
+
+use crate::time::{Duration, Instant};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Where the client reads the current time: event and breadcrumb
+/// timestamps, dedupe, budget and periodic windows, rate-limit pauses and
+/// measured durations. Waiting for a flush or a retry always uses the
+/// system clock.
+///
+/// Pass one to [`PulseKit::with_clock`](crate::PulseKit::with_clock);
+/// [`SystemClock`] is used otherwise.
+pub trait Clock: Send + Sync {
+    /// The current wall-clock time.
+    fn now(&self) -> DateTime<Utc>;
+
+    /// The current monotonic time, which windows and durations are
+    /// measured with.
+    fn instant(&self) -> Instant;
+}
+
+/// The system's wall clock and monotonic clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that stands still until [`advance`](TestClock::advance)d.
+/// Clones share the same time, so keep one handle to move the time of the
+/// client it was given to.
+///
+/// ```
+/// use pulsekit::{Config, PulseKit, TestClock};
+/// use std::time::Duration;
+///
+/// let clock = TestClock::new();
+/// let client = PulseKit::with_clock(Config::default(), clock.clone());
+/// clock.advance(Duration::from_secs(60));
+/// ```
+#[derive(Debug, Clone)]
+pub struct TestClock {
+    start_wall: DateTime<Utc>,
+    start_instant: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl TestClock {
+    /// A clock stopped at the current system time.
+    pub fn new() -> Self {
+        Self::starting_at(Utc::now())
+    }
+
+    /// A clock stopped at `wall`.
+    pub fn starting_at(wall: DateTime<Utc>) -> Self {
+        Self {
+            start_wall: wall,
+            start_instant: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Move the time forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock() += by;
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> DateTime<Utc> {
+        let elapsed = *self.elapsed.lock();
+        self.start_wall
+            + ChronoDuration::from_std(elapsed).unwrap_or_else(|_| ChronoDuration::zero())
+    }
+
+    fn instant(&self) -> Instant {
+        self.start_instant + *self.elapsed.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level, PulseKit};
+
+    #[test]
+    fn test_clock_drives_timestamps_and_windows() {
+        let start = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let clock = TestClock::starting_at(start);
+        let client = PulseKit::with_clock(
+            Config {
+                dedupe_window: Some(Duration::from_secs(60)),
+                ..Default::default()
+            },
+            clock.clone(),
+        );
+
+        client.capture_message("disk full", Level::Error);
+        client.capture_message("disk full", Level::Error);
+        clock.advance(Duration::from_secs(61));
+        client.capture_message("disk full", Level::Error);
+
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].timestamp, Some(start));
+        assert_eq!(
+            events[1].timestamp,
+            Some(start + ChronoDuration::seconds(61))
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Bounded buffer of recently dropped events, kept for diagnostics.
+
+use crate::Event;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+
+/// Why `capture` dropped an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// The innermost application frame is in one of `ignore_modules`
+    IgnoredModule,
+    /// The event was below `min_level`
+    BelowMinLevel,
+    /// The event was below `startup_grace_level` during `startup_grace`
+    StartupGrace,
+    /// `max_events_per_window` was reached
+    OverBudget,
+    /// An event with the same `dedupe_key` was sent within `dedupe_window`
+    Duplicate,
+    /// The queue held `max_queue_size` events
+    QueueFull,
+    /// The event lost the `sample_rate` roll
+    Sampled,
+    /// Preparing the event took longer than `capture_deadline`
+    DeadlineExceeded,
+    /// The event exceeded `max_request_bytes` under
+    /// `OversizedEventPolicy::Drop` or `Truncate`
+    Oversized,
+    /// Neither the transport nor `fallback_transport` delivered the event,
+    /// and the queue had no room to try again
+    SendFailed,
+}
+
+/// Keeps the most recent `capacity` dropped events, discarding the oldest.
+pub(crate) struct DeadLetters {
+    capacity: usize,
+    events: Mutex<VecDeque<(DropReason, Event)>>,
+}
+
+impl DeadLetters {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub(crate) fn push(&self, reason: DropReason, event: Event) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut events = self.events.lock();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back((reason, event));
+    }
+
+    pub(crate) fn drain(&self) -> Vec<(DropReason, Event)> {
+        self.events.lock().drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level, PulseKit};
+    use std::time::Duration;
+
+    #[test]
+    fn test_dropped_events_are_kept_with_reasons() {
+        let client = PulseKit::new(Config {
+            dead_letter_capacity: 2,
+            startup_grace: Some(Duration::from_secs(60)),
+            max_events_per_window: Some((1, Duration::from_secs(60))),
+            ..Default::default()
+        });
+
+        client.capture_message("booting", Level::Info);
+        client.capture_message("warming caches", Level::Warning);
+        client.capture_error("first error");
+        client.capture_error("second error");
+
+        let dropped: Vec<_> = client
+            .drained_dead_letters()
+            .into_iter()
+            .map(|(reason, event)| (reason, event.message.unwrap()))
+            .collect();
+        assert_eq!(
+            dropped,
+            [
+                (DropReason::StartupGrace, "warming caches".to_string()),
+                (DropReason::OverBudget, "second error".to_string()),
+            ]
+        );
+        assert!(client.drained_dead_letters().is_empty());
+    }
+}
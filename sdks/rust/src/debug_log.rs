@@ -0,0 +1,90 @@
+//! Where the SDK's own diagnostics go when `debug` is on.
+
+use crate::Config;
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DebugLevel {
+    /// Routine progress, such as a batch being sent
+    Debug,
+    /// Something was dropped or ignored
+    Warn,
+    /// Events could not be stored or delivered
+    Error,
+}
+
+/// Emit a diagnostic through [`emit`] if `debug` is on in `$config`. The
+/// message is not formatted otherwise.
+macro_rules! debug_log {
+    ($config:expr, $level:ident, $($arg:tt)+) => {
+        if $config.debug {
+            $crate::debug_log::emit(
+                &$config,
+                $crate::debug_log::DebugLevel::$level,
+                &format!($($arg)+),
+            );
+        }
+    };
+}
+pub(crate) use debug_log;
+
+/// Send `message` to `debug_sink` if one is set. Otherwise it goes to the
+/// `log` crate with the `log` feature, or to stderr.
+pub(crate) fn emit(config: &Config, level: DebugLevel, message: &str) {
+    if let Some(sink) = &config.debug_sink {
+        sink(message);
+        return;
+    }
+    #[cfg(feature = "log")]
+    match level {
+        DebugLevel::Debug => ::log::debug!("{}", message),
+        DebugLevel::Warn => ::log::warn!("{}", message),
+        DebugLevel::Error => ::log::error!("{}", message),
+    }
+    #[cfg(not(feature = "log"))]
+    match level {
+        DebugLevel::Warn => eprintln!("[PulseKit] Warning: {}", message),
+        DebugLevel::Debug | DebugLevel::Error => eprintln!("[PulseKit] {}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Level, PulseKit};
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    fn recording(debug: bool) -> (Config, Arc<Mutex<Vec<String>>>) {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let config = Config {
+            endpoint: "https://pulsekit.invalid".to_string(),
+            debug,
+            debug_sink: Some(Arc::new({
+                let messages = messages.clone();
+                move |message: &str| messages.lock().push(message.to_string())
+            })),
+            ..Default::default()
+        };
+        (config, messages)
+    }
+
+    #[test]
+    fn test_diagnostics_go_to_the_debug_sink() {
+        let (config, messages) = recording(true);
+        let client = PulseKit::new(config);
+        client.capture_message("disk full", Level::Warning);
+
+        assert_eq!(*messages.lock(), ["Event queued, queue size: 1"]);
+    }
+
+    #[test]
+    fn test_nothing_is_emitted_without_debug() {
+        let (config, messages) = recording(false);
+        let client = PulseKit::new(config);
+        client.capture_message("disk full", Level::Warning);
+        debug_log!(client.config, Error, "never formatted {}", 1);
+
+        assert!(messages.lock().is_empty());
+    }
+}
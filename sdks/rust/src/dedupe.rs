@@ -0,0 +1,282 @@
+//! Collapsing of repeated events within a time window.
+
+use crate::clock::Clock;
+use crate::stacktrace::trace_hash;
+use crate::time::{Duration, Instant};
+use crate::Event;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Metadata key holding how many duplicates of an event were dropped.
+const DUPLICATE_COUNT_KEY: &str = "duplicate_count";
+
+/// Most keys remembered at once; past it the key let through longest ago
+/// is forgotten.
+const MAX_DEDUPE_KEYS: usize = 1024;
+
+/// What two events must share to count as duplicates under `dedupe_window`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DedupeKey {
+    /// The event's fingerprint, or its message if it has none
+    #[default]
+    Fingerprint,
+    /// The event's message
+    Message,
+    /// The value of the named tag
+    Tag(String),
+    /// The value of the named metadata key
+    Metadata(String),
+    /// A hash of the stack trace's frames (function, file and line), so
+    /// the same failure is collapsed however its message is formatted
+    Stacktrace,
+}
+
+impl DedupeKey {
+    /// The value to dedupe `event` on, or `None` if the event lacks it and
+    /// should never be treated as a duplicate.
+    fn value(&self, event: &Event) -> Option<String> {
+        match self {
+            DedupeKey::Fingerprint => event.fingerprint.clone().or_else(|| event.message.clone()),
+            DedupeKey::Message => event.message.clone(),
+            DedupeKey::Tag(name) => event.tags.as_ref()?.get(name).cloned(),
+            DedupeKey::Metadata(name) => event.metadata.as_ref()?.get(name).map(|value| {
+                value
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| value.to_string())
+            }),
+            DedupeKey::Stacktrace => event
+                .stacktrace
+                .as_deref()
+                .filter(|frames| !frames.is_empty())
+                .map(trace_hash),
+        }
+    }
+}
+
+/// When a key was last let through and how many of its duplicates were
+/// dropped since their count was last sent.
+struct Seen {
+    at: Instant,
+    dropped: u64,
+}
+
+/// Remembers when each key was last let through.
+pub(crate) struct Deduper {
+    key: DedupeKey,
+    window: Duration,
+    seen: Mutex<HashMap<String, Seen>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Deduper {
+    pub(crate) fn new(key: DedupeKey, window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            key,
+            window,
+            seen: Mutex::new(HashMap::new()),
+            clock,
+        }
+    }
+
+    /// Whether an event with the same key was let through within the window.
+    /// Duplicates are counted; events that aren't duplicates start a new
+    /// window for their key.
+    pub(crate) fn is_duplicate(&self, event: &Event) -> bool {
+        let Some(value) = self.key.value(event) else {
+            return false;
+        };
+        let now = self.clock.instant();
+        let mut seen = self.seen.lock();
+        if let Some(entry) = seen.get_mut(&value) {
+            if now.duration_since(entry.at) < self.window {
+                entry.dropped += 1;
+                return true;
+            }
+            entry.at = now;
+            return false;
+        }
+        if seen.len() >= MAX_DEDUPE_KEYS {
+            let oldest = seen
+                .iter()
+                .min_by_key(|(_, entry)| entry.at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                seen.remove(&oldest);
+            }
+        }
+        seen.insert(
+            value,
+            Seen {
+                at: now,
+                dropped: 0,
+            },
+        );
+        false
+    }
+
+    /// Add the duplicates dropped since the last count was sent to the
+    /// event's `duplicate_count` metadata, when there are any.
+    pub(crate) fn attach_duplicate_count(&self, event: &mut Event) {
+        let Some(value) = self.key.value(event) else {
+            return;
+        };
+        let dropped = match self.seen.lock().get_mut(&value) {
+            Some(entry) => std::mem::take(&mut entry.dropped),
+            None => return,
+        };
+        if dropped == 0 {
+            return;
+        }
+        let metadata = event.metadata.get_or_insert_with(HashMap::new);
+        let count = metadata
+            .get(DUPLICATE_COUNT_KEY)
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(0);
+        metadata.insert(DUPLICATE_COUNT_KEY.to_string(), (count + dropped).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level, PulseKit, StackFrame, SystemClock, TestClock};
+    use std::collections::HashMap;
+
+    fn order_event(order_id: &str, message: &str) -> Event {
+        Event {
+            event_type: "checkout.failed".to_string(),
+            level: Some(Level::Error),
+            message: Some(message.to_string()),
+            tags: Some(HashMap::from([(
+                "order_id".to_string(),
+                order_id.to_string(),
+            )])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_dedupe_on_a_custom_tag() {
+        let client = PulseKit::new(Config {
+            dedupe_key: DedupeKey::Tag("order_id".to_string()),
+            dedupe_window: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+
+        client.capture(order_event("A-1", "card declined"));
+        client.capture(order_event("A-1", "retry failed"));
+        client.capture(order_event("B-2", "card declined"));
+        client.capture(order_event("B-2", "retry failed"));
+        client.capture(order_event("A-1", "gave up"));
+        client.capture_message("no order tag", Level::Info);
+
+        let sent: Vec<_> = client
+            .delivery
+            .take_events()
+            .into_iter()
+            .map(|event| event.message.unwrap())
+            .collect();
+        assert_eq!(sent, ["card declined", "card declined", "no order tag"]);
+        assert_eq!(client.stats().dropped_duplicates, 3);
+    }
+
+    #[test]
+    fn test_key_is_let_through_again_after_the_window() {
+        let clock = TestClock::new();
+        let deduper = Deduper::new(
+            DedupeKey::Message,
+            Duration::from_millis(30),
+            Arc::new(clock.clone()),
+        );
+        let event = order_event("A-1", "card declined");
+
+        assert!(!deduper.is_duplicate(&event));
+        assert!(deduper.is_duplicate(&event));
+        clock.advance(Duration::from_millis(30));
+        assert!(!deduper.is_duplicate(&event));
+    }
+
+    #[test]
+    fn test_dedupe_on_the_stack_trace() {
+        let client = PulseKit::new(Config {
+            dedupe_key: DedupeKey::Stacktrace,
+            dedupe_window: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        let frame = |line| StackFrame {
+            file: Some("src/parse.rs".to_string()),
+            line: Some(line),
+            function: Some("app::parse::amount".to_string()),
+        };
+        let panic_at = |line, message: &str| Event {
+            stacktrace: Some(vec![frame(line)]),
+            ..order_event("A-1", message)
+        };
+
+        client.capture(panic_at(17, "invalid digit in \"12x\""));
+        client.capture(panic_at(17, "invalid digit in \"4o0\""));
+        client.capture(panic_at(23, "invalid digit in \"12x\""));
+        client.capture(order_event("A-1", "no stack trace"));
+
+        let mut sent: Vec<_> = client
+            .delivery
+            .take_events()
+            .into_iter()
+            .map(|event| event.message.unwrap())
+            .collect();
+        sent.sort();
+        assert_eq!(
+            sent,
+            [
+                "invalid digit in \"12x\"",
+                "invalid digit in \"12x\"",
+                "no stack trace"
+            ]
+        );
+        assert_eq!(client.stats().dropped_duplicates, 1);
+    }
+
+    #[test]
+    fn test_dropped_duplicates_are_counted_on_the_sent_event() {
+        let client = PulseKit::new(Config {
+            dedupe_window: Some(Duration::from_secs(60)),
+            batch_size: 10_000,
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..Default::default()
+        });
+
+        for _ in 0..1001 {
+            client.capture_message("database unreachable", Level::Error);
+        }
+        client.capture_message("cache miss", Level::Warning);
+
+        let mut events = client.delivery.take_events();
+        events.sort_by_key(|event| event.message.clone());
+        assert_eq!(events.len(), 2);
+        assert!(events[0].metadata.is_none());
+        assert_eq!(
+            events[1].metadata.as_ref().unwrap()[DUPLICATE_COUNT_KEY],
+            1000
+        );
+        assert_eq!(client.stats().dropped_duplicates, 1000);
+
+        client.capture_message("database unreachable", Level::Error);
+        assert!(client.delivery.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_remembered_keys_are_bounded() {
+        let deduper = Deduper::new(
+            DedupeKey::Message,
+            Duration::from_secs(60),
+            Arc::new(SystemClock),
+        );
+        for i in 0..MAX_DEDUPE_KEYS + 10 {
+            assert!(!deduper.is_duplicate(&order_event("A-1", &i.to_string())));
+        }
+        assert_eq!(deduper.seen.lock().len(), MAX_DEDUPE_KEYS);
+    }
+}
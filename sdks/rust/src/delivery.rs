@@ -0,0 +1,3896 @@
+//! Event queueing and network delivery shared by client handles.
+
+use crate::attachment;
+use crate::breadcrumbs::Breadcrumbs;
+use crate::budget::EventBudget;
+use crate::client_report::ClientReports;
+use crate::clock::Clock;
+use crate::dead_letter::{DeadLetters, DropReason};
+use crate::debug_log::debug_log;
+use crate::dedupe::Deduper;
+use crate::offline::OfflineStore;
+use crate::open_event::OpenEvents;
+use crate::periodic::PeriodicKeys;
+use crate::queue::Queue;
+use crate::rate_limit::RateLimit;
+use crate::sample::Sampler;
+use crate::scope::Scope;
+use crate::scrub::Scrubber;
+use crate::session::{SessionAggregate, SessionStatus, Sessions};
+use crate::stacktrace::ResolutionSlots;
+use crate::stats::Counters;
+use crate::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "async")]
+use crate::timer;
+use crate::timestamp::Timestamps;
+use crate::transport::{Transport, TransportRequest, TransportResponse};
+use crate::{process, stacktrace, Config, Event, Level, Priority, PulseKitError};
+#[cfg(feature = "async")]
+use futures_util::{stream, StreamExt};
+use parking_lot::{Mutex, ReentrantMutex};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc;
+use std::sync::{Arc, Weak};
+use std::thread;
+
+pub(crate) const API_KEY: &str = "X-PulseKit-Key";
+const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+const EVENT_COUNT: &str = "X-PulseKit-Event-Count";
+const ATTEMPT: &str = "X-PulseKit-Attempt";
+const FLUSH_REASON: &str = "X-PulseKit-Flush-Reason";
+
+/// How many events a flush delivered.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FlushOutcome {
+    /// Events in batches the server accepted
+    pub sent: usize,
+    /// Events in batches that failed on every attempt
+    pub failed: usize,
+    /// Events put back in the queue for a later flush
+    pub requeued: usize,
+    /// The first error a failed batch ran into
+    pub first_error: Option<PulseKitError>,
+    /// What the server reported for each accepted batch, in send order
+    pub responses: Vec<IngestResponse>,
+}
+
+/// What [`PulseKit::try_flush`](crate::PulseKit::try_flush) did.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TryFlush {
+    /// Whole batches were sent, with this outcome
+    Flushed(FlushOutcome),
+    /// Nothing was sent because only this many events were queued
+    Pending(usize),
+}
+
+impl FlushOutcome {
+    /// Whether every batch was delivered.
+    pub fn is_success(&self) -> bool {
+        self.failed == 0 && self.requeued == 0
+    }
+
+    /// The IDs the server assigned to delivered events.
+    pub fn event_ids(&self) -> impl Iterator<Item = &str> {
+        self.responses
+            .iter()
+            .flat_map(|response| response.ids.iter().map(String::as_str))
+    }
+
+    /// `Ok` if every batch was delivered, otherwise the first error.
+    pub fn into_result(self) -> Result<(), PulseKitError> {
+        match self.first_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn record(&mut self, count: usize, result: BatchResult) {
+        match result {
+            BatchResult::Sent(response) => {
+                self.sent += count;
+                self.responses.push(response);
+            }
+            BatchResult::Failed(err) => {
+                self.failed += count;
+                self.first_error.get_or_insert(err);
+            }
+            BatchResult::Requeued(err) => {
+                self.requeued += count;
+                self.first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    fn merge(&mut self, other: FlushOutcome) {
+        self.sent += other.sent;
+        self.failed += other.failed;
+        self.requeued += other.requeued;
+        if self.first_error.is_none() {
+            self.first_error = other.first_error;
+        }
+        self.responses.extend(other.responses);
+    }
+}
+
+/// Why [`crate::PulseKit::flush_with_timeout`] returned early.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlushError {
+    /// The timeout passed. `outcome` covers the batches settled before
+    /// then; the rest, including a batch cut off mid-send, are back in the
+    /// queue for a later flush.
+    Timeout { outcome: FlushOutcome },
+}
+
+impl fmt::Display for FlushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlushError::Timeout { outcome } => {
+                write!(f, "flush timed out after sending {} event(s)", outcome.sent)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FlushError {}
+
+/// The body of a `2xx` response to a batch.
+///
+/// The single-event endpoint's `{"id": ...}` is read as one ID. Responses
+/// from transports without a body are empty.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct IngestResponse {
+    /// IDs the server assigned to the events it stored, in request order
+    #[serde(default)]
+    pub ids: Vec<String>,
+    /// Events in the request that the server refused
+    #[serde(default)]
+    pub rejected: Vec<RejectedEvent>,
+}
+
+/// An event the server refused to store.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RejectedEvent {
+    /// Position of the event in its request
+    pub index: usize,
+    #[serde(default)]
+    pub reason: String,
+}
+
+impl IngestResponse {
+    /// Parse a response body; an empty body is an empty response.
+    fn parse(body: &[u8]) -> Result<Self, PulseKitError> {
+        #[derive(Deserialize)]
+        struct Body {
+            id: Option<String>,
+            #[serde(flatten)]
+            response: IngestResponse,
+        }
+
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Self::default());
+        }
+        let Body { id, mut response } = serde_json::from_slice(body)
+            .map_err(|e| PulseKitError::MalformedResponse(e.to_string()))?;
+        response.ids.extend(id);
+        Ok(response)
+    }
+}
+
+/// A delivered batch's status and response, or why it wasn't delivered.
+type SendResult = Result<(u16, IngestResponse), PulseKitError>;
+
+/// What `capture` does with an event when the queue already holds
+/// `max_queue_size` events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueueOverflow {
+    /// Evict the event that has been queued longest to make room
+    #[default]
+    DropOldest,
+    /// Evict the most recently queued event to make room
+    DropNewest,
+    /// Drop the incoming event and keep the queue as it is
+    DropEvent,
+}
+
+/// How request bodies are serialized, chosen with `Config::wire_format`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WireFormat {
+    /// JSON, sent as `application/json`
+    #[default]
+    Json,
+    /// MessagePack with named fields, sent as `application/msgpack`;
+    /// around a fifth smaller than JSON for typical batches and no slower
+    /// to encode (see `benches/wire_format.rs`). Requires the `msgpack`
+    /// feature
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+}
+
+impl WireFormat {
+    pub(crate) fn content_type(self) -> &'static str {
+        match self {
+            WireFormat::Json => "application/json",
+            #[cfg(feature = "msgpack")]
+            WireFormat::MsgPack => "application/msgpack",
+        }
+    }
+
+    pub(crate) fn encode(self, body: &impl Serialize) -> Result<Vec<u8>, PulseKitError> {
+        match self {
+            WireFormat::Json => Ok(serde_json::to_vec(body)?),
+            #[cfg(feature = "msgpack")]
+            WireFormat::MsgPack => rmp_serde::to_vec_named(body)
+                .map_err(|e| PulseKitError::Serialization(e.to_string())),
+        }
+    }
+}
+
+/// How soon `capture` sends an event, chosen per level with
+/// `Config::flush_modes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlushMode {
+    /// Wait for a full batch, an explicit flush or a timer
+    #[default]
+    Batch,
+    /// Flush the queue from a background thread at most this long after
+    /// the event is captured
+    Within(Duration),
+    /// Flush the queue before `capture` returns
+    Immediate,
+}
+
+/// Where a batch is sent.
+#[derive(Clone, PartialEq)]
+struct Destination {
+    endpoint: String,
+    api_key: String,
+}
+
+/// What happened to one batch.
+enum BatchResult {
+    Sent(IngestResponse),
+    Failed(PulseKitError),
+    /// Failed on every attempt and was set aside to be queued again
+    Requeued(PulseKitError),
+}
+
+/// Called once with the outcome of the batch an event was sent in.
+pub(crate) type Ack = Box<dyn FnOnce(Result<(), PulseKitError>) + Send>;
+
+/// An event waiting in the queue.
+pub(crate) struct Queued {
+    pub(crate) event: Event,
+    enqueued_at: Instant,
+    ack: Option<Ack>,
+}
+
+impl Queued {
+    /// The event's priority, raised one step for every `aging` interval it
+    /// has been waiting so low-priority events are not starved. Aging stops
+    /// at `High`, so events captured as `Critical` always go first.
+    fn effective_priority(&self, now: Instant, aging: Duration) -> Priority {
+        let base = self.event.effective_priority();
+        if aging.is_zero() {
+            return base;
+        }
+        let waited = now.saturating_duration_since(self.enqueued_at);
+        let steps = (waited.as_millis() / aging.as_millis().max(1)).min(u8::MAX as u128) as u8;
+        base.raised(steps).min(Priority::High).max(base)
+    }
+}
+
+/// Owns the event queue and the transport used to send it.
+pub(crate) struct Delivery {
+    pub(crate) config: Config,
+    pub(crate) queue: Queue,
+    pub(crate) transport: Arc<dyn Transport>,
+    pub(crate) clock: Arc<dyn Clock>,
+    last_enqueue: Mutex<Instant>,
+    pub(crate) timestamps: Timestamps,
+    pub(crate) counters: Counters,
+    pub(crate) started_at: Instant,
+    pub(crate) budget: Option<EventBudget>,
+    pub(crate) dead_letters: DeadLetters,
+    pub(crate) deduper: Option<Deduper>,
+    pub(crate) resolution_slots: Option<ResolutionSlots>,
+    pub(crate) open_events: OpenEvents,
+    pub(crate) periodic: PeriodicKeys,
+    pub(crate) scope: Mutex<Scope>,
+    pub(crate) sampler: Sampler,
+    pub(crate) breadcrumbs: Breadcrumbs,
+    pub(crate) sessions: Sessions,
+    rate_limit: RateLimit,
+    pub(crate) panic_hook_installed: AtomicBool,
+    sequence: AtomicU64,
+    pub(crate) scrubber: Scrubber,
+    client_reports: ClientReports,
+    /// The `runtime` metadata block, if `capture_runtime_context` is on
+    pub(crate) runtime_context: Option<serde_json::Value>,
+    sent_stacktraces: Mutex<HashSet<String>>,
+    /// `flush_interval` with this worker's jitter applied
+    flush_period: Option<Duration>,
+    flush_timer: Mutex<Option<FlushTimer>>,
+    /// Live `PulseKit` handles; the last one to go stops the queue worker
+    /// sending full batches
+    handles: AtomicUsize,
+    /// Set once `shutdown` on the last handle has flushed what it could,
+    /// so that drop doesn't flush again
+    handed_off: AtomicBool,
+    /// Batches given up on during the current flush, for `on_send_failure`
+    abandoned: Mutex<Vec<(Vec<Event>, PulseKitError)>>,
+    /// Set by `shutdown` or while the final flush on drop runs, for
+    /// `batch_hints`
+    shutting_down: AtomicBool,
+    /// When the earliest `FlushMode::Within` flush is due
+    flush_deadline: Mutex<Option<Instant>>,
+    flush_lock: ReentrantMutex<()>,
+    #[cfg(feature = "async")]
+    async_flush_lock: tokio::sync::Mutex<()>,
+}
+
+impl Delivery {
+    pub(crate) fn new(
+        config: Config,
+        transport: Arc<dyn Transport>,
+        clock: Arc<dyn Clock>,
+    ) -> Arc<Self> {
+        let budget = config
+            .max_events_per_window
+            .map(|(limit, window)| EventBudget::new(limit, window, clock.clone()));
+        let dead_letters = DeadLetters::new(config.dead_letter_capacity);
+        let deduper = config
+            .dedupe_window
+            .map(|window| Deduper::new(config.dedupe_key.clone(), window, clock.clone()));
+        let offline = config
+            .offline_storage
+            .clone()
+            .map(|path| OfflineStore::new(path, config.offline_storage_max_bytes));
+        let restored: Vec<Queued> = offline
+            .iter()
+            .flat_map(OfflineStore::load)
+            .take(config.max_queue_size)
+            .map(|event| Queued {
+                event,
+                enqueued_at: clock.instant(),
+                ack: None,
+            })
+            .collect();
+        let scrubber = Scrubber::new(&config);
+        let sampler = Sampler::new(config.sample_seed);
+        let breadcrumbs = Breadcrumbs::new(config.max_breadcrumbs);
+        let resolution_slots = config
+            .max_concurrent_backtrace_resolution
+            .map(ResolutionSlots::new);
+        let flush_period = config
+            .flush_interval
+            .map(|interval| jittered(interval, config.flush_interval_jitter));
+        let runtime_context = config
+            .capture_runtime_context
+            .then(process::runtime_context);
+        let delivery = Arc::new_cyclic(|delivery| Self {
+            queue: Queue::spawn(delivery.clone(), &config, offline, restored),
+            config,
+            transport,
+            clock: clock.clone(),
+            last_enqueue: Mutex::new(Instant::now()),
+            timestamps: Timestamps::new(clock.clone()),
+            counters: Counters::default(),
+            started_at: clock.instant(),
+            budget,
+            dead_letters,
+            deduper,
+            resolution_slots,
+            open_events: OpenEvents::default(),
+            periodic: PeriodicKeys::default(),
+            scope: Mutex::new(Scope::default()),
+            sampler,
+            breadcrumbs,
+            sessions: Sessions::default(),
+            rate_limit: RateLimit::new(clock.clone()),
+            panic_hook_installed: AtomicBool::new(false),
+            sequence: AtomicU64::new(0),
+            scrubber,
+            client_reports: ClientReports::default(),
+            runtime_context,
+            sent_stacktraces: Mutex::new(HashSet::new()),
+            flush_period,
+            flush_timer: Mutex::new(None),
+            handles: AtomicUsize::new(0),
+            handed_off: AtomicBool::new(false),
+            abandoned: Mutex::new(Vec::new()),
+            shutting_down: AtomicBool::new(false),
+            flush_deadline: Mutex::new(None),
+            flush_lock: ReentrantMutex::new(()),
+            #[cfg(feature = "async")]
+            async_flush_lock: tokio::sync::Mutex::new(()),
+        });
+
+        if let Some(idle) = delivery.config.sync_blocking_flush {
+            spawn_idle_flusher(Arc::downgrade(&delivery), idle);
+        }
+        if let Some(period) = delivery.flush_period {
+            let timer = spawn_interval_flusher(Arc::downgrade(&delivery), period);
+            *delivery.flush_timer.lock() = Some(timer);
+        }
+        if delivery.config.warm_connection {
+            spawn_warm_up(&delivery);
+        }
+
+        delivery
+    }
+
+    /// Tag the event's stack trace with its hash, dropping the frames if the
+    /// same trace was already queued in this session. With `record` unset
+    /// the trace isn't remembered, so a later event still carries it.
+    pub(crate) fn dedupe_stacktrace(&self, event: &mut Event, record: bool) {
+        let Some(frames) = event.stacktrace.as_deref() else {
+            return;
+        };
+        let hash = stacktrace::trace_hash(frames);
+        let mut sent = self.sent_stacktraces.lock();
+        let seen = if record {
+            !sent.insert(hash.clone())
+        } else {
+            sent.contains(&hash)
+        };
+        drop(sent);
+        if seen {
+            event.stacktrace = None;
+        }
+        event.stacktrace_ref = Some(hash);
+    }
+
+    /// Queue an event. Once the queue reaches `batch_size` it is sent by the
+    /// queue worker, so capturing never waits on the network; it is sent on
+    /// this thread with `test_sync_sends` or `FlushMode::Immediate`.
+    /// If the queue is full, an event is dropped as chosen by
+    /// `queue_overflow`.
+    /// `ack` is called with the result of the batch the event is sent in.
+    pub(crate) fn enqueue(self: &Arc<Self>, event: Event, ack: Option<Ack>) {
+        self.push(event, ack, false);
+    }
+
+    /// [`enqueue`](Delivery::enqueue), but discard the event without
+    /// counting it if the queue is full. Returns whether it was queued.
+    pub(crate) fn try_enqueue(self: &Arc<Self>, event: Event) -> bool {
+        self.push(event, None, true)
+    }
+
+    fn push(self: &Arc<Self>, mut event: Event, ack: Option<Ack>, reject_when_full: bool) -> bool {
+        let mode = self
+            .config
+            .flush_modes
+            .get(&event.level.unwrap_or_default())
+            .copied()
+            .unwrap_or_default();
+        let fits = self.queue.reserve();
+        if reject_when_full && !fits {
+            return false;
+        }
+        if self.config.attach_sequence {
+            event.sequence = Some(self.sequence.fetch_add(1, Ordering::Relaxed) + 1);
+        }
+        // Only the idle flusher reads it, so captures skip the lock otherwise.
+        if self.config.sync_blocking_flush.is_some() {
+            *self.last_enqueue.lock() = Instant::now();
+        }
+
+        let incoming = Queued {
+            event,
+            enqueued_at: self.clock.instant(),
+            ack,
+        };
+        self.queue.push(incoming, fits);
+        debug_log!(
+            self.config,
+            Debug,
+            "Event queued, queue size: {}",
+            self.queue.len()
+        );
+
+        if let Some(remaining) = self.rate_limit.remaining() {
+            self.flush_within(remaining);
+        } else if self.config.test_sync_sends || mode == FlushMode::Immediate {
+            self.flush_blocking();
+        } else if let FlushMode::Within(delay) = mode {
+            self.flush_within(delay);
+        }
+        true
+    }
+
+    pub(crate) fn add_handle(&self) {
+        self.handles.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn is_last_handle(&self) -> bool {
+        self.handles.load(Ordering::Acquire) == 1
+    }
+
+    #[cfg(feature = "async")]
+    /// Give up on the events `shutdown` could not send in time, and skip
+    /// the flush on drop, which would block the runtime. The events stay
+    /// in offline storage, if enabled, for the next start.
+    pub(crate) async fn hand_off(&self) {
+        self.handed_off.store(true, Ordering::Relaxed);
+        let abandoned = self.queue.take_async().await;
+        if abandoned.is_empty() {
+            return;
+        }
+        debug_log!(
+            self.config,
+            Warn,
+            "{} event(s) were not sent before shutdown timed out",
+            abandoned.len()
+        );
+        for queued in abandoned {
+            if let Some(ack) = queued.ack {
+                ack(Err(PulseKitError::Dropped));
+            }
+        }
+    }
+
+    /// Once the last handle is gone, stop the queue worker sending full
+    /// batches and wait for the one it is sending, so that the flush on drop
+    /// runs on the dropping thread rather than on the worker's, after the
+    /// caller has moved on.
+    ///
+    /// Inside a Tokio runtime, where that wait and the flush would block a
+    /// runtime thread, a task on the runtime does both and shuts down the
+    /// way `PulseKit::shutdown` does, leaving nothing for drop to do. So
+    /// does a task on the event loop on wasm32.
+    pub(crate) fn release_handle(self: &Arc<Self>) {
+        if self.handles.fetch_sub(1, Ordering::AcqRel) != 1 {
+            return;
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            wasm_bindgen_futures::spawn_local(self.clone().release());
+            return;
+        }
+        #[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(self.clone().release());
+            return;
+        }
+        #[cfg_attr(target_arch = "wasm32", allow(unreachable_code))]
+        self.queue.stop();
+    }
+
+    #[cfg(feature = "async")]
+    async fn release(self: Arc<Self>) {
+        self.queue.stop_async().await;
+        if !self.handed_off.load(Ordering::Relaxed) {
+            self.close().await;
+            self.hand_off().await;
+        }
+    }
+
+    /// Queue an event without sending, for captures during panic
+    /// unwinding. A full queue drops the event.
+    pub(crate) fn try_enqueue_unprepared(&self, event: Event) {
+        if !self.queue.reserve() {
+            Counters::increment(&self.counters.dropped_queue_full);
+            return;
+        }
+        let queued = Queued {
+            event,
+            enqueued_at: self.clock.instant(),
+            ack: None,
+        };
+        self.queue.push(queued, true);
+    }
+
+    /// Make sure a flush happens within `delay`. Only the thread for the
+    /// earliest pending deadline flushes.
+    fn flush_within(self: &Arc<Self>, delay: Duration) {
+        let deadline = Instant::now() + delay;
+        {
+            let mut pending = self.flush_deadline.lock();
+            if pending.is_some_and(|pending| pending <= deadline) {
+                return;
+            }
+            *pending = Some(deadline);
+        }
+
+        let delivery = Arc::downgrade(self);
+        let due = move || {
+            let delivery = delivery.upgrade()?;
+            let mut pending = delivery.flush_deadline.lock();
+            if *pending != Some(deadline) {
+                return None;
+            }
+            *pending = None;
+            drop(pending);
+            Some(delivery)
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        thread::spawn(move || {
+            thread::sleep(delay);
+            if let Some(delivery) = due() {
+                delivery.flush_blocking();
+            }
+        });
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(async move {
+            timer::sleep(delay).await;
+            if let Some(delivery) = due() {
+                delivery.flush().await;
+            }
+        });
+    }
+
+    pub(crate) fn drop_overflow(&self, evicted: Queued) {
+        debug_log!(
+            self.config,
+            Warn,
+            "Queue is full ({} events), dropped an event",
+            self.config.max_queue_size
+        );
+        Counters::increment(&self.counters.dropped_queue_full);
+        if let Some(ack) = evicted.ack {
+            ack(Err(PulseKitError::Dropped));
+        }
+        self.dead_letters.push(DropReason::QueueFull, evicted.event);
+    }
+
+    /// Drain the queue, highest effective priority first. Events of equal
+    /// priority keep their capture order.
+    fn take_queued(&self) -> Vec<Queued> {
+        let mut queued = self.queue.take();
+        self.attach_duplicate_counts(&mut queued);
+        self.by_priority(queued)
+    }
+
+    #[cfg(feature = "async")]
+    async fn take_queued_async(&self) -> Vec<Queued> {
+        let mut queued = self.queue.take_async().await;
+        self.attach_duplicate_counts(&mut queued);
+        self.by_priority(queued)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn take_events(&self) -> Vec<Event> {
+        self.take_queued().into_iter().map(|q| q.event).collect()
+    }
+
+    /// Remove only the events matching `pred`, leaving the others queued in
+    /// their original order.
+    ///
+    /// `pred` runs on this thread with the events taken out of the queue,
+    /// so it can't stall the queue worker; the rest go back ahead of
+    /// anything queued meanwhile. If `pred` panics, every event is put back
+    /// before the panic continues.
+    pub(crate) fn take_matching(&self, pred: impl Fn(&Event) -> bool) -> Vec<Queued> {
+        let taken = self.queue.take();
+        let hits = panic::catch_unwind(AssertUnwindSafe(|| {
+            taken.iter().map(|q| pred(&q.event)).collect::<Vec<_>>()
+        }));
+        let hits = match hits {
+            Ok(hits) => hits,
+            Err(panic) => {
+                self.queue.put_back(taken);
+                panic::resume_unwind(panic);
+            }
+        };
+        let mut hits = hits.into_iter();
+        let (mut matching, rest): (Vec<Queued>, Vec<Queued>) =
+            taken.into_iter().partition(|_| hits.next() == Some(true));
+        self.queue.put_back(rest);
+        self.attach_duplicate_counts(&mut matching);
+        self.by_priority(matching)
+    }
+
+    /// Record on each event how many of its duplicates `dedupe_window`
+    /// dropped, as it leaves the queue to be sent.
+    fn attach_duplicate_counts(&self, queued: &mut [Queued]) {
+        if let Some(deduper) = &self.deduper {
+            for q in queued {
+                deduper.attach_duplicate_count(&mut q.event);
+            }
+        }
+    }
+
+    fn by_priority(&self, mut queued: Vec<Queued>) -> Vec<Queued> {
+        let now = self.clock.instant();
+        let aging = self.config.priority_aging;
+        queued.sort_by_key(|q| Reverse(q.effective_priority(now, aging)));
+        queued
+    }
+
+    /// Send everything queued, including events queued while the flush is
+    /// in progress. A concurrent flush waits for this one and then usually
+    /// finds the queue empty, instead of sending a fragment of it.
+    ///
+    /// Up to `max_concurrent_sends` batches are in flight at once, so a
+    /// failed batch doesn't hold up the others.
+    #[cfg(feature = "async")]
+    pub(crate) async fn flush(&self) -> FlushOutcome {
+        let _guard = self.async_flush_lock.lock().await;
+        self.queue_client_report();
+        let mut outcome = FlushOutcome::default();
+        let mut requeued = Vec::new();
+        loop {
+            let queued = self.take_queued_async().await;
+            if queued.is_empty() {
+                break;
+            }
+            self.send_all_async(queued, None, &mut outcome, &mut requeued)
+                .await;
+        }
+        self.requeue_async(requeued).await;
+        self.flush_sessions_async().await;
+        outcome
+    }
+
+    /// [`Delivery::flush`], giving up at `deadline`. A batch whose send is
+    /// still in progress then is queued again along with the unsent ones,
+    /// so the server may already have stored it; idempotency keys let it
+    /// discard the duplicate.
+    #[cfg(feature = "async")]
+    pub(crate) async fn flush_until(&self, deadline: Instant) -> Result<FlushOutcome, FlushError> {
+        let mut outcome = FlushOutcome::default();
+        let Some(_guard) = timer::timeout_at(deadline, self.async_flush_lock.lock()).await else {
+            return Err(FlushError::Timeout { outcome });
+        };
+        self.queue_client_report();
+        let mut requeued = Vec::new();
+        loop {
+            let Some(queued) = timer::timeout_at(deadline, self.take_queued_async()).await else {
+                self.requeue_async(requeued).await;
+                return Err(FlushError::Timeout { outcome });
+            };
+            if queued.is_empty() {
+                break;
+            }
+            if !self
+                .send_all_async(queued, Some(deadline), &mut outcome, &mut requeued)
+                .await
+            {
+                self.requeue_async(requeued).await;
+                return Err(FlushError::Timeout { outcome });
+            }
+        }
+        self.requeue_async(requeued).await;
+        let sessions = self.sessions.take();
+        if !sessions.is_empty() {
+            match timer::timeout_at(deadline, self.send_sessions_async(&sessions)).await {
+                Some(result) => self.settle_sessions(sessions, result),
+                None => {
+                    self.sessions.restore(sessions);
+                    return Err(FlushError::Timeout { outcome });
+                }
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Blocking counterpart of [`Delivery::flush`]. The lock is reentrant so
+    /// a panic hook firing mid-flush can still flush its own event.
+    pub(crate) fn flush_blocking(&self) -> FlushOutcome {
+        let _guard = self.flush_lock.lock();
+        self.flush_queue()
+    }
+
+    /// Send the queue for the queue worker, once it holds a full batch. No
+    /// flush lock is taken: a flush holding it may be waiting on the worker
+    /// for the queue. While sends are paused by a rate limit the flush
+    /// scheduled for its end sends the batch instead.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn send_full_queue(&self) {
+        if self.rate_limit.remaining().is_none() {
+            self.flush_queue();
+        }
+    }
+
+    /// On wasm32, where blocking sends fail, the batch is sent on a task.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn send_full_queue(self: &Arc<Self>) {
+        if self.rate_limit.remaining().is_none() {
+            let delivery = self.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                delivery.flush().await;
+            });
+        }
+    }
+
+    fn flush_queue(&self) -> FlushOutcome {
+        self.queue_client_report();
+        let mut outcome = FlushOutcome::default();
+        let mut requeued = Vec::new();
+        loop {
+            let queued = self.take_queued();
+            if queued.is_empty() {
+                break;
+            }
+            outcome.merge(self.send_all_sync(queued, &mut requeued));
+        }
+        self.requeue(requeued);
+        self.flush_sessions();
+        outcome
+    }
+
+    /// Queue a client report of the events dropped since the last one, if
+    /// `client_reports` is on. It goes past `max_queue_size` rather than
+    /// displacing an event.
+    fn queue_client_report(&self) {
+        if !self.config.client_reports {
+            return;
+        }
+        let timestamp = self.timestamps.now(self.config.timestamp_mode);
+        if let Some(report) = self
+            .client_reports
+            .take(&self.counters, &self.config, timestamp)
+        {
+            self.queue.push_unbounded(Queued {
+                event: report,
+                enqueued_at: self.clock.instant(),
+                ack: None,
+            });
+        }
+    }
+
+    /// Send the queued events that fill whole batches of `batch_size`,
+    /// oldest first, leaving the rest queued.
+    pub(crate) fn flush_full_batches(&self) -> TryFlush {
+        let _guard = self.flush_lock.lock();
+        let size = self.config.batch_size.max(1);
+        let mut queued = self.queue.take_batches(size);
+        if queued.is_empty() {
+            return TryFlush::Pending(self.queue.len());
+        }
+        self.attach_duplicate_counts(&mut queued);
+        let queued = self.by_priority(queued);
+
+        let mut requeued = Vec::new();
+        let outcome = self.send_all_sync(queued, &mut requeued);
+        self.requeue(requeued);
+        TryFlush::Flushed(outcome)
+    }
+
+    /// Stop the interval flusher and end the session in
+    /// progress ahead of the final flush of `PulseKit::shutdown`.
+    #[cfg(feature = "async")]
+    pub(crate) fn shut_down(&self) {
+        self.flush_timer.lock().take();
+        self.shutting_down.store(true, Ordering::Relaxed);
+        self.sessions.end(SessionStatus::Healthy);
+    }
+
+    /// [`Delivery::shut_down`], then flush within `drop_flush_timeout`.
+    #[cfg(feature = "async")]
+    pub(crate) async fn close(&self) -> FlushOutcome {
+        self.shut_down();
+        match self.config.drop_flush_timeout {
+            None => self.flush().await,
+            Some(timeout) => match self.flush_until(Instant::now() + timeout).await {
+                Ok(outcome) => outcome,
+                Err(FlushError::Timeout { outcome }) => outcome,
+            },
+        }
+    }
+
+    /// Flush for at most `drop_flush_timeout`. Events still queued after
+    /// that are lost, unless `offline_storage` keeps them.
+    fn flush_on_drop(&self) {
+        match self.config.drop_flush_timeout {
+            Some(timeout) => {
+                self.drain_with_progress(Instant::now() + timeout, &|_| {});
+            }
+            None => {
+                self.flush_blocking();
+            }
+        }
+    }
+
+    /// Send the queue one batch at a time until it is empty or `deadline`
+    /// passes, reporting the queue length before each batch.
+    pub(crate) fn drain_with_progress(
+        &self,
+        deadline: Instant,
+        on_progress: &dyn Fn(usize),
+    ) -> bool {
+        let _guard = self.flush_lock.lock();
+        let mut requeued = Vec::new();
+        loop {
+            let remaining = self.queue.len();
+            if remaining == 0 || Instant::now() >= deadline {
+                break;
+            }
+            on_progress(remaining);
+
+            let mut queued = self.take_queued();
+            let rest = queued.split_off(queued.len().min(self.request_size()));
+            self.queue.put_back(rest);
+            self.send_all_sync(queued, &mut requeued);
+        }
+        self.requeue(requeued);
+        if Instant::now() < deadline {
+            self.flush_sessions();
+        }
+
+        let remaining = self.queue.len();
+        on_progress(remaining);
+        remaining == 0
+    }
+
+    /// Send events in batches, continuing past failed batches. Batches to be
+    /// queued again are moved to `requeued` rather than back into the queue,
+    /// so the caller's flush doesn't pick them up again.
+    pub(crate) fn send_all_sync(
+        &self,
+        queued: Vec<Queued>,
+        requeued: &mut Vec<Queued>,
+    ) -> FlushOutcome {
+        let mut outcome = FlushOutcome::default();
+        for (destination, batch) in self.batches(queued) {
+            let count = batch.len();
+            let result = self.send_events_sync(&destination, batch, requeued);
+            outcome.record(count, result);
+        }
+        outcome
+    }
+
+    /// Put batches that failed on every attempt back at the front of the
+    /// queue for the next flush, then bring offline storage in line with
+    /// the queue so sent events are removed from it. Ends every flush, so
+    /// it also runs `on_send_failure`.
+    pub(crate) fn requeue(&self, requeued: Vec<Queued>) {
+        if !requeued.is_empty() {
+            debug_log!(
+                self.config,
+                Debug,
+                "Requeued {} event(s) for the next flush",
+                requeued.len()
+            );
+        }
+        self.queue.restore(requeued, None);
+        self.run_send_failure_hook();
+    }
+
+    #[cfg(feature = "async")]
+    async fn requeue_async(&self, requeued: Vec<Queued>) {
+        if !requeued.is_empty() {
+            debug_log!(
+                self.config,
+                Debug,
+                "Requeued {} event(s) for the next flush",
+                requeued.len()
+            );
+        }
+        self.queue.restore_async(requeued).await;
+        self.run_send_failure_hook();
+    }
+
+    /// Empty the queue for the caller to deliver, highest effective
+    /// priority first. Their acks are dropped without being called.
+    pub(crate) fn drain(&self) -> Vec<Event> {
+        let mut queued = self.queue.drain();
+        self.attach_duplicate_counts(&mut queued);
+        self.by_priority(queued)
+            .into_iter()
+            .map(|q| q.event)
+            .collect()
+    }
+
+    /// Put `events` back at the front of the queue in the order given.
+    /// Those past `max_queue_size` are dropped as if the queue were full.
+    pub(crate) fn push_front(&self, events: Vec<Event>) {
+        let enqueued_at = self.clock.instant();
+        let incoming: Vec<Queued> = events
+            .into_iter()
+            .map(|event| Queued {
+                event,
+                enqueued_at,
+                ack: None,
+            })
+            .collect();
+        let evicted = self
+            .queue
+            .restore(incoming, Some(self.config.max_queue_size));
+        for queued in evicted {
+            self.drop_overflow(queued);
+        }
+    }
+
+    /// Send the session counts recorded since the last flush.
+    fn flush_sessions(&self) {
+        if self.rate_limit.remaining().is_some() {
+            return;
+        }
+        let sessions = self.sessions.take();
+        if sessions.is_empty() {
+            return;
+        }
+        let result = self.session_request(&sessions).and_then(|request| {
+            self.transport
+                .send(&request)
+                .and_then(|response| self.check_response(response))
+        });
+        self.settle_sessions(sessions, result.map(|_| ()));
+    }
+
+    /// Async counterpart of [`Delivery::flush_sessions`].
+    #[cfg(feature = "async")]
+    async fn flush_sessions_async(&self) {
+        let sessions = self.sessions.take();
+        if sessions.is_empty() {
+            return;
+        }
+        let result = self.send_sessions_async(&sessions).await;
+        self.settle_sessions(sessions, result);
+    }
+
+    #[cfg(feature = "async")]
+    async fn send_sessions_async(
+        &self,
+        sessions: &[SessionAggregate],
+    ) -> Result<(), PulseKitError> {
+        if let Some(remaining) = self.rate_limit.remaining() {
+            return Err(PulseKitError::RateLimited(remaining));
+        }
+        let request = self.session_request(sessions)?;
+        let response = self.transport.send_async(&request).await?;
+        self.check_response(response).map(|_| ())
+    }
+
+    /// Session counts get one attempt per flush; counts that failed with a
+    /// retryable error are kept and sent with the next one.
+    fn settle_sessions(&self, sessions: Vec<SessionAggregate>, result: Result<(), PulseKitError>) {
+        let Err(err) = result else {
+            return;
+        };
+        debug_log!(self.config, Error, "Failed to send session counts: {}", err);
+        if err.is_retryable() {
+            self.sessions.restore(sessions);
+        }
+    }
+
+    fn session_request(
+        &self,
+        sessions: &[SessionAggregate],
+    ) -> Result<TransportRequest, PulseKitError> {
+        let body = serde_json::json!({ "aggregates": sessions });
+        self.log_payload(&body);
+        let mut headers = vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            (API_KEY.to_string(), self.config.api_key.clone()),
+        ];
+        self.apply_custom_headers(&mut headers);
+        Ok(TransportRequest {
+            url: join_url(&self.config.endpoint, &self.config.sessions_path),
+            headers,
+            body: serde_json::to_vec(&body)?,
+            event_count: 0,
+        })
+    }
+
+    /// Most events sent in one request.
+    fn request_size(&self) -> usize {
+        self.config
+            .max_events_per_request
+            .unwrap_or(self.config.batch_size)
+            .max(1)
+    }
+
+    /// Split drained events into batches of at most `request_size` per
+    /// endpoint and API key, keeping their order within each destination.
+    /// Events with attachments are sent on their own, after the rest.
+    fn batches(&self, queued: Vec<Queued>) -> Vec<(Destination, Vec<Queued>)> {
+        let mut routes: Vec<(Destination, Vec<Queued>)> = Vec::new();
+        let mut attached = Vec::new();
+        for q in queued {
+            let destination = self.destination_for(&q.event);
+            if attachment::attachments(&q.event).is_some() {
+                attached.push((destination, vec![q]));
+                continue;
+            }
+            match routes.iter_mut().find(|(d, _)| *d == destination) {
+                Some((_, queued)) => queued.push(q),
+                None => routes.push((destination, vec![q])),
+            }
+        }
+
+        let size = self.request_size();
+        let mut batches = Vec::new();
+        for (destination, queued) in routes {
+            let Some(max_bytes) = self.config.max_request_bytes else {
+                let mut queued = queued.into_iter().peekable();
+                while queued.peek().is_some() {
+                    batches.push((destination.clone(), queued.by_ref().take(size).collect()));
+                }
+                continue;
+            };
+            let mut batch = Vec::new();
+            let mut events_bytes = 0;
+            for q in queued {
+                let bytes = crate::oversize::serialized_size(&q.event);
+                if !batch.is_empty()
+                    && (batch.len() >= size
+                        || batch_body_size(events_bytes + bytes, batch.len() + 1) > max_bytes)
+                {
+                    batches.push((destination.clone(), std::mem::take(&mut batch)));
+                    events_bytes = 0;
+                }
+                events_bytes += bytes;
+                batch.push(q);
+            }
+            if !batch.is_empty() {
+                batches.push((destination.clone(), batch));
+            }
+        }
+        batches.extend(attached);
+        batches
+    }
+
+    fn destination_for(&self, event: &Event) -> Destination {
+        let api_key = self
+            .config
+            .api_key_fn
+            .as_ref()
+            .and_then(|api_key_fn| api_key_fn(event))
+            .unwrap_or_else(|| self.config.api_key.clone());
+        Destination {
+            endpoint: self.endpoint_for(event).to_string(),
+            api_key,
+        }
+    }
+
+    /// The endpoint of the first route matching the event type, then the
+    /// error endpoint for errors, then the default endpoint.
+    fn endpoint_for(&self, event: &Event) -> &str {
+        if let Some(route) = self
+            .config
+            .routes
+            .iter()
+            .find(|route| event.event_type.starts_with(&route.prefix))
+        {
+            return &route.endpoint;
+        }
+        match &self.config.error_endpoint {
+            Some(endpoint) if is_error(event) => endpoint,
+            _ => &self.config.endpoint,
+        }
+    }
+
+    /// Send `queued` in batches, up to `max_concurrent_sends` at once, then
+    /// settle them in queue order. Returns `false` if `deadline` passed
+    /// first; batches still unsent or in flight then are set aside to be
+    /// queued again.
+    #[cfg(feature = "async")]
+    async fn send_all_async(
+        &self,
+        queued: Vec<Queued>,
+        deadline: Option<Instant>,
+        outcome: &mut FlushOutcome,
+        requeued: &mut Vec<Queued>,
+    ) -> bool {
+        // Acks aren't `Sync`, so they are kept apart from what the sends
+        // borrow.
+        let (batches, slots): (Vec<_>, Vec<_>) = self
+            .batches(queued)
+            .into_iter()
+            .map(|(destination, batch)| {
+                let (events, slots) = split_batch(batch);
+                ((destination, events), slots)
+            })
+            .unzip();
+
+        // `buffered` yields in order, so the nth result is the nth batch's.
+        let mut sent = Vec::with_capacity(batches.len());
+        let completed = {
+            let batches = &batches;
+            let mut sends = stream::iter(0..batches.len())
+                .map(|i| self.send_batch_async(&batches[i].0, &batches[i].1))
+                .buffered(self.config.max_concurrent_sends.max(1));
+            loop {
+                let next = match deadline {
+                    Some(deadline) => match timer::timeout_at(deadline, sends.next()).await {
+                        Some(next) => next,
+                        None => break false,
+                    },
+                    None => sends.next().await,
+                };
+                match next {
+                    Some(result) => sent.push(result),
+                    None => break true,
+                }
+            }
+        };
+
+        let mut sent = sent.into_iter();
+        for ((_, events), slots) in batches.into_iter().zip(slots) {
+            let count = events.len();
+            match sent.next() {
+                Some(Ok((request, result))) => {
+                    let result = self
+                        .finish_batch_async(&request, events, slots, result, requeued)
+                        .await;
+                    outcome.record(count, result);
+                }
+                Some(Err(err)) => outcome.record(count, self.reject_batch(&events, slots, err)),
+                None => requeued.extend(join_batch(events, slots)),
+            }
+        }
+        completed
+    }
+
+    /// Send a batch with retries, failing over to the next endpoint as
+    /// `failover_endpoints` allows. Returns the request and its last result,
+    /// or why no request could be built.
+    #[cfg(feature = "async")]
+    async fn send_batch_async(
+        &self,
+        destination: &Destination,
+        events: &[Event],
+    ) -> Result<(TransportRequest, SendResult), PulseKitError> {
+        let mut request = self.build_request(destination, events)?;
+        let mut result = self.send_with_retries_async(&mut request).await;
+        let mut endpoint = destination.endpoint.as_str();
+        for next in self.failover_endpoints(destination) {
+            if !result
+                .as_ref()
+                .is_err_and(|err| self.should_fail_over(err, &request))
+            {
+                break;
+            }
+            self.redirect(&mut request, endpoint, next);
+            endpoint = next;
+            result = self.send_with_retries_async(&mut request).await;
+        }
+        Ok((request, result))
+    }
+
+    /// Send `request`, retrying as `retry` allows.
+    #[cfg(feature = "async")]
+    async fn send_with_retries_async(&self, request: &mut TransportRequest) -> SendResult {
+        let mut attempt = 1;
+        loop {
+            if let Some(remaining) = self.rate_limit.remaining() {
+                return Err(PulseKitError::RateLimited(remaining));
+            }
+            self.mark_attempt(request, attempt);
+            let result = self
+                .transport
+                .send_async(request)
+                .await
+                .and_then(|response| self.check_response(response));
+            if result.is_err() {
+                Counters::increment(&self.counters.send_failures);
+            }
+            match self.retry_delay(&result, attempt, request.header(IDEMPOTENCY_KEY).is_some()) {
+                Some(delay) => {
+                    timer::sleep(delay).await;
+                    attempt += 1;
+                }
+                None => return result,
+            }
+        }
+    }
+
+    fn send_events_sync(
+        &self,
+        destination: &Destination,
+        batch: Vec<Queued>,
+        requeued: &mut Vec<Queued>,
+    ) -> BatchResult {
+        let (events, slots) = split_batch(batch);
+        let mut request = match self.build_request(destination, &events) {
+            Ok(request) => request,
+            Err(err) => return self.reject_batch(&events, slots, err),
+        };
+
+        let mut result = self.send_with_retries(&mut request);
+        let mut endpoint = destination.endpoint.as_str();
+        for next in self.failover_endpoints(destination) {
+            if !result
+                .as_ref()
+                .is_err_and(|err| self.should_fail_over(err, &request))
+            {
+                break;
+            }
+            self.redirect(&mut request, endpoint, next);
+            endpoint = next;
+            result = self.send_with_retries(&mut request);
+        }
+        self.finish_batch(&request, events, slots, result, requeued)
+    }
+
+    /// Send `request`, retrying as `retry` allows.
+    fn send_with_retries(&self, request: &mut TransportRequest) -> SendResult {
+        let mut attempt = 1;
+        loop {
+            if let Some(remaining) = self.rate_limit.remaining() {
+                return Err(PulseKitError::RateLimited(remaining));
+            }
+            self.mark_attempt(request, attempt);
+            let result = self
+                .transport
+                .send(request)
+                .and_then(|response| self.check_response(response));
+            if result.is_err() {
+                Counters::increment(&self.counters.send_failures);
+            }
+            match self.retry_delay(&result, attempt, request.header(IDEMPOTENCY_KEY).is_some()) {
+                Some(delay) => {
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                None => return result,
+            }
+        }
+    }
+
+    /// Whether a batch that failed with `err` goes on to the next endpoint:
+    /// the endpoint was unreachable or failing, and resending is allowed.
+    fn should_fail_over(&self, err: &PulseKitError, request: &TransportRequest) -> bool {
+        let failing = match err {
+            PulseKitError::Network(_) => true,
+            PulseKitError::Http { status, .. } => *status >= 500,
+            _ => false,
+        };
+        failing && self.may_retry(err, request.header(IDEMPOTENCY_KEY).is_some())
+    }
+
+    /// The endpoints to fail over to from `destination`, which only the
+    /// default endpoint has.
+    fn failover_endpoints<'a>(
+        &'a self,
+        destination: &Destination,
+    ) -> impl Iterator<Item = &'a str> {
+        let failover = if destination.endpoint == self.config.endpoint {
+            &self.config.failover_endpoints[..]
+        } else {
+            &[]
+        };
+        failover.iter().map(String::as_str)
+    }
+
+    /// Point `request` at `to` instead of `from`, keeping its path.
+    fn redirect(&self, request: &mut TransportRequest, from: &str, to: &str) {
+        debug_log!(
+            self.config,
+            Warn,
+            "Sending to {} failed, failing over to {}",
+            from,
+            to
+        );
+        if let Some(path) = request.url.strip_prefix(from) {
+            request.url = format!("{}{}", to, path);
+        }
+    }
+
+    /// Fail a batch that could not be turned into a request.
+    fn reject_batch(
+        &self,
+        events: &[Event],
+        slots: Vec<(Instant, Option<Ack>)>,
+        err: PulseKitError,
+    ) -> BatchResult {
+        debug_log!(self.config, Error, "Failed to build request: {}", err);
+        notify_acks(slots, &Err(err.clone()));
+        self.report_send_failure(events, &err);
+        BatchResult::Failed(err)
+    }
+
+    /// Keep events that will never be delivered for `on_send_failure`,
+    /// which runs once the flush is over.
+    fn report_send_failure(&self, events: &[Event], err: &PulseKitError) {
+        if self.config.on_send_failure.is_some() {
+            self.abandoned.lock().push((events.to_vec(), err.clone()));
+        }
+    }
+
+    /// Run `on_send_failure` for the batches abandoned by the flush that
+    /// just ended. No lock is held, and events the hook captures wait for
+    /// the next flush rather than extending this one, which could
+    /// otherwise keep failing and capturing forever.
+    fn run_send_failure_hook(&self) {
+        let Some(on_send_failure) = &self.config.on_send_failure else {
+            return;
+        };
+        let abandoned = std::mem::take(&mut *self.abandoned.lock());
+        for (events, err) in abandoned {
+            on_send_failure(&events, &err);
+        }
+    }
+
+    /// Settle a batch once no more attempts will be made, handing it to
+    /// the fallback transport first if every attempt failed.
+    fn finish_batch(
+        &self,
+        request: &TransportRequest,
+        events: Vec<Event>,
+        slots: Vec<(Instant, Option<Ack>)>,
+        result: SendResult,
+        requeued: &mut Vec<Queued>,
+    ) -> BatchResult {
+        let fallback = match (&result, &self.config.fallback_transport) {
+            (Err(_), Some(fallback)) => Some(fallback.send(request)),
+            _ => None,
+        };
+        self.settle_batch(request, events, slots, result, fallback, requeued)
+    }
+
+    /// [`Delivery::finish_batch`] for async flushes, which send to the
+    /// fallback transport without blocking the runtime.
+    #[cfg(feature = "async")]
+    async fn finish_batch_async(
+        &self,
+        request: &TransportRequest,
+        events: Vec<Event>,
+        slots: Vec<(Instant, Option<Ack>)>,
+        result: SendResult,
+        requeued: &mut Vec<Queued>,
+    ) -> BatchResult {
+        let fallback = match (&result, &self.config.fallback_transport) {
+            (Err(_), Some(fallback)) => Some(fallback.send_async(request).await),
+            _ => None,
+        };
+        self.settle_batch(request, events, slots, result, fallback, requeued)
+    }
+
+    /// A batch that failed on every attempt, and that the fallback
+    /// transport, if any, failed to take too, is set aside for the next
+    /// flush while the queue has room under `retry.requeue_limit` and
+    /// `max_queue_size`. Acks of requeued events are kept for the later
+    /// attempt. One the fallback failed on that can't be requeued goes to
+    /// the dead letters.
+    fn settle_batch(
+        &self,
+        request: &TransportRequest,
+        events: Vec<Event>,
+        slots: Vec<(Instant, Option<Ack>)>,
+        result: SendResult,
+        fallback: Option<Result<TransportResponse, PulseKitError>>,
+        requeued: &mut Vec<Queued>,
+    ) -> BatchResult {
+        self.log_result(events.len(), &result);
+        let err = match result {
+            Ok((_, response)) => {
+                Counters::add(&self.counters.sent, events.len());
+                notify_acks(slots, &Ok(()));
+                return BatchResult::Sent(response);
+            }
+            Err(err) => err,
+        };
+
+        let fallback_failed = match fallback {
+            Some(Ok(response)) => {
+                debug_log!(
+                    self.config,
+                    Debug,
+                    "Sent {} event(s) to the fallback transport",
+                    request.event_count
+                );
+                Counters::add(&self.counters.sent, events.len());
+                notify_acks(slots, &Ok(()));
+                // The batch was taken either way; a body that isn't an
+                // ingest response just has no IDs to report.
+                return BatchResult::Sent(
+                    IngestResponse::parse(&response.body).unwrap_or_default(),
+                );
+            }
+            Some(Err(e)) => {
+                debug_log!(self.config, Error, "Fallback transport failed: {}", e);
+                true
+            }
+            None => false,
+        };
+        let keyed = request.header(IDEMPOTENCY_KEY).is_some();
+        if self.may_retry(&err, keyed) {
+            let pending = self.queue.len() + requeued.len();
+            let limit = self
+                .config
+                .retry
+                .requeue_limit
+                .min(self.config.max_queue_size);
+            if pending + events.len() <= limit {
+                requeued.extend(join_batch(events, slots));
+                return BatchResult::Requeued(err);
+            }
+        }
+
+        notify_acks(slots, &Err(err.clone()));
+        self.report_send_failure(&events, &err);
+        if fallback_failed {
+            for event in events {
+                self.dead_letters.push(DropReason::SendFailed, event);
+            }
+        }
+        BatchResult::Failed(err)
+    }
+
+    fn build_request(
+        &self,
+        destination: &Destination,
+        events: &[Event],
+    ) -> Result<TransportRequest, PulseKitError> {
+        let (mut url, body) = self.prepare_request(&destination.endpoint, events);
+        self.log_payload(&body);
+        let attachments = match events {
+            [event] => attachment::attachments(event),
+            _ => None,
+        };
+        let (body, content_type) = match attachments {
+            // The event part of a multipart body is always JSON.
+            Some(attachments) => {
+                let boundary = format!("pulsekit-{}", new_idempotency_key());
+                let body =
+                    attachment::multipart_body(&serde_json::to_vec(&body)?, attachments, &boundary);
+                let path = format!("{}/attachments", self.config.events_path);
+                url = join_url(&destination.endpoint, &path);
+                (body, format!("multipart/form-data; boundary={}", boundary))
+            }
+            None => {
+                let format = self.config.wire_format;
+                (format.encode(&body)?, format.content_type().to_string())
+            }
+        };
+
+        let mut headers = vec![
+            ("Content-Type".to_string(), content_type),
+            (API_KEY.to_string(), destination.api_key.clone()),
+        ];
+        if let Some(key) = self.idempotency_key() {
+            headers.push((IDEMPOTENCY_KEY.to_string(), key));
+        }
+        if self.config.event_count_header {
+            headers.push((EVENT_COUNT.to_string(), events.len().to_string()));
+        }
+        if self.config.batch_hints && self.shutting_down.load(Ordering::Relaxed) {
+            headers.push((FLUSH_REASON.to_string(), "shutdown".to_string()));
+        }
+        self.apply_custom_headers(&mut headers);
+
+        #[cfg(feature = "gzip")]
+        let body = match self.config.compression_min_bytes {
+            Some(min) if body.len() > min => match gzip(&body) {
+                Ok(compressed) => {
+                    headers.push(("Content-Encoding".to_string(), "gzip".to_string()));
+                    compressed
+                }
+                Err(_) => body,
+            },
+            _ => body,
+        };
+
+        Ok(TransportRequest {
+            url,
+            headers,
+            body,
+            event_count: events.len(),
+        })
+    }
+
+    /// Add the `headers` from the config, replacing defaults of the same
+    /// name except the API key.
+    fn apply_custom_headers(&self, headers: &mut Vec<(String, String)>) {
+        for (name, value) in &self.config.headers {
+            if name.eq_ignore_ascii_case(API_KEY) {
+                continue;
+            }
+            headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+            headers.push((name.clone(), value.clone()));
+        }
+    }
+
+    /// Number the attempt in the `X-PulseKit-Attempt` header with
+    /// `batch_hints`, so the server can tell retries from first sends.
+    fn mark_attempt(&self, request: &mut TransportRequest, attempt: u32) {
+        if !self.config.batch_hints {
+            return;
+        }
+        request
+            .headers
+            .retain(|(name, _)| !name.eq_ignore_ascii_case(ATTEMPT));
+        request
+            .headers
+            .push((ATTEMPT.to_string(), attempt.to_string()));
+    }
+
+    fn check_response(&self, response: TransportResponse) -> SendResult {
+        self.rate_limit.observe(&response);
+        if self.config.correct_clock_skew {
+            self.timestamps.observe(&response, self.config.timestamp_mode);
+        }
+        check_status(response)
+    }
+
+    fn idempotency_key(&self) -> Option<String> {
+        self.config.idempotency_keys.then(new_idempotency_key)
+    }
+
+    /// How long to wait before retrying a failed send, or `None` to give up.
+    ///
+    /// Batches without an idempotency key may have been partially processed
+    /// by the server, so they are only retried when `retry_unkeyed` accepts
+    /// the risk of duplicates.
+    fn retry_delay(&self, result: &SendResult, attempt: u32, keyed: bool) -> Option<Duration> {
+        let retry = &self.config.retry;
+        let err = result.as_ref().err()?;
+        if attempt >= retry.max_attempts
+            || !self.may_retry(err, keyed)
+            || self.rate_limit.remaining().is_some()
+        {
+            return None;
+        }
+
+        let delay = jittered(
+            retry.base_delay * 2u32.saturating_pow(attempt - 1),
+            retry.jitter,
+        );
+        debug_log!(
+            self.config,
+            Warn,
+            "Send failed ({}), retrying in {:?}",
+            err,
+            delay
+        );
+        Some(delay)
+    }
+
+    /// Whether resending a batch that failed with `err` is allowed. A
+    /// rate-limited batch was refused unprocessed, so it may always be.
+    fn may_retry(&self, err: &PulseKitError, keyed: bool) -> bool {
+        let unprocessed = matches!(
+            err,
+            PulseKitError::RateLimited(_) | PulseKitError::Http { status: 429, .. }
+        );
+        err.is_retryable() && (keyed || unprocessed || self.config.retry.retry_unkeyed)
+    }
+
+    fn log_result(&self, count: usize, result: &SendResult) {
+        match result {
+            Ok((status, _)) => debug_log!(
+                self.config,
+                Debug,
+                "Sent {} event(s), status: {}",
+                count,
+                status
+            ),
+            Err(e) => debug_log!(self.config, Error, "Failed to send events: {}", e),
+        }
+    }
+
+    fn log_payload(&self, body: &impl Serialize) {
+        if self.config.log_payloads {
+            debug_log!(self.config, Debug, "{}", format_payload(body));
+        }
+    }
+
+    pub(crate) fn prepare_request<'a>(
+        &self,
+        endpoint: &str,
+        events: &'a [Event],
+    ) -> (String, RequestBody<'a>) {
+        if let [event] = events {
+            let url = join_url(endpoint, &self.config.events_path);
+            (url, RequestBody::Event(event))
+        } else {
+            let url = join_url(endpoint, &self.config.batch_path);
+            let body = if self.config.hoist_common_fields {
+                hoist_common_fields(events)
+            } else {
+                BatchBody {
+                    events: Cow::Borrowed(events),
+                    ..Default::default()
+                }
+            };
+            (url, RequestBody::Batch(body))
+        }
+    }
+}
+
+impl Drop for Delivery {
+    /// Flush what is left, for at most `drop_flush_timeout`. Inside a
+    /// Tokio runtime the last handle has already left the flush to a task,
+    /// so it is skipped: waiting for it here would block a runtime thread.
+    /// That leaves only a client whose task never ran, such as one dropped
+    /// as its runtime shuts down. On wasm32 it is always skipped, as a
+    /// blocking send can't reach the server there. Without the `async`
+    /// feature there is no telling whether this is such a thread, so the
+    /// flush runs on a separate one while this one waits for it.
+    ///
+    /// Events left unsent by a client that was never shut down are reported
+    /// with `debug` on.
+    fn drop(&mut self) {
+        self.flush_timer.get_mut().take();
+        let shut_down = std::mem::replace(self.shutting_down.get_mut(), true);
+        if *self.handed_off.get_mut() {
+            return;
+        }
+        self.sessions.end(SessionStatus::Healthy);
+        #[cfg(feature = "async")]
+        if cfg!(target_arch = "wasm32") || tokio::runtime::Handle::try_current().is_ok() {
+            debug_log!(
+                self.config,
+                Warn,
+                "Not flushing on drop inside an async runtime, which it would block"
+            );
+        } else {
+            self.flush_on_drop();
+        }
+        #[cfg(not(feature = "async"))]
+        {
+            let delivery = &*self;
+            thread::scope(|scope| {
+                scope.spawn(|| delivery.flush_on_drop());
+            });
+        }
+
+        let unsent = self.queue.len();
+        if unsent > 0 && !shut_down {
+            debug_log!(
+                self.config,
+                Warn,
+                "{} event(s) were not sent before the client was dropped; \
+                 await PulseKit::shutdown to flush them",
+                unsent
+            );
+        }
+    }
+}
+
+fn is_error(event: &Event) -> bool {
+    event.event_type == "error" || event.level.is_some_and(|level| level >= Level::Error)
+}
+
+/// A request body, serialized straight from the events.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub(crate) enum RequestBody<'a> {
+    /// A lone event, sent bare
+    Event(&'a Event),
+    Batch(BatchBody<'a>),
+}
+
+/// Several events inside `{"events":[...]}`, along with the fields
+/// `hoist_common_fields` moved out of them.
+#[derive(Default, Serialize)]
+pub(crate) struct BatchBody<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    release: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_version: Option<u32>,
+    events: Cow<'a, [Event]>,
+}
+
+/// Move each of `environment`, `release` and `schema_version` that every
+/// event sets to the same value from the events to the envelope. The events
+/// are only copied when there is something to move.
+fn hoist_common_fields(events: &[Event]) -> BatchBody<'_> {
+    fn common<T: Clone + PartialEq>(
+        events: &[Event],
+        field: impl Fn(&Event) -> Option<&T>,
+    ) -> Option<T> {
+        let first = field(events.first()?)?;
+        events
+            .iter()
+            .all(|event| field(event) == Some(first))
+            .then(|| first.clone())
+    }
+
+    let environment = common(events, |event| event.environment.as_ref());
+    let release = common(events, |event| event.release.as_ref());
+    let schema_version = common(events, |event| event.schema_version.as_ref());
+    let mut events = Cow::Borrowed(events);
+    if environment.is_some() || release.is_some() || schema_version.is_some() {
+        for event in events.to_mut() {
+            if environment.is_some() {
+                event.environment = None;
+            }
+            if release.is_some() {
+                event.release = None;
+            }
+            if schema_version.is_some() {
+                event.schema_version = None;
+            }
+        }
+    }
+    BatchBody {
+        environment,
+        release,
+        schema_version,
+        events,
+    }
+}
+
+/// Size of the body [`Delivery::prepare_request`] builds for `count` events
+/// of `events_bytes` in total: a lone event is sent bare, several inside
+/// `{"events":[...]}` separated by commas. Hoisting common fields only
+/// makes the body smaller.
+fn batch_body_size(events_bytes: usize, count: usize) -> usize {
+    match count {
+        0 | 1 => events_bytes,
+        _ => r#"{"events":[]}"#.len() + events_bytes + count - 1,
+    }
+}
+
+/// Separate a batch's events, which go into the request body, from the
+/// bookkeeping needed to queue them again.
+fn split_batch(batch: Vec<Queued>) -> (Vec<Event>, Vec<(Instant, Option<Ack>)>) {
+    batch
+        .into_iter()
+        .map(|q| (q.event, (q.enqueued_at, q.ack)))
+        .unzip()
+}
+
+/// Undo [`split_batch`].
+fn join_batch(
+    events: Vec<Event>,
+    slots: Vec<(Instant, Option<Ack>)>,
+) -> impl Iterator<Item = Queued> {
+    events
+        .into_iter()
+        .zip(slots)
+        .map(|(event, (enqueued_at, ack))| Queued {
+            event,
+            enqueued_at,
+            ack,
+        })
+}
+
+fn notify_acks(slots: Vec<(Instant, Option<Ack>)>, result: &Result<(), PulseKitError>) {
+    for ack in slots.into_iter().filter_map(|(_, ack)| ack) {
+        ack(result.clone());
+    }
+}
+
+/// Turn a non-2xx response, or a 2xx one with a body that isn't an
+/// [`IngestResponse`], into an error.
+fn check_status(response: TransportResponse) -> SendResult {
+    if (200..300).contains(&response.status) {
+        Ok((response.status, IngestResponse::parse(&response.body)?))
+    } else {
+        Err(PulseKitError::Http {
+            status: response.status,
+            body: String::from_utf8_lossy(&response.body).into_owned(),
+        })
+    }
+}
+
+/// A key unique to one batch, reused across its retries so the server can
+/// discard duplicates.
+fn new_idempotency_key() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!(
+        "{:x}-{:x}-{:x}",
+        nanos,
+        process::pid().unwrap_or_default(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// `path` appended to `endpoint` with exactly one `/` between them.
+pub(crate) fn join_url(endpoint: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        endpoint.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// Render a request body for debug output. Events are logged exactly as they
+/// were queued, so any scrubbing applied during `capture` is already reflected.
+pub(crate) fn format_payload(body: &impl Serialize) -> String {
+    let pretty = serde_json::to_string_pretty(body).unwrap_or_default();
+    format!("Payload:\n{}", pretty)
+}
+
+/// Connect to the health endpoint in the background; failures are only
+/// logged, the first real send will simply connect itself. Inside a Tokio
+/// runtime the connections of async sends are warmed up on a task too, and
+/// on wasm32 only theirs are, on the event loop.
+fn spawn_warm_up(delivery: &Delivery) {
+    let transport = delivery.transport.clone();
+    let url = join_url(&delivery.config.endpoint, &delivery.config.health_path);
+    let config = delivery.config.clone();
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Err(e) = transport.warm_up_async(&url).await {
+            debug_log!(config, Warn, "Connection warm-up failed: {}", e);
+        }
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    warm_up(transport, url, config);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn warm_up(transport: Arc<dyn Transport>, url: String, config: Config) {
+    #[cfg(feature = "async")]
+    if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+        let (transport, url, config) = (transport.clone(), url.clone(), config.clone());
+        runtime.spawn(async move {
+            if let Err(e) = transport.warm_up_async(&url).await {
+                debug_log!(config, Warn, "Connection warm-up failed: {}", e);
+            }
+        });
+    }
+    thread::spawn(move || {
+        if let Err(e) = transport.warm_up(&url) {
+            debug_log!(config, Warn, "Connection warm-up failed: {}", e);
+        }
+    });
+}
+
+/// `interval` scaled by a random factor in `1 ± jitter`, so clients started
+/// together don't flush in lockstep.
+fn jittered(interval: Duration, jitter: f64) -> Duration {
+    let jitter = jitter.clamp(0.0, 1.0);
+    if jitter == 0.0 {
+        return interval;
+    }
+    let random = RandomState::new().build_hasher().finish();
+    let unit = random as f64 / u64::MAX as f64 * 2.0 - 1.0;
+    interval.mul_f64(1.0 + jitter * unit)
+}
+
+/// The worker behind `flush_interval`, stopped when dropped.
+enum FlushTimer {
+    /// Dropping the sender wakes the thread, which then exits
+    #[cfg(not(target_arch = "wasm32"))]
+    Thread { _shutdown: mpsc::Sender<()> },
+    #[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+    Task(tokio::task::JoinHandle<()>),
+    /// A task on the event loop, on wasm32
+    #[cfg(target_arch = "wasm32")]
+    Local(futures_util::future::AbortHandle),
+}
+
+impl Drop for FlushTimer {
+    fn drop(&mut self) {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            FlushTimer::Thread { .. } => {}
+            #[cfg(all(feature = "async", not(target_arch = "wasm32")))]
+            FlushTimer::Task(task) => task.abort(),
+            #[cfg(target_arch = "wasm32")]
+            FlushTimer::Local(task) => task.abort(),
+        }
+    }
+}
+
+/// Flush the queue every `period`: on a task when the client is created
+/// inside a Tokio runtime or on wasm32, otherwise on a thread.
+fn spawn_interval_flusher(delivery: Weak<Delivery>, period: Duration) -> FlushTimer {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let (task, abort) = futures_util::future::abortable(interval_flush_task(delivery, period));
+        wasm_bindgen_futures::spawn_local(async move {
+            let _ = task.await;
+        });
+        FlushTimer::Local(abort)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    interval_flush_thread(delivery, period)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn interval_flush_thread(delivery: Weak<Delivery>, period: Duration) -> FlushTimer {
+    #[cfg(feature = "async")]
+    if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+        return FlushTimer::Task(runtime.spawn(interval_flush_task(delivery, period)));
+    }
+
+    let (shutdown, stopped) = mpsc::channel::<()>();
+    thread::spawn(move || {
+        while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(period) {
+            let Some(delivery) = delivery.upgrade() else {
+                return;
+            };
+            if !delivery.queue.is_empty() {
+                delivery.flush_blocking();
+            }
+        }
+    });
+    FlushTimer::Thread {
+        _shutdown: shutdown,
+    }
+}
+
+/// Each tick waits for the previous flush to finish, and a tick that comes
+/// due meanwhile is pushed back rather than bunched up behind it, so the
+/// timer never has more than one send in flight.
+#[cfg(feature = "async")]
+async fn interval_flush_task(delivery: Weak<Delivery>, period: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut ticks = {
+        use tokio::time::{interval_at, MissedTickBehavior};
+
+        let mut ticks = interval_at(tokio::time::Instant::now() + period, period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticks
+    };
+    loop {
+        #[cfg(not(target_arch = "wasm32"))]
+        ticks.tick().await;
+        // Sleeping once the flush is done pushes the next tick back the
+        // same way.
+        #[cfg(target_arch = "wasm32")]
+        timer::sleep(period).await;
+
+        let Some(delivery) = delivery.upgrade() else {
+            return;
+        };
+        if !delivery.queue.is_empty() {
+            delivery.flush().await;
+        }
+        // The client was dropped mid-flush; its final flush blocks, so it
+        // mustn't run on a runtime thread. On wasm32 it is skipped anyway.
+        #[cfg(not(target_arch = "wasm32"))]
+        if Arc::strong_count(&delivery) == 1 {
+            tokio::task::spawn_blocking(move || drop(delivery));
+            return;
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+/// Flush the queue once no event has been captured for `idle`. The thread
+/// exits when the last client handle is dropped. On wasm32 a task on the
+/// event loop does the same.
+fn spawn_idle_flusher(delivery: Weak<Delivery>, idle: Duration) {
+    let tick = (idle / 4).max(Duration::from_millis(10));
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(async move {
+        loop {
+            timer::sleep(tick).await;
+
+            let Some(delivery) = delivery.upgrade() else {
+                return;
+            };
+
+            let quiet = delivery.last_enqueue.lock().elapsed() >= idle;
+            if quiet && !delivery.queue.is_empty() {
+                delivery.flush().await;
+            }
+        }
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    thread::spawn(move || loop {
+        thread::sleep(tick);
+
+        let Some(delivery) = delivery.upgrade() else {
+            return;
+        };
+
+        let quiet = delivery.last_enqueue.lock().elapsed() >= idle;
+        if quiet && !delivery.queue.is_empty() {
+            delivery.flush_blocking();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use crate::{
+        CaptureError, ClientOverrides, Event, Level, MemoryTransport, PulseKit, RetryConfig, Route,
+    };
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_idle_timer_delivers_without_explicit_flush() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            sync_blocking_flush: Some(Duration::from_millis(50)),
+            ..Default::default()
+        });
+
+        client.capture_message("job step 1", Level::Info);
+        client.capture_message("job step 2", Level::Info);
+
+        let requests = server.wait_for_requests(1, Duration::from_secs(5));
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/api/v1/events/batch");
+        assert_eq!(requests[0].events().len(), 2);
+        assert!(client.delivery.queue.is_empty());
+    }
+
+    #[test]
+    fn test_idle_timer_waits_for_quiet_period() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            sync_blocking_flush: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+
+        client.capture_message("still busy", Level::Info);
+        thread::sleep(Duration::from_millis(100));
+
+        assert!(server.requests().is_empty());
+        assert_eq!(client.delivery.queue.len(), 1);
+    }
+
+    #[test]
+    fn test_sub_clients_share_one_transport() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            ..Default::default()
+        });
+        let billing = client.sub_client(ClientOverrides {
+            tags: HashMap::from([("plugin".to_string(), "billing".to_string())]),
+            ..Default::default()
+        });
+        let search = client.sub_client(ClientOverrides {
+            environment: Some("staging".to_string()),
+            tags: HashMap::from([("plugin".to_string(), "search".to_string())]),
+            ..Default::default()
+        });
+
+        billing.capture_message("invoice sent", Level::Info);
+        search.capture_message("index rebuilt", Level::Info);
+        drop(billing);
+        drop(search);
+
+        assert!(server.requests().is_empty());
+        assert!(Arc::ptr_eq(
+            &client.delivery,
+            &client.sub_client(Default::default()).delivery
+        ));
+
+        client.flush_blocking();
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let events = requests[0].events();
+        assert_eq!(events[0]["tags"]["plugin"], "billing");
+        assert_eq!(events[0]["environment"], "production");
+        assert_eq!(events[1]["tags"]["plugin"], "search");
+        assert_eq!(events[1]["environment"], "staging");
+    }
+
+    fn failing_twice() -> MockServer {
+        let attempts = AtomicUsize::new(0);
+        MockServer::with_handler(move |_| {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                MockResponse::status(503)
+            } else {
+                MockResponse::status(200)
+            }
+        })
+    }
+
+    fn retry_config(retry_unkeyed: bool) -> RetryConfig {
+        RetryConfig {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(5),
+            retry_unkeyed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_keyed_batch_is_retried() {
+        let server = failing_twice();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            idempotency_keys: true,
+            retry: retry_config(false),
+            ..Default::default()
+        });
+
+        client.capture_message("keyed", Level::Info);
+        client.flush_blocking();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        let key = requests[0].header("Idempotency-Key").unwrap();
+        assert!(requests
+            .iter()
+            .all(|r| r.header("Idempotency-Key") == Some(key)));
+    }
+
+    #[test]
+    fn test_unkeyed_batch_is_not_retried_by_default() {
+        let server = failing_twice();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            idempotency_keys: false,
+            retry: retry_config(false),
+            ..Default::default()
+        });
+
+        client.capture_message("unkeyed", Level::Info);
+        client.flush_blocking();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].header("Idempotency-Key"), None);
+    }
+
+    #[test]
+    fn test_unkeyed_batch_retries_when_opted_in() {
+        let server = failing_twice();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            idempotency_keys: false,
+            retry: retry_config(true),
+            ..Default::default()
+        });
+
+        client.capture_message("unkeyed", Level::Info);
+        client.flush_blocking();
+
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_fatal_event_jumps_the_queue() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 10,
+            ..Default::default()
+        });
+        let backlog = (0..25)
+            .map(|i| Queued {
+                event: Event {
+                    event_type: "backlog".to_string(),
+                    level: Some(Level::Info),
+                    message: Some(format!("info {}", i)),
+                    ..Default::default()
+                },
+                enqueued_at: Instant::now(),
+                ack: None,
+            })
+            .collect();
+        client.delivery.queue.put_back(backlog);
+
+        client.capture_message("disk failure", Level::Fatal);
+
+        let requests = server.wait_for_requests(3, Duration::from_secs(5));
+        assert_eq!(requests.len(), 3);
+        let first = requests[0].events();
+        assert_eq!(first[0]["message"], "disk failure");
+        assert_eq!(first[1]["message"], "info 0");
+        assert_eq!(requests[2].events().last().unwrap()["message"], "info 24");
+    }
+
+    #[test]
+    fn test_waiting_events_are_boosted_by_age() {
+        let queued = Queued {
+            event: Event {
+                level: Some(Level::Debug),
+                ..Default::default()
+            },
+            enqueued_at: Instant::now() - Duration::from_secs(95),
+            ack: None,
+        };
+        let aging = Duration::from_secs(30);
+
+        assert_eq!(queued.event.effective_priority(), Priority::Low);
+        assert_eq!(
+            queued.effective_priority(Instant::now(), aging),
+            Priority::High
+        );
+        assert_eq!(
+            queued.effective_priority(Instant::now(), Duration::ZERO),
+            Priority::Low
+        );
+    }
+
+    #[test]
+    fn test_aged_events_stay_behind_critical_ones() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                batch_size: 100,
+                priority_aging: Duration::from_millis(1),
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+        client.capture_message("old news", Level::Info);
+        thread::sleep(Duration::from_millis(20));
+        client.capture_message("crash", Level::Fatal);
+        client.flush_blocking();
+
+        let messages: Vec<_> = transport
+            .events()
+            .into_iter()
+            .map(|event| event.message.unwrap())
+            .collect();
+        assert_eq!(messages, ["crash", "old news"]);
+    }
+
+    #[test]
+    fn test_event_types_are_routed_to_their_endpoints() {
+        let default = MockServer::start();
+        let payments = MockServer::start();
+        let errors = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: default.url(),
+            routes: vec![
+                Route {
+                    prefix: "payment.".to_string(),
+                    endpoint: payments.url(),
+                },
+                Route {
+                    prefix: "error".to_string(),
+                    endpoint: errors.url(),
+                },
+            ],
+            ..Default::default()
+        });
+
+        for event_type in ["payment.success", "signup", "payment.failed"] {
+            client.capture(Event {
+                event_type: event_type.to_string(),
+                ..Default::default()
+            });
+        }
+        client.capture_error("boom");
+        client.flush_blocking();
+
+        let payment_types: Vec<_> = payments
+            .events()
+            .iter()
+            .map(|e| e["type"].clone())
+            .collect();
+        assert_eq!(payment_types, ["payment.success", "payment.failed"]);
+        assert_eq!(errors.events().len(), 1);
+        assert_eq!(errors.events()[0]["message"], "boom");
+        assert_eq!(default.events().len(), 1);
+        assert_eq!(default.events()[0]["type"], "signup");
+    }
+
+    #[test]
+    fn test_flush_where_sends_only_matching_events() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            ..Default::default()
+        });
+        client.capture_message("info 1", Level::Info);
+        client.capture_error("first failure");
+        client.capture_message("info 2", Level::Info);
+        client.capture_error("second failure");
+        client.capture_message("info 3", Level::Info);
+
+        client
+            .flush_where(|event| event.level == Some(Level::Error))
+            .unwrap();
+
+        let sent: Vec<_> = server
+            .events()
+            .iter()
+            .map(|e| e["message"].clone())
+            .collect();
+        assert_eq!(sent, ["first failure", "second failure"]);
+        let remaining: Vec<_> = client
+            .delivery
+            .take_events()
+            .into_iter()
+            .map(|e| e.message.unwrap())
+            .collect();
+        assert_eq!(remaining, ["info 1", "info 2", "info 3"]);
+    }
+
+    #[test]
+    fn test_flush_where_keeps_the_queue_when_the_predicate_panics() {
+        let client = PulseKit::new(Config {
+            batch_size: 100,
+            ..Default::default()
+        });
+        client.capture_message("info 1", Level::Info);
+        client.capture_error("failure");
+        client.capture_message("info 2", Level::Info);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            client.flush_where(|event| {
+                // Capturing here would deadlock if the queue were locked.
+                client.capture_message("from predicate", Level::Info);
+                if event.level == Some(Level::Error) {
+                    panic!("predicate failed");
+                }
+                false
+            })
+        }));
+
+        assert!(result.is_err());
+        let remaining: Vec<_> = client
+            .delivery
+            .queue
+            .take()
+            .into_iter()
+            .map(|q| q.event.message.unwrap())
+            .collect();
+        assert_eq!(
+            remaining,
+            [
+                "info 1",
+                "failure",
+                "info 2",
+                "from predicate",
+                "from predicate"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flush_where_reports_send_errors() {
+        let server = MockServer::with_handler(|_| MockResponse::status(400));
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            ..Default::default()
+        });
+        client.capture_error("rejected");
+
+        let err = client.flush_where(|_| true).unwrap_err();
+        assert!(matches!(err, PulseKitError::Http { status: 400, .. }));
+    }
+
+    #[test]
+    fn test_try_capture_refuses_events_when_the_queue_is_full() {
+        let client = PulseKit::new(Config {
+            batch_size: 100,
+            max_queue_size: 2,
+            ..Default::default()
+        });
+        let event = |message: &str| Event {
+            event_type: "message".to_string(),
+            message: Some(message.to_string()),
+            ..Default::default()
+        };
+
+        client.try_capture(event("one")).unwrap();
+        client.try_capture(event("two")).unwrap();
+        assert_eq!(
+            client.try_capture(event("three")),
+            Err(CaptureError::QueueFull)
+        );
+
+        assert_eq!(client.queue_len(), 2);
+        assert_eq!(client.stats().dropped_queue_full, 0);
+        client.delivery.take_events();
+        client.try_capture(event("four")).unwrap();
+    }
+
+    #[test]
+    fn test_drained_events_can_be_queued_again() {
+        let client = PulseKit::new(Config {
+            batch_size: 100,
+            max_queue_size: 3,
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..Default::default()
+        });
+        client.capture_message("one", Level::Info);
+        client.capture_message("two", Level::Info);
+
+        let drained = client.drain_queue();
+        assert_eq!(client.queue_len(), 0);
+        let messages: Vec<_> = drained.iter().map(|e| e.message.clone().unwrap()).collect();
+        assert_eq!(messages, ["one", "two"]);
+
+        client.capture_message("three", Level::Info);
+        client.capture_message("four", Level::Info);
+        client.enqueue_batch(drained);
+
+        let queued: Vec<_> = client
+            .delivery
+            .take_events()
+            .into_iter()
+            .map(|e| e.message.unwrap())
+            .collect();
+        assert_eq!(queued, ["one", "three", "four"]);
+        assert_eq!(client.stats().dropped_queue_full, 1);
+    }
+
+    #[test]
+    fn test_blocking_capture_reports_its_delivery() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            ..Default::default()
+        });
+        client.capture_message("queued earlier", Level::Info);
+
+        client.capture_error_blocking("config missing").unwrap();
+
+        let sent: Vec<_> = server
+            .events()
+            .iter()
+            .map(|e| e["message"].clone())
+            .collect();
+        assert_eq!(sent, ["config missing", "queued earlier"]);
+        assert_eq!(client.queue_len(), 0);
+
+        let server = MockServer::with_handler(|_| MockResponse::status(400));
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            ..Default::default()
+        });
+        let err = client
+            .capture_message_blocking("rejected", Level::Warning)
+            .unwrap_err();
+        assert!(matches!(err, PulseKitError::Http { status: 400, .. }));
+    }
+
+    #[test]
+    fn test_fallback_receives_batches_the_primary_rejected() {
+        let server = MockServer::with_handler(|_| MockResponse::status(503));
+        let fallback = MemoryTransport::new();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            retry: RetryConfig {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(1),
+                ..Default::default()
+            },
+            fallback_transport: Some(Arc::new(fallback.clone())),
+            on_send_failure: Some(Arc::new(|_: &[Event], _: &PulseKitError| {
+                panic!("a batch the fallback took was reported as failed")
+            })),
+            ..Default::default()
+        });
+        let (sender, receiver) = mpsc::channel();
+
+        client.capture_with_ack(
+            Event {
+                event_type: "audit.login".to_string(),
+                message: Some("kept".to_string()),
+                ..Default::default()
+            },
+            move |result| sender.send(result).unwrap(),
+        );
+        let outcome = client.flush_blocking();
+
+        assert_eq!(server.requests().len(), 2);
+        let events = fallback.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].message.as_deref(), Some("kept"));
+        assert_eq!(receiver.try_recv(), Ok(Ok(())));
+        assert_eq!((outcome.sent, outcome.failed), (1, 0));
+        assert_eq!(client.stats().sent, 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async_flush_hands_failed_batches_to_the_fallback() {
+        let server = MockServer::with_handler(|_| MockResponse::status(503));
+        let fallback = MemoryTransport::new();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            retry: RetryConfig {
+                max_attempts: 1,
+                ..Default::default()
+            },
+            fallback_transport: Some(Arc::new(fallback.clone())),
+            ..Default::default()
+        });
+
+        client.capture_message("kept", Level::Warning);
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(client.flush());
+
+        assert_eq!(fallback.events().len(), 1);
+    }
+
+    #[test]
+    fn test_batches_the_fallback_fails_on_are_requeued_or_dead_lettered() {
+        let server = MockServer::with_handler(|_| MockResponse::status(503));
+        struct FailingTransport;
+        impl Transport for FailingTransport {
+            fn send(&self, _: &TransportRequest) -> Result<TransportResponse, PulseKitError> {
+                Err(PulseKitError::Network("disk full".to_string()))
+            }
+        }
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 10,
+            retry: RetryConfig {
+                max_attempts: 1,
+                requeue_limit: 1,
+                ..Default::default()
+            },
+            fallback_transport: Some(Arc::new(FailingTransport)),
+            dead_letter_capacity: 10,
+            ..Default::default()
+        });
+
+        client.capture_message("requeued", Level::Warning);
+        client.flush_blocking();
+        assert_eq!(client.stats().queue_depth, 1);
+        assert!(client.drained_dead_letters().is_empty());
+
+        client.capture_message("over the limit", Level::Warning);
+        client.flush_blocking();
+        let dropped = client.drained_dead_letters();
+        assert_eq!(dropped.len(), 2);
+        assert!(dropped
+            .iter()
+            .all(|(reason, _)| *reason == DropReason::SendFailed));
+    }
+
+    #[test]
+    fn test_send_failure_hook_receives_abandoned_events() {
+        let server = MockServer::with_handler(|_| MockResponse::status(503).body("overloaded"));
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let client_slot: Arc<std::sync::OnceLock<PulseKit>> = Arc::default();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 10,
+            retry: RetryConfig {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(1),
+                requeue_limit: 0,
+                ..Default::default()
+            },
+            on_send_failure: Some(Arc::new({
+                let failures = failures.clone();
+                let client_slot = client_slot.clone();
+                move |events: &[Event], err: &PulseKitError| {
+                    let messages: Vec<_> = events.iter().map(|e| e.message.clone()).collect();
+                    failures.lock().push((messages, err.clone()));
+                    if let Some(client) = client_slot.get() {
+                        client.capture_message("delivery failed", Level::Warning);
+                    }
+                }
+            })),
+            ..Default::default()
+        });
+        let _ = client_slot.set(client);
+        let client = client_slot.get().unwrap();
+
+        client.capture_message("first", Level::Error);
+        client.capture_message("second", Level::Error);
+        let outcome = client.flush_blocking();
+
+        assert_eq!(outcome.failed, 2);
+        assert_eq!(server.requests().len(), 2);
+        let failures = failures.lock();
+        assert_eq!(failures.len(), 1);
+        let (messages, err) = &failures[0];
+        assert_eq!(
+            messages,
+            &[Some("first".to_string()), Some("second".to_string())]
+        );
+        assert!(matches!(err, PulseKitError::Http { status: 503, body } if body == "overloaded"));
+        let queued = client.delivery.take_events();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].message.as_deref(), Some("delivery failed"));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_warm_connection_inside_a_runtime_warms_both_clients() {
+        let server = MockServer::start();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _client = runtime.block_on(async {
+            PulseKit::new(Config {
+                endpoint: server.url(),
+                warm_connection: true,
+                ..Default::default()
+            })
+        });
+
+        let requests = server.wait_for_requests(2, Duration::from_secs(2));
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| r.path == "/api/v1/health"));
+    }
+
+    #[test]
+    fn test_warm_connection_contacts_the_server_at_startup() {
+        let server = MockServer::start();
+        let _client = PulseKit::new(Config {
+            endpoint: format!("{}/", server.url()),
+            health_path: "healthz".to_string(),
+            warm_connection: true,
+            ..Default::default()
+        });
+
+        let requests = server.wait_for_requests(1, Duration::from_secs(2));
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, "/healthz");
+    }
+
+    #[test]
+    fn test_concurrent_flush_waits_for_the_one_in_progress() {
+        let server = MockServer::with_handler(|_| {
+            thread::sleep(Duration::from_millis(100));
+            MockResponse::status(200)
+        });
+        let client = Arc::new(PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            ..Default::default()
+        }));
+
+        for i in 0..5 {
+            client.capture_message(&format!("early {}", i), Level::Info);
+        }
+        let first = thread::spawn({
+            let client = client.clone();
+            move || client.flush_blocking()
+        });
+        thread::sleep(Duration::from_millis(30));
+        for i in 0..5 {
+            client.capture_message(&format!("late {}", i), Level::Info);
+        }
+        client.flush_blocking();
+
+        assert_eq!(server.events().len(), 10);
+        assert_eq!(server.requests().len(), 2);
+        first.join().unwrap();
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_errors_go_to_the_error_endpoint() {
+        let events_server = MockServer::start();
+        let errors_server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: events_server.url(),
+            error_endpoint: Some(errors_server.url()),
+            ..Default::default()
+        });
+
+        client.capture_message("signed up", Level::Info);
+        client.capture_error("charge failed");
+        client.capture_message("disk full", Level::Fatal);
+        client.flush_blocking();
+
+        let messages = |server: &MockServer| -> Vec<String> {
+            server
+                .events()
+                .iter()
+                .map(|e| e["message"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(messages(&events_server), ["signed up"]);
+        let mut errors = messages(&errors_server);
+        errors.sort();
+        assert_eq!(errors, ["charge failed", "disk full"]);
+    }
+
+    #[test]
+    fn test_batch_fails_over_to_the_next_endpoint() {
+        let primary = MockServer::with_handler(|_| MockResponse::status(503));
+        let secondary = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: primary.url(),
+            failover_endpoints: vec!["http://127.0.0.1:1".to_string(), secondary.url()],
+            retry: retry_config(false),
+            ..Default::default()
+        });
+
+        client.capture_message("first", Level::Info);
+        client.capture_message("second", Level::Info);
+        let outcome = client.flush_blocking();
+
+        assert_eq!(outcome.sent, 2);
+        assert_eq!(primary.requests().len(), 3);
+        let requests = secondary.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/api/v1/events/batch");
+        assert_eq!(
+            requests[0].header(API_KEY),
+            Some(client.config.api_key.as_str())
+        );
+        assert_eq!(requests[0].events().len(), 2);
+    }
+
+    #[test]
+    fn test_ingest_paths_are_configurable() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                endpoint: "https://gateway.example.com/pulsekit/".to_string(),
+                events_path: "ingest/event".to_string(),
+                batch_path: "//ingest/events/".to_string(),
+                sessions_path: "/ingest/sessions".to_string(),
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        client.capture_message("alone", Level::Info);
+        client.flush_blocking();
+        client.capture_message("first", Level::Info);
+        client.capture_message("second", Level::Info);
+        client.flush_blocking();
+        client.start_session();
+        client.end_session(SessionStatus::Healthy);
+        client.flush_blocking();
+
+        let urls: Vec<String> = transport
+            .requests()
+            .into_iter()
+            .map(|request| request.url)
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://gateway.example.com/pulsekit/ingest/event",
+                "https://gateway.example.com/pulsekit/ingest/events/",
+                "https://gateway.example.com/pulsekit/ingest/sessions",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flush_interval_jitter_spreads_workers() {
+        let config = Config {
+            flush_interval: Some(Duration::from_millis(100)),
+            flush_interval_jitter: 0.5,
+            ..Default::default()
+        };
+        let first = PulseKit::with_transport(config.clone(), MemoryTransport::new());
+        let second = PulseKit::with_transport(config, MemoryTransport::new());
+
+        let first_period = first.delivery.flush_period.unwrap();
+        let second_period = second.delivery.flush_period.unwrap();
+        assert_ne!(first_period, second_period);
+        for period in [first_period, second_period] {
+            assert!(period >= Duration::from_millis(50) && period <= Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn test_interval_worker_flushes_periodically() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                flush_interval: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        client.capture_message("tick", Level::Info);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(transport.events().len(), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_interval_task_stops_when_client_is_dropped() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let transport = MemoryTransport::new();
+
+        let timer = runtime.block_on(async {
+            let client = PulseKit::with_transport(
+                Config {
+                    flush_interval: Some(Duration::from_millis(50)),
+                    ..Default::default()
+                },
+                transport.clone(),
+            );
+            let timer = match client.delivery.flush_timer.lock().as_ref() {
+                Some(FlushTimer::Task(task)) => task.abort_handle(),
+                _ => panic!("expected the timer to run on the runtime"),
+            };
+
+            client.capture_message("tick", Level::Info);
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert_eq!(transport.events().len(), 1);
+
+            drop(client);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            timer
+        });
+        assert!(timer.is_finished());
+    }
+
+    #[test]
+    fn test_drop_inside_a_runtime_flushes_without_blocking_it() {
+        let server = MockServer::with_handler(|_| {
+            thread::sleep(Duration::from_millis(300));
+            MockResponse::status(200)
+        });
+        let runtimes = [
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .unwrap(),
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap(),
+        ];
+
+        for (i, runtime) in runtimes.iter().enumerate() {
+            let endpoint = server.url();
+            let dropped_in = runtime.block_on(async move {
+                let client = PulseKit::new(Config {
+                    endpoint,
+                    batch_size: 100,
+                    ..Default::default()
+                });
+                client.capture_message(&format!("dropped in runtime {}", i), Level::Info);
+                let started = Instant::now();
+                drop(client);
+                started.elapsed()
+            });
+            // Without the `async` feature the drop can only flush in place.
+            if cfg!(feature = "async") {
+                assert!(dropped_in < Duration::from_millis(200));
+            }
+            // Keep the runtime going until the flush task has sent the event.
+            runtime.block_on(async {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while server.events().len() <= i && Instant::now() < deadline {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            });
+        }
+
+        let mut messages: Vec<_> = server
+            .events()
+            .iter()
+            .map(|event| event["message"].as_str().unwrap().to_string())
+            .collect();
+        messages.sort();
+        assert_eq!(messages, ["dropped in runtime 0", "dropped in runtime 1"]);
+    }
+
+    #[test]
+    fn test_events_are_sent_with_their_tenant_api_key() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                api_key: "pk_default".to_string(),
+                api_key_fn: Some(Arc::new(|event: &Event| {
+                    let tenant = event.tags.as_ref()?.get("tenant")?;
+                    Some(format!("pk_{}", tenant))
+                })),
+                batch_size: 100,
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        for tenant in ["acme", "globex", "acme"] {
+            client.capture_message_with_options(
+                "login",
+                Level::Info,
+                Some(HashMap::from([("tenant".to_string(), tenant.to_string())])),
+                None,
+            );
+        }
+        client.capture_message("health check", Level::Info);
+        client.flush_blocking();
+
+        let mut keys: Vec<_> = transport
+            .requests()
+            .iter()
+            .map(|request| {
+                let key = request.header("X-PulseKit-Key").unwrap().to_string();
+                (key, request.event_count)
+            })
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                ("pk_acme".to_string(), 2),
+                ("pk_default".to_string(), 1),
+                ("pk_globex".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_event_count_header_matches_each_request() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                event_count_header: true,
+                batch_size: 100,
+                max_events_per_request: Some(4),
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        for i in 0..9 {
+            client.capture_message(&format!("event {}", i), Level::Info);
+        }
+        client.flush_blocking();
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        for request in requests {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let count = body["events"].as_array().map_or(1, Vec::len);
+            assert_eq!(
+                request.header(EVENT_COUNT),
+                Some(count.to_string().as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn test_batches_are_split_to_fit_max_request_bytes() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            max_request_bytes: Some(4096),
+            ..Default::default()
+        });
+        for i in 0..20 {
+            client.capture_message(&format!("{} {}", i, "x".repeat(900)), Level::Info);
+        }
+        client.capture_message(&"y".repeat(6000), Level::Info);
+        client.flush_blocking();
+
+        let requests = server.requests();
+        let mut sizes: Vec<_> = requests.iter().map(|r| r.body.len()).collect();
+        sizes.sort();
+        assert!(requests.len() > 5, "{} requests", requests.len());
+        assert!(sizes[sizes.len() - 1] > 6000);
+        assert!(sizes[..sizes.len() - 1].iter().all(|size| *size <= 4096));
+        assert!(sizes[..sizes.len() - 1].iter().any(|size| *size > 3000));
+        assert_eq!(server.events().len(), 21);
+    }
+
+    #[test]
+    fn test_batch_hints_mark_retries_and_the_shutdown_flush() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let server = MockServer::with_handler({
+            let calls = calls.clone();
+            move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => MockResponse::status(503),
+                _ => MockResponse::status(200),
+            }
+        });
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_hints: true,
+            retry: RetryConfig {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        client.capture_message("flushed", Level::Info);
+        client.flush_blocking();
+        client.capture_message("left at shutdown", Level::Info);
+        drop(client);
+
+        let requests = server.requests();
+        let hints: Vec<_> = requests
+            .iter()
+            .map(|request| {
+                (
+                    request.header(ATTEMPT).unwrap(),
+                    request.header(FLUSH_REASON),
+                )
+            })
+            .collect();
+        assert_eq!(hints, [("1", None), ("2", None), ("1", Some("shutdown"))]);
+    }
+
+    #[test]
+    fn test_custom_headers_are_sent_with_every_request() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            api_key: "pk_live".to_string(),
+            headers: HashMap::from([
+                ("X-Tenant-Id".to_string(), "acme".to_string()),
+                ("Authorization".to_string(), "Bearer gw-token".to_string()),
+                (
+                    "content-type".to_string(),
+                    "application/vnd.pulsekit+json".to_string(),
+                ),
+                ("x-pulsekit-key".to_string(), "pk_spoofed".to_string()),
+            ]),
+            ..Default::default()
+        });
+
+        client.capture_message("single", Level::Info);
+        client.flush_blocking();
+        client.capture_message("batch 1", Level::Info);
+        client.capture_message("batch 2", Level::Info);
+        client.flush_blocking();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/api/v1/events");
+        assert_eq!(requests[1].path, "/api/v1/events/batch");
+        for request in &requests {
+            assert_eq!(request.header("x-tenant-id"), Some("acme"));
+            assert_eq!(request.header("authorization"), Some("Bearer gw-token"));
+            assert_eq!(
+                request.header("content-type"),
+                Some("application/vnd.pulsekit+json")
+            );
+            assert_eq!(request.header(API_KEY), Some("pk_live"));
+        }
+
+        let invalid = Config {
+            headers: HashMap::from([("X-Tenant-Id".to_string(), "acme\n".to_string())]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            PulseKit::try_new(invalid),
+            Err(PulseKitError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_trigger_size_and_request_size_are_independent() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                batch_size: 50,
+                max_events_per_request: Some(20),
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        for i in 0..49 {
+            client.capture_message(&format!("event {}", i), Level::Info);
+        }
+        assert!(transport.requests().is_empty());
+        client.capture_message("event 49", Level::Info);
+
+        let sizes: Vec<_> = transport
+            .wait_for_requests(3, Duration::from_secs(5))
+            .iter()
+            .map(|request| request.event_count)
+            .collect();
+        assert_eq!(sizes, [20, 20, 10]);
+    }
+
+    #[test]
+    fn test_flush_modes_pick_urgency_by_level() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                batch_size: 100,
+                flush_modes: HashMap::from([
+                    (Level::Fatal, FlushMode::Immediate),
+                    (Level::Error, FlushMode::Within(Duration::from_millis(50))),
+                ]),
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        client.capture_message("cache warmed", Level::Info);
+        client.capture_message("disk almost full", Level::Info);
+        assert!(transport.requests().is_empty());
+
+        client.capture_message("out of memory", Level::Fatal);
+        assert_eq!(transport.requests().len(), 1);
+        assert_eq!(transport.events().len(), 3);
+
+        client.capture_message("charge failed", Level::Error);
+        assert_eq!(transport.requests().len(), 1);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(transport.requests().len(), 2);
+        assert_eq!(transport.events().len(), 4);
+    }
+
+    #[test]
+    fn test_flush_outcome_counts_sent_and_failed_events() {
+        let healthy = MockServer::start();
+        let broken = MockServer::with_handler(|_| MockResponse::status(400));
+        let client = PulseKit::new(Config {
+            endpoint: healthy.url(),
+            routes: vec![Route {
+                prefix: "billing.".to_string(),
+                endpoint: broken.url(),
+            }],
+            batch_size: 100,
+            ..Default::default()
+        });
+
+        for event_type in ["signup", "login", "billing.charge"] {
+            client.capture(Event {
+                event_type: event_type.to_string(),
+                ..Default::default()
+            });
+        }
+        let outcome = client.flush_blocking();
+
+        assert_eq!(outcome.sent, 2);
+        assert_eq!(outcome.failed, 1);
+        assert_eq!(outcome.requeued, 0);
+        assert!(!outcome.is_success());
+        assert!(matches!(
+            outcome.into_result(),
+            Err(PulseKitError::Http { status: 400, .. })
+        ));
+    }
+
+    #[test]
+    fn test_sync_sends_deliver_without_a_flush() {
+        let transport = MemoryTransport::new();
+        let client = Arc::new(PulseKit::with_transport(
+            Config {
+                test_sync_sends: true,
+                batch_size: 100,
+                ..Default::default()
+            },
+            transport.clone(),
+        ));
+
+        client.capture_message("from the test thread", Level::Info);
+        assert_eq!(transport.events().len(), 1);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let task_client = client.clone();
+        runtime
+            .block_on(runtime.spawn(async move {
+                task_client.capture_message("from a task", Level::Info);
+            }))
+            .unwrap();
+
+        let messages: Vec<_> = transport
+            .events()
+            .into_iter()
+            .map(|event| event.message.unwrap())
+            .collect();
+        assert_eq!(messages, ["from the test thread", "from a task"]);
+        assert!(client.delivery.queue.is_empty());
+    }
+
+    #[test]
+    fn test_common_fields_are_sent_once_per_batch() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                hoist_common_fields: true,
+                release: Some("2.4.0".to_string()),
+                batch_size: 100,
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+        client.capture_message("one", Level::Info);
+        client.capture_message("two", Level::Info);
+        client.capture(Event {
+            event_type: "message".to_string(),
+            release: Some("2.5.0-rc1".to_string()),
+            ..Default::default()
+        });
+        client.flush_blocking();
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&transport.requests()[0].body).unwrap();
+        assert_eq!(body["environment"], "production");
+        assert!(body.get("release").is_none());
+        for event in body["events"].as_array().unwrap() {
+            assert!(event.get("environment").is_none());
+            assert!(event.get("release").is_some());
+        }
+
+        let events = transport.take_events();
+        assert!(events
+            .iter()
+            .all(|e| e.environment.as_deref() == Some("production")));
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_bodies_round_trip() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                wire_format: WireFormat::MsgPack,
+                batch_size: 100,
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+        client.capture_message_with_options(
+            "checkout failed",
+            Level::Error,
+            Some(HashMap::from([("region".to_string(), "eu".to_string())])),
+            Some(HashMap::from([(
+                "cart".to_string(),
+                serde_json::json!({"items": [1, 2], "total": 9.5}),
+            )])),
+        );
+        client.capture_message("retrying", Level::Info);
+        client.flush_blocking();
+
+        let requests = transport.requests();
+        assert_eq!(
+            requests[0].header("Content-Type"),
+            Some("application/msgpack")
+        );
+        assert!(serde_json::from_slice::<serde_json::Value>(&requests[0].body).is_err());
+
+        let events = transport.take_events();
+        assert_eq!(events.len(), 2);
+        let failed = &events[0];
+        assert_eq!(failed.message.as_deref(), Some("checkout failed"));
+        assert_eq!(failed.level, Some(Level::Error));
+        assert_eq!(failed.tags.as_ref().unwrap()["region"], "eu");
+        assert_eq!(
+            failed.metadata.as_ref().unwrap()["cart"],
+            serde_json::json!({"items": [1, 2], "total": 9.5})
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_only_bodies_over_the_threshold_are_compressed() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                compression_min_bytes: Some(1024),
+                batch_size: 100,
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        client.capture_message("small", Level::Info);
+        client.flush_blocking();
+        for i in 0..50 {
+            client.capture_message(&format!("large batch event {}", i), Level::Info);
+        }
+        client.flush_blocking();
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].header("Content-Encoding"), None);
+        assert!(serde_json::from_slice::<serde_json::Value>(&requests[0].body).is_ok());
+
+        assert_eq!(requests[1].header("Content-Encoding"), Some("gzip"));
+        let mut json = Vec::new();
+        GzDecoder::new(&requests[1].body[..])
+            .read_to_end(&mut json)
+            .unwrap();
+        assert!(json.len() > 1024 && json.len() > requests[1].body.len());
+        let body: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(body["events"].as_array().unwrap().len(), 50);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_async_flush_sends_gzipped_batches_that_round_trip() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            compression_min_bytes: Some(256),
+            batch_size: 100,
+            ..Default::default()
+        });
+        let sent: Vec<Event> = (0..20)
+            .map(|i| {
+                client.build_enriched(Event {
+                    event_type: "import.row".to_string(),
+                    message: Some(format!("row {} rejected", i)),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        for event in &sent {
+            client.delivery.enqueue(event.clone(), None);
+        }
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(client.flush());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].header("Content-Encoding"), Some("gzip"));
+        let mut json = Vec::new();
+        GzDecoder::new(&requests[0].body[..])
+            .read_to_end(&mut json)
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let received: Vec<Event> = serde_json::from_value(body["events"].clone()).unwrap();
+        let as_json = |events: &[Event]| serde_json::to_value(events).unwrap();
+        assert_eq!(as_json(&received), as_json(&sent));
+    }
+
+    #[test]
+    fn test_exhausted_batches_are_requeued_for_the_next_flush() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let server = MockServer::with_handler({
+            let attempts = attempts.clone();
+            move |_| {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    MockResponse::status(503)
+                } else {
+                    MockResponse::status(200)
+                }
+            }
+        });
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            retry: RetryConfig {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        client.capture_message("survives an outage", Level::Error);
+        let outcome = client.flush_blocking();
+        assert_eq!((outcome.sent, outcome.requeued), (0, 1));
+        assert_eq!(client.delivery.queue.len(), 1);
+
+        let outcome = client.flush_blocking();
+        assert_eq!((outcome.sent, outcome.requeued), (1, 0));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].events()[0]["message"], "survives an outage");
+    }
+
+    #[test]
+    fn test_requeue_respects_the_limit() {
+        let server = MockServer::with_handler(|_| MockResponse::status(503));
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            retry: RetryConfig {
+                max_attempts: 1,
+                requeue_limit: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        client.capture_message("one", Level::Info);
+        client.capture_message("two", Level::Info);
+        let outcome = client.flush_blocking();
+
+        assert_eq!((outcome.failed, outcome.requeued), (2, 0));
+        assert!(client.delivery.queue.is_empty());
+    }
+
+    struct SlowTransport;
+
+    impl Transport for SlowTransport {
+        fn send(&self, _: &TransportRequest) -> Result<TransportResponse, PulseKitError> {
+            thread::sleep(Duration::from_millis(20));
+            Ok(TransportResponse {
+                status: 200,
+                ..Default::default()
+            })
+        }
+    }
+
+    fn fill_queue(client: &PulseKit, count: usize) {
+        let mut queued = client.delivery.queue.take();
+        queued.extend((0..count).map(|i| Queued {
+            event: Event {
+                event_type: "log".to_string(),
+                message: Some(format!("event {}", i)),
+                ..Default::default()
+            },
+            enqueued_at: Instant::now(),
+            ack: None,
+        }));
+        client.delivery.queue.put_back(queued);
+    }
+
+    /// Holds every send until the test releases it.
+    struct GatedTransport {
+        release: Mutex<mpsc::Receiver<()>>,
+        sent: MemoryTransport,
+    }
+
+    impl Transport for GatedTransport {
+        fn send(&self, request: &TransportRequest) -> Result<TransportResponse, PulseKitError> {
+            let _ = self.release.lock().recv_timeout(Duration::from_secs(10));
+            self.sent.send(request)
+        }
+    }
+
+    #[test]
+    fn test_full_batch_is_sent_without_blocking_the_capturing_thread() {
+        let (release, gate) = mpsc::channel();
+        let sent = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                batch_size: 2,
+                ..Default::default()
+            },
+            GatedTransport {
+                release: Mutex::new(gate),
+                sent: sent.clone(),
+            },
+        );
+
+        let started = Instant::now();
+        for i in 0..4 {
+            client.capture_message(&format!("event {}", i), Level::Info);
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(sent.requests().is_empty());
+
+        for _ in 0..4 {
+            release.send(()).unwrap();
+        }
+        drop(client);
+        assert_eq!(sent.events().len(), 4);
+    }
+
+    #[test]
+    fn test_drain_reports_decreasing_remaining_counts() {
+        let client = PulseKit::with_transport(
+            Config {
+                batch_size: 2,
+                ..Default::default()
+            },
+            SlowTransport,
+        );
+        fill_queue(&client, 6);
+
+        let reports = Mutex::new(Vec::new());
+        let drained = client.drain_with_progress(Duration::from_secs(5), |remaining| {
+            reports.lock().push(remaining)
+        });
+
+        assert!(drained);
+        assert_eq!(*reports.lock(), [6, 4, 2, 0]);
+    }
+
+    #[test]
+    fn test_try_flush_sends_only_whole_batches() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                batch_size: 3,
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        client.capture_message("first", Level::Info);
+        client.capture_message("second", Level::Info);
+        assert_eq!(client.queue_len(), 2);
+        assert_eq!(client.try_flush(), TryFlush::Pending(2));
+        assert!(transport.requests().is_empty());
+
+        fill_queue(&client, 5);
+        assert_eq!(client.queue_len(), 7);
+        match client.try_flush() {
+            TryFlush::Flushed(outcome) => assert_eq!(outcome.sent, 6),
+            pending => panic!("expected a flush, got {:?}", pending),
+        }
+        assert_eq!(transport.requests().len(), 2);
+        assert_eq!(client.queue_len(), 1);
+        assert_eq!(
+            client.delivery.take_events()[0].message.as_deref(),
+            Some("event 4")
+        );
+    }
+
+    #[test]
+    fn test_drain_stops_at_the_timeout() {
+        let client = PulseKit::with_transport(
+            Config {
+                batch_size: 1,
+                ..Default::default()
+            },
+            SlowTransport,
+        );
+        fill_queue(&client, 50);
+
+        let drained = client.drain_with_progress(Duration::from_millis(50), |_| {});
+
+        assert!(!drained);
+        assert!(!client.delivery.queue.is_empty());
+    }
+
+    #[test]
+    fn test_flush_surfaces_network_and_status_errors() {
+        let unreachable = PulseKit::new(Config {
+            endpoint: "http://127.0.0.1:1".to_string(),
+            retry: RetryConfig {
+                max_attempts: 1,
+                requeue_limit: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        unreachable.capture_message("smoke test", Level::Info);
+        let err = unreachable.flush_blocking().into_result().unwrap_err();
+        assert!(matches!(err, PulseKitError::Network(_)));
+        assert!(err.is_retryable());
+
+        let server =
+            MockServer::with_handler(|_| MockResponse::status(422).body("missing field `type`"));
+        let rejected = PulseKit::new(Config {
+            endpoint: server.url(),
+            ..Default::default()
+        });
+        rejected.capture_message("smoke test", Level::Info);
+        let outcome = rejected.flush_blocking();
+        assert_eq!((outcome.sent, outcome.failed), (0, 1));
+        assert_eq!(
+            outcome.into_result(),
+            Err(PulseKitError::Http {
+                status: 422,
+                body: "missing field `type`".to_string(),
+            })
+        );
+    }
+
+    fn overflowing_client(policy: QueueOverflow) -> PulseKit {
+        PulseKit::new(Config {
+            batch_size: 100,
+            max_queue_size: 3,
+            queue_overflow: policy,
+            ..Default::default()
+        })
+    }
+
+    fn queued_messages(client: &PulseKit) -> Vec<String> {
+        let queued = client.delivery.queue.take();
+        let messages = queued
+            .iter()
+            .map(|q| q.event.message.clone().unwrap())
+            .collect();
+        client.delivery.queue.put_back(queued);
+        messages
+    }
+
+    #[test]
+    fn test_full_queue_drops_events_by_policy() {
+        for (policy, expected) in [
+            (QueueOverflow::DropOldest, ["2", "3", "4"]),
+            (QueueOverflow::DropNewest, ["0", "1", "4"]),
+            (QueueOverflow::DropEvent, ["0", "1", "2"]),
+        ] {
+            let client = overflowing_client(policy);
+            for i in 0..5 {
+                client.capture_message(&i.to_string(), Level::Info);
+                assert!(client.delivery.queue.len() <= 3);
+            }
+
+            assert_eq!(queued_messages(&client), expected, "{:?}", policy);
+            assert_eq!(client.stats().dropped_queue_full, 2);
+        }
+    }
+
+    #[test]
+    fn test_flush_exposes_the_ids_the_server_assigned() {
+        let server = MockServer::with_handler(|request| match request.path.as_str() {
+            "/api/v1/events" => MockResponse::status(201).body(r#"{"id":"evt_1"}"#),
+            _ => MockResponse::status(200)
+                .body(r#"{"ids":["evt_2"],"rejected":[{"index":1,"reason":"payload too large"}]}"#),
+        });
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            ..Default::default()
+        });
+
+        client.capture_message("single", Level::Info);
+        let outcome = client.flush_blocking();
+        client.capture_message("first", Level::Info);
+        client.capture_message("second", Level::Info);
+        let batch = client.flush_blocking();
+
+        assert_eq!(outcome.event_ids().collect::<Vec<_>>(), ["evt_1"]);
+        assert_eq!(batch.event_ids().collect::<Vec<_>>(), ["evt_2"]);
+        assert_eq!(
+            batch.responses[0].rejected,
+            [RejectedEvent {
+                index: 1,
+                reason: "payload too large".to_string(),
+            }]
+        );
+
+        let garbled = MockServer::with_handler(|_| MockResponse::status(200).body("OK"));
+        let client = PulseKit::new(Config {
+            endpoint: garbled.url(),
+            ..Default::default()
+        });
+        client.capture_message("accepted?", Level::Info);
+        let outcome = client.flush_blocking();
+        assert_eq!(outcome.failed, 1);
+        assert!(matches!(
+            outcome.first_error,
+            Some(PulseKitError::MalformedResponse(_))
+        ));
+        assert_eq!(garbled.requests().len(), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_flush_with_timeout_requeues_what_it_could_not_send() {
+        let slow = Arc::new(AtomicBool::new(true));
+        let server = {
+            let slow = slow.clone();
+            MockServer::with_handler(move |_| {
+                if slow.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(500));
+                }
+                MockResponse::status(200)
+            })
+        };
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            ..Default::default()
+        });
+        client.capture_message("first", Level::Info);
+        client.capture_message("second", Level::Info);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let started = Instant::now();
+        let result = runtime.block_on(client.flush_with_timeout(Duration::from_millis(100)));
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert_eq!(
+            result,
+            Err(FlushError::Timeout {
+                outcome: FlushOutcome::default()
+            })
+        );
+        assert_eq!(client.delivery.queue.len(), 2);
+
+        slow.store(false, Ordering::SeqCst);
+        let outcome = runtime
+            .block_on(client.flush_with_timeout(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(outcome.sent, 2);
+        assert!(client.delivery.queue.is_empty());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_flush_with_timeout_fails_over_to_the_next_endpoint() {
+        let primary = MockServer::with_handler(|_| MockResponse::status(503));
+        let secondary = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: primary.url(),
+            failover_endpoints: vec![secondary.url()],
+            retry: retry_config(false),
+            ..Default::default()
+        });
+        client.capture_message("first", Level::Info);
+        client.capture_message("second", Level::Info);
+
+        let outcome = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(client.flush_with_timeout(Duration::from_secs(5)))
+            .unwrap();
+
+        assert_eq!(outcome.sent, 2);
+        assert_eq!(primary.requests().len(), 3);
+        assert_eq!(secondary.requests().len(), 1);
+        assert_eq!(secondary.requests()[0].events().len(), 2);
+    }
+
+    /// Counts how many async sends overlap; rejects the batch holding
+    /// "event 0".
+    #[cfg(feature = "async")]
+    #[derive(Clone, Default)]
+    struct CountingTransport {
+        in_flight: Arc<AtomicUsize>,
+        most_in_flight: Arc<AtomicUsize>,
+    }
+
+    #[cfg(feature = "async")]
+    impl Transport for CountingTransport {
+        fn send(&self, _: &TransportRequest) -> Result<TransportResponse, PulseKitError> {
+            unreachable!("only async sends are expected")
+        }
+
+        fn send_async<'a>(
+            &'a self,
+            request: &'a TransportRequest,
+        ) -> crate::transport::TransportFuture<'a> {
+            Box::pin(async move {
+                let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.most_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                let body = String::from_utf8_lossy(&request.body);
+                let status = if body.contains("\"event 0\"") {
+                    400
+                } else {
+                    200
+                };
+                Ok(TransportResponse {
+                    status,
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async_flush_caps_concurrent_sends() {
+        let transport = CountingTransport::default();
+        let client = PulseKit::with_transport(
+            Config {
+                batch_size: 1000,
+                max_events_per_request: Some(2),
+                max_concurrent_sends: 4,
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+        fill_queue(&client, 40);
+
+        let outcome = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(client.flush());
+
+        assert_eq!(transport.most_in_flight.load(Ordering::SeqCst), 4);
+        assert_eq!((outcome.sent, outcome.failed), (38, 2));
+        assert_eq!(outcome.responses.len(), 19);
+        assert!(client.delivery.queue.is_empty());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_flush_with_timeout_caps_concurrent_sends() {
+        let transport = CountingTransport::default();
+        let client = PulseKit::with_transport(
+            Config {
+                batch_size: 1000,
+                max_events_per_request: Some(2),
+                max_concurrent_sends: 4,
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+        fill_queue(&client, 40);
+
+        let outcome = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(client.flush_with_timeout(Duration::from_secs(5)))
+            .unwrap();
+
+        assert_eq!(transport.most_in_flight.load(Ordering::SeqCst), 4);
+        assert_eq!((outcome.sent, outcome.failed), (38, 2));
+        assert!(client.delivery.queue.is_empty());
+    }
+}
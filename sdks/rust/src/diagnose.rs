@@ -0,0 +1,311 @@
+//! A step-by-step connectivity self-test for troubleshooting delivery.
+
+use crate::delivery::join_url;
+use crate::time::{Duration, Instant};
+use crate::timestamp::server_skew_ms;
+use crate::transport::TransportRequest;
+use crate::{PulseKit, PulseKitError};
+use std::fmt;
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// How long `diagnose` waits for a TCP connection when `timeout` is off.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome and duration of one `diagnose` step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticCheck {
+    pub duration: Duration,
+    /// Why the step failed, or `None` if it passed
+    pub error: Option<String>,
+}
+
+impl DiagnosticCheck {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn run<T>(step: impl FnOnce() -> Result<T, String>) -> (Self, Option<T>) {
+        let started = Instant::now();
+        let result = step();
+        let duration = started.elapsed();
+        match result {
+            Ok(value) => (
+                Self {
+                    duration,
+                    error: None,
+                },
+                Some(value),
+            ),
+            Err(error) => (
+                Self {
+                    duration,
+                    error: Some(error),
+                },
+                None,
+            ),
+        }
+    }
+}
+
+/// What [`PulseKit::diagnose`] found. A step is `None` when an earlier one
+/// failed and it could not run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticReport {
+    pub endpoint: String,
+    /// Parsing the endpoint and resolving its host
+    pub dns: Option<DiagnosticCheck>,
+    /// Opening a TCP connection to the first resolved address
+    pub connect: Option<DiagnosticCheck>,
+    /// Sending an empty batch through the client's transport; for `https`
+    /// endpoints this includes the TLS handshake
+    pub request: Option<DiagnosticCheck>,
+    /// Whether the server accepted the API key, judged from the response
+    /// to `request` (so it has the same duration)
+    pub auth: Option<DiagnosticCheck>,
+    /// How far the server's clock is ahead of this host's, from the
+    /// response's `Date` header
+    pub clock_skew_ms: Option<i64>,
+}
+
+impl DiagnosticReport {
+    /// Whether every step ran and passed.
+    pub fn is_ok(&self) -> bool {
+        [&self.dns, &self.connect, &self.request, &self.auth]
+            .iter()
+            .all(|check| check.as_ref().is_some_and(DiagnosticCheck::is_ok))
+    }
+}
+
+impl fmt::Display for DiagnosticReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "PulseKit diagnostics for {}", self.endpoint)?;
+        let steps = [
+            ("dns", &self.dns),
+            ("connect", &self.connect),
+            ("request", &self.request),
+            ("auth", &self.auth),
+        ];
+        for (name, check) in steps {
+            match check {
+                Some(DiagnosticCheck {
+                    duration,
+                    error: None,
+                }) => writeln!(f, "  {:<8} ok ({:?})", name, duration)?,
+                Some(DiagnosticCheck {
+                    duration,
+                    error: Some(error),
+                }) => writeln!(f, "  {:<8} FAILED after {:?}: {}", name, duration, error)?,
+                None => writeln!(f, "  {:<8} skipped", name)?,
+            }
+        }
+        match self.clock_skew_ms {
+            Some(skew) => write!(f, "  clock skew: {}ms", skew),
+            None => write!(f, "  clock skew: unknown"),
+        }
+    }
+}
+
+impl PulseKit {
+    /// DiagnosticCheck each step of delivering to `endpoint` in turn: DNS, a TCP
+    /// connection, a request through the transport (sending an empty batch,
+    /// so nothing is stored), the API key, and the server's clock. Blocks
+    /// the calling thread, so call it from outside async code.
+    ///
+    /// ```no_run
+    /// # let client = pulsekit::PulseKit::new(Default::default());
+    /// let report = client.diagnose();
+    /// if !report.is_ok() {
+    ///     eprintln!("{}", report);
+    /// }
+    /// ```
+    pub fn diagnose(&self) -> DiagnosticReport {
+        let endpoint = self.config.endpoint.clone();
+        let mut report = DiagnosticReport {
+            endpoint: endpoint.clone(),
+            dns: None,
+            connect: None,
+            request: None,
+            auth: None,
+            clock_skew_ms: None,
+        };
+
+        let (dns, address) = DiagnosticCheck::run(|| {
+            let url = reqwest::Url::parse(&endpoint).map_err(|e| e.to_string())?;
+            let host = url.host_str().ok_or("endpoint has no host")?;
+            let port = url.port_or_known_default().ok_or("endpoint has no port")?;
+            (host, port)
+                .to_socket_addrs()
+                .map_err(|e| e.to_string())?
+                .next()
+                .ok_or_else(|| format!("{} did not resolve to any address", host))
+        });
+        report.dns = Some(dns);
+        let Some(address) = address else {
+            return report;
+        };
+
+        let timeout = self
+            .config
+            .timeout
+            .filter(|timeout| !timeout.is_zero())
+            .unwrap_or(CONNECT_TIMEOUT);
+        let (connect, _) = DiagnosticCheck::run(|| {
+            TcpStream::connect_timeout(&address, timeout).map_err(|e| e.to_string())
+        });
+        let connected = connect.is_ok();
+        report.connect = Some(connect);
+        if !connected {
+            return report;
+        }
+
+        let request = self.probe_request();
+        let (check, response) = DiagnosticCheck::run(|| {
+            self.delivery
+                .transport
+                .send(&request)
+                .map_err(|e| e.to_string())
+        });
+        let duration = check.duration;
+        report.request = Some(check);
+        let Some(response) = response else {
+            return report;
+        };
+
+        report.clock_skew_ms = server_skew_ms(&response, self.delivery.clock.now());
+        let error = match response.status {
+            200..=299 => None,
+            401 | 403 => Some(format!(
+                "the server rejected the API key: {}",
+                PulseKitError::Http {
+                    status: response.status,
+                    body: String::from_utf8_lossy(&response.body).into_owned(),
+                }
+            )),
+            status => Some(format!(
+                "could not verify the API key, server returned status {}",
+                status
+            )),
+        };
+        report.auth = Some(DiagnosticCheck { duration, error });
+        report
+    }
+
+    /// Check that the endpoint is reachable and accepts the API key, to
+    /// fail fast on a bad configuration at startup. It sends an empty
+    /// batch, so nothing is stored.
+    ///
+    /// A `401` or `403` gives [`PulseKitError::Unauthorized`], any other
+    /// non-2xx status [`PulseKitError::Http`], and a failed connection
+    /// [`PulseKitError::Network`].
+    ///
+    /// ```no_run
+    /// # async fn run(client: pulsekit::PulseKit) {
+    /// if let Err(err) = client.ping().await {
+    ///     panic!("PulseKit is misconfigured: {}", err);
+    /// }
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    pub async fn ping(&self) -> Result<(), PulseKitError> {
+        let request = self.probe_request();
+        let response = self.delivery.transport.send_async(&request).await?;
+        match response.status {
+            200..=299 => Ok(()),
+            401 | 403 => Err(PulseKitError::Unauthorized(response.status)),
+            status => Err(PulseKitError::Http {
+                status,
+                body: String::from_utf8_lossy(&response.body).into_owned(),
+            }),
+        }
+    }
+
+    /// An authenticated request for an empty batch, which the server
+    /// accepts without storing anything.
+    fn probe_request(&self) -> TransportRequest {
+        let mut headers = vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("X-PulseKit-Key".to_string(), self.config.api_key.clone()),
+        ];
+        for (name, value) in &self.config.headers {
+            if name.eq_ignore_ascii_case("X-PulseKit-Key") {
+                continue;
+            }
+            headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+            headers.push((name.clone(), value.clone()));
+        }
+        TransportRequest {
+            url: join_url(&self.config.endpoint, &self.config.batch_path),
+            headers,
+            body: br#"{"events":[]}"#.to_vec(),
+            event_count: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use crate::Config;
+
+    fn client_for(server: &MockServer) -> PulseKit {
+        PulseKit::new(Config {
+            endpoint: server.url(),
+            api_key: "pk_test".to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_diagnose_reports_success_and_auth_failure() {
+        let healthy = MockServer::with_handler(|_| {
+            MockResponse::status(200).header("Date", &chrono::Utc::now().to_rfc2822())
+        });
+        let report = client_for(&healthy).diagnose();
+        assert!(report.is_ok(), "{}", report);
+        assert!(report.clock_skew_ms.unwrap().abs() < 2_000);
+        let requests = healthy.requests();
+        assert_eq!(requests[0].path, "/api/v1/events/batch");
+        assert_eq!(requests[0].header("X-PulseKit-Key"), Some("pk_test"));
+        assert!(requests[0].events().is_empty());
+
+        let rejecting = MockServer::with_handler(|_| MockResponse::status(401));
+        let report = client_for(&rejecting).diagnose();
+        assert!(!report.is_ok());
+        assert!(report.request.as_ref().unwrap().is_ok());
+        let auth = report.auth.unwrap();
+        assert!(auth.error.unwrap().contains("401"));
+
+        let unresolvable = PulseKit::new(Config {
+            endpoint: "not a url".to_string(),
+            ..Default::default()
+        })
+        .diagnose();
+        assert!(!unresolvable.dns.unwrap().is_ok());
+        assert!(unresolvable.connect.is_none());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_ping_maps_the_response_to_an_error() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let healthy = MockServer::with_handler(|_| MockResponse::status(202));
+        assert_eq!(runtime.block_on(client_for(&healthy).ping()), Ok(()));
+        assert_eq!(healthy.requests()[0].path, "/api/v1/events/batch");
+
+        let rejecting = MockServer::with_handler(|_| MockResponse::status(401));
+        assert_eq!(
+            runtime.block_on(client_for(&rejecting).ping()),
+            Err(PulseKitError::Unauthorized(401))
+        );
+
+        let unreachable = PulseKit::new(Config {
+            endpoint: "http://127.0.0.1:1".to_string(),
+            ..Default::default()
+        });
+        assert!(matches!(
+            runtime.block_on(unreachable.ping()),
+            Err(PulseKitError::Network(_))
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+//! Reporting of `miette` diagnostics.
+
+use crate::{Event, Level, Mechanism, PulseKit};
+use miette::{Diagnostic, Severity};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+impl PulseKit {
+    /// Capture a `miette` diagnostic as an error event.
+    ///
+    /// The severity sets the level (`Advice` is `Info`, `Warning` is
+    /// `Warning`, `Error` or none is `Error`) and the code is sent as the
+    /// `miette.code` tag. The help text, URL, labeled spans and related
+    /// diagnostics go into metadata; a span's `line`, `column` (both
+    /// 1-based) and `text` are included when the diagnostic has source code.
+    pub fn capture_miette(&self, diagnostic: &dyn Diagnostic) {
+        self.capture(miette_event(diagnostic));
+    }
+}
+
+fn miette_event(diagnostic: &dyn Diagnostic) -> Event {
+    let level = match diagnostic.severity() {
+        Some(Severity::Advice) => Level::Info,
+        Some(Severity::Warning) => Level::Warning,
+        Some(Severity::Error) | None => Level::Error,
+    };
+
+    let mut metadata = HashMap::new();
+    if let Some(help) = diagnostic.help() {
+        metadata.insert("help".to_string(), Value::from(help.to_string()));
+    }
+    if let Some(url) = diagnostic.url() {
+        metadata.insert("url".to_string(), Value::from(url.to_string()));
+    }
+    let labels: Vec<Value> = diagnostic
+        .labels()
+        .into_iter()
+        .flatten()
+        .map(|label| {
+            let mut span = json!({
+                "label": label.label(),
+                "offset": label.offset(),
+                "length": label.len(),
+            });
+            let contents = diagnostic
+                .source_code()
+                .and_then(|source| source.read_span(label.inner(), 0, 0).ok());
+            if let Some(contents) = contents {
+                span["line"] = json!(contents.line() + 1);
+                span["column"] = json!(contents.column() + 1);
+                span["text"] = json!(String::from_utf8_lossy(contents.data()));
+            }
+            span
+        })
+        .collect();
+    if !labels.is_empty() {
+        metadata.insert("labels".to_string(), Value::Array(labels));
+    }
+    let related: Vec<Value> = diagnostic
+        .related()
+        .into_iter()
+        .flatten()
+        .map(|related| Value::from(related.to_string()))
+        .collect();
+    if !related.is_empty() {
+        metadata.insert("related".to_string(), Value::Array(related));
+    }
+
+    Event {
+        event_type: "error".to_string(),
+        level: Some(level),
+        message: Some(diagnostic.to_string()),
+        tags: diagnostic
+            .code()
+            .map(|code| HashMap::from([("miette.code".to_string(), code.to_string())])),
+        metadata: (!metadata.is_empty()).then_some(metadata),
+        mechanism: Some(Mechanism::generic()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use miette::{LabeledSpan, SourceCode};
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct UnknownKey {
+        source: String,
+    }
+
+    impl fmt::Display for UnknownKey {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "unknown key `colour` in config")
+        }
+    }
+
+    impl std::error::Error for UnknownKey {}
+
+    impl Diagnostic for UnknownKey {
+        fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+            Some(Box::new("config::unknown_key"))
+        }
+
+        fn severity(&self) -> Option<Severity> {
+            Some(Severity::Warning)
+        }
+
+        fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+            Some(Box::new("did you mean `color`?"))
+        }
+
+        fn source_code(&self) -> Option<&dyn SourceCode> {
+            Some(&self.source)
+        }
+
+        fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+            let offset = self.source.find("colour").unwrap();
+            Some(Box::new(std::iter::once(LabeledSpan::at(
+                offset..offset + 6,
+                "not a known key",
+            ))))
+        }
+    }
+
+    #[test]
+    fn test_code_help_and_spans_are_captured() {
+        let client = PulseKit::new(Config::default());
+        client.capture_miette(&UnknownKey {
+            source: "[theme]\nname = \"dark\"\ncolour = \"blue\"\n".to_string(),
+        });
+
+        let event = client.delivery.take_events().pop().unwrap();
+        assert_eq!(event.level, Some(Level::Warning));
+        assert_eq!(
+            event.message.as_deref(),
+            Some("unknown key `colour` in config")
+        );
+        assert_eq!(event.tags.unwrap()["miette.code"], "config::unknown_key");
+        let metadata = event.metadata.unwrap();
+        assert_eq!(metadata["help"], "did you mean `color`?");
+        assert_eq!(
+            metadata["labels"],
+            json!([{
+                "label": "not a known key",
+                "offset": 22,
+                "length": 6,
+                "line": 3,
+                "column": 1,
+                "text": "colour",
+            }])
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Error types surfaced by the delivery path.
+
+use std::fmt;
+use std::time::Duration;
+
+/// An error that prevented events from being delivered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PulseKitError {
+    /// The request could not be built (e.g. the endpoint is not a valid URL)
+    InvalidRequest(String),
+    /// The server could not be reached or the connection failed
+    Network(String),
+    /// Events could not be serialized into a request body
+    Serialization(String),
+    /// The server answered with a non-2xx status
+    Http { status: u16, body: String },
+    /// The event was dropped before it was queued
+    Dropped,
+    /// The server accepted the request but its response body could not be
+    /// parsed
+    MalformedResponse(String),
+    /// Not sent because a `429` response asked to wait this much longer
+    RateLimited(Duration),
+    /// The server rejected the API key with this `401` or `403` status
+    Unauthorized(u16),
+}
+
+impl PulseKitError {
+    /// Whether retrying the same request may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            PulseKitError::InvalidRequest(_) => false,
+            PulseKitError::Network(_) => true,
+            PulseKitError::Serialization(_) => false,
+            PulseKitError::Http { status, .. } => *status == 429 || *status >= 500,
+            PulseKitError::Dropped => false,
+            PulseKitError::MalformedResponse(_) => false,
+            PulseKitError::RateLimited(_) => true,
+            PulseKitError::Unauthorized(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for PulseKitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PulseKitError::InvalidRequest(message) => write!(f, "invalid request: {}", message),
+            PulseKitError::Network(message) => write!(f, "network error: {}", message),
+            PulseKitError::Serialization(message) => {
+                write!(f, "failed to serialize events: {}", message)
+            }
+            PulseKitError::Http { status, body } if body.is_empty() => {
+                write!(f, "server returned status {}", status)
+            }
+            PulseKitError::Http { status, body } => {
+                write!(f, "server returned status {}: {}", status, body)
+            }
+            PulseKitError::Dropped => write!(f, "event was dropped before sending"),
+            PulseKitError::MalformedResponse(message) => {
+                write!(f, "malformed server response: {}", message)
+            }
+            PulseKitError::RateLimited(remaining) => {
+                write!(f, "rate limited by the server for another {:?}", remaining)
+            }
+            PulseKitError::Unauthorized(status) => {
+                write!(f, "the server rejected the API key with status {}", status)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PulseKitError {}
+
+/// Why [`PulseKit::try_capture`](crate::PulseKit::try_capture) did not queue
+/// an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureError {
+    /// The queue already holds `max_queue_size` events
+    QueueFull,
+    /// The event was dropped by the pipeline (a filter, sampling,
+    /// `before_send`, deduplication or the event budget)
+    Dropped,
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::QueueFull => write!(f, "the event queue is full"),
+            CaptureError::Dropped => write!(f, "the event was dropped before queueing"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl From<serde_json::Error> for PulseKitError {
+    fn from(err: serde_json::Error) -> Self {
+        PulseKitError::Serialization(err.to_string())
+    }
+}
+
+impl From<reqwest::Error> for PulseKitError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_builder() {
+            PulseKitError::InvalidRequest(err.to_string())
+        } else {
+            PulseKitError::Network(err.to_string())
+        }
+    }
+}
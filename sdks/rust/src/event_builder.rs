@@ -0,0 +1,158 @@
+//! Chainable construction of an `Event` with optional fields.
+
+use crate::{Event, Level};
+use std::collections::HashMap;
+
+/// Builds an [`Event`]. The `_if` and `_opt` setters attach a field only
+/// when the condition holds or the value is present, so optional context
+/// doesn't need `if let` blocks around the construction.
+///
+/// ```
+/// # let (user_id, retry_count) = (Some("u_42"), None::<u32>);
+/// let event = pulsekit::Event::builder("checkout.failed")
+///     .level(pulsekit::Level::Error)
+///     .message("card declined")
+///     .tag_if(cfg!(debug_assertions), "build", "debug")
+///     .tag_opt("user_id", user_id)
+///     .metadata_opt("retry_count", retry_count)
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EventBuilder {
+    event: Event,
+}
+
+impl EventBuilder {
+    pub fn new(event_type: impl Into<String>) -> Self {
+        Self {
+            event: Event {
+                event_type: event_type.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn level(mut self, level: Level) -> Self {
+        self.event.level = Some(level);
+        self
+    }
+
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.event.message = Some(message.into());
+        self
+    }
+
+    pub fn fingerprint(mut self, fingerprint: impl Into<String>) -> Self {
+        self.event.fingerprint = Some(fingerprint.into());
+        self
+    }
+
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.event
+            .tags
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Add the tag only if `condition` is true.
+    pub fn tag_if(self, condition: bool, key: impl Into<String>, value: impl Into<String>) -> Self {
+        if condition {
+            self.tag(key, value)
+        } else {
+            self
+        }
+    }
+
+    /// Add the tag only if `value` is `Some`.
+    pub fn tag_opt(self, key: impl Into<String>, value: Option<impl Into<String>>) -> Self {
+        match value {
+            Some(value) => self.tag(key, value),
+            None => self,
+        }
+    }
+
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.event
+            .metadata
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Add the metadata only if `condition` is true.
+    pub fn metadata_if(
+        self,
+        condition: bool,
+        key: impl Into<String>,
+        value: impl Into<serde_json::Value>,
+    ) -> Self {
+        if condition {
+            self.metadata(key, value)
+        } else {
+            self
+        }
+    }
+
+    /// Add the metadata only if `value` is `Some`.
+    pub fn metadata_opt(
+        self,
+        key: impl Into<String>,
+        value: Option<impl Into<serde_json::Value>>,
+    ) -> Self {
+        match value {
+            Some(value) => self.metadata(key, value),
+            None => self,
+        }
+    }
+
+    pub fn build(self) -> Event {
+        self.event
+    }
+}
+
+impl Event {
+    /// Start building an event of type `event_type`.
+    pub fn builder(event_type: impl Into<String>) -> EventBuilder {
+        EventBuilder::new(event_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_conditional_fields_are_attached_only_when_present() {
+        let order_id: Option<&str> = Some("ord_7");
+        let coupon: Option<&str> = None;
+        let event = Event::builder("checkout")
+            .level(Level::Warning)
+            .tag_if(true, "retried", "yes")
+            .tag_if(false, "first_try", "yes")
+            .tag_opt("order_id", order_id)
+            .tag_opt("coupon", coupon)
+            .metadata_opt("items", Some(3))
+            .metadata_opt("discount", None::<f64>)
+            .metadata_if(false, "debug", "on")
+            .build();
+
+        assert_eq!(event.event_type, "checkout");
+        assert_eq!(event.level, Some(Level::Warning));
+        let tags = event.tags.unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags["retried"], "yes");
+        assert_eq!(tags["order_id"], "ord_7");
+        assert_eq!(
+            event.metadata.unwrap(),
+            HashMap::from([("items".to_string(), json!(3))])
+        );
+
+        let bare = Event::builder("checkout")
+            .tag_opt("coupon", coupon)
+            .metadata_if(false, "debug", "on")
+            .build();
+        assert!(bare.tags.is_none() && bare.metadata.is_none());
+    }
+}
@@ -0,0 +1,191 @@
+//! Extension traits for reporting errors inline.
+
+use crate::PulseKit;
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Capture the error of a `Result` without changing it, so reporting an
+/// error path takes one call and leaves the control flow alone.
+///
+/// ```no_run
+/// use pulsekit::{CaptureResultExt, PulseKit};
+///
+/// fn load(client: &PulseKit) -> std::io::Result<String> {
+///     std::fs::read_to_string("settings.toml").capture_exception(client)
+/// }
+/// ```
+pub trait CaptureResultExt<T, E>: Sized {
+    /// Capture an `Err` as an error event with the error's `Display`
+    /// output as the message, then return the result unchanged.
+    fn capture_err(self, client: &PulseKit) -> Self;
+
+    /// Capture an `Err` like [`PulseKit::capture_exception`], with its
+    /// type and chain of causes, then return the result unchanged.
+    fn capture_exception(self, client: &PulseKit) -> Self
+    where
+        E: std::error::Error;
+}
+
+impl<T, E: Display> CaptureResultExt<T, E> for Result<T, E> {
+    fn capture_err(self, client: &PulseKit) -> Self {
+        if let Err(err) = &self {
+            client.capture_error(&err.to_string());
+        }
+        self
+    }
+
+    fn capture_exception(self, client: &PulseKit) -> Self
+    where
+        E: std::error::Error,
+    {
+        if let Err(err) = &self {
+            client.capture_exception(err);
+        }
+        self
+    }
+}
+
+/// Capture the error of a fallible future without changing its result.
+///
+/// ```no_run
+/// use pulsekit::{CaptureFutureExt, Config, PulseKit};
+///
+/// async fn fetch() -> Result<String, std::io::Error> {
+///     Ok(String::new())
+/// }
+///
+/// async fn run(client: &PulseKit) -> Result<(), std::io::Error> {
+///     let body = fetch().capture_on_err(client).await?;
+///     println!("{body}");
+///     Ok(())
+/// }
+/// ```
+pub trait CaptureFutureExt<T, E>: Future<Output = Result<T, E>> + Sized {
+    /// Wrap the future so an `Err` output is captured as an error event
+    /// before being passed through unchanged.
+    fn capture_on_err(self, client: &PulseKit) -> CaptureOnErr<'_, Self>;
+}
+
+impl<F, T, E> CaptureFutureExt<T, E> for F
+where
+    F: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    fn capture_on_err(self, client: &PulseKit) -> CaptureOnErr<'_, Self> {
+        CaptureOnErr {
+            future: Box::pin(self),
+            client,
+        }
+    }
+}
+
+/// Future returned by [`CaptureFutureExt::capture_on_err`].
+pub struct CaptureOnErr<'a, F> {
+    future: Pin<Box<F>>,
+    client: &'a PulseKit,
+}
+
+impl<F, T, E> Future for CaptureOnErr<'_, F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Display,
+{
+    type Output = Result<T, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = match self.future.as_mut().poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        if let Err(err) = &result {
+            self.client.capture_error(&err.to_string());
+        }
+
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level};
+
+    #[test]
+    fn test_capture_on_err_captures_and_propagates() {
+        let client = PulseKit::new(Config::default());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let result: Result<(), String> = runtime.block_on(async {
+            async { Err::<(), _>("upstream timed out".to_string()) }
+                .capture_on_err(&client)
+                .await
+        });
+
+        assert_eq!(result, Err("upstream timed out".to_string()));
+        let queue = client.delivery.take_events();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].level, Some(Level::Error));
+        assert_eq!(queue[0].message.as_deref(), Some("upstream timed out"));
+    }
+
+    #[test]
+    fn test_capture_on_err_ok_captures_nothing() {
+        let client = PulseKit::new(Config::default());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let result =
+            runtime.block_on(async { async { Ok::<_, String>(7) }.capture_on_err(&client).await });
+
+        assert_eq!(result, Ok(7));
+        assert!(client.delivery.queue.is_empty());
+    }
+
+    #[test]
+    fn test_capture_err_forwards_the_message_and_keeps_the_result() {
+        let client = PulseKit::new(Config::default());
+
+        let ok: Result<u8, String> = Ok(3);
+        assert_eq!(ok.capture_err(&client), Ok(3));
+        assert!(client.delivery.queue.is_empty());
+
+        let err: Result<u8, String> = Err("quota exceeded: 120%".to_string());
+        assert_eq!(
+            err.capture_err(&client),
+            Err("quota exceeded: 120%".to_string())
+        );
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].message.as_deref(), Some("quota exceeded: 120%"));
+    }
+
+    #[test]
+    fn test_capture_exception_keeps_the_source_chain() {
+        #[derive(Debug)]
+        struct ConfigError(std::io::Error);
+
+        impl Display for ConfigError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "could not load config")
+            }
+        }
+
+        impl std::error::Error for ConfigError {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let client = PulseKit::new(Config::default());
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "settings.toml missing");
+        let result: Result<(), _> = Err(ConfigError(io)).capture_exception(&client);
+        assert!(result.is_err());
+
+        let events = client.delivery.take_events();
+        assert_eq!(events[0].message.as_deref(), Some("could not load config"));
+        let causes = &events[0].metadata.as_ref().unwrap()["causes"];
+        assert_eq!(causes[0]["message"], "settings.toml missing");
+    }
+}
@@ -0,0 +1,120 @@
+//! The fingerprint events are grouped by when the caller doesn't set one.
+
+use crate::stacktrace::{is_in_app, stable_hash};
+use crate::Event;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Frames whose function names go into the fingerprint.
+const FINGERPRINT_FRAMES: usize = 5;
+
+/// Numbers, hex values and addresses in messages, which vary between
+/// occurrences of the same error.
+fn variable_parts() -> &'static Regex {
+    static VARIABLE_PARTS: OnceLock<Regex> = OnceLock::new();
+    VARIABLE_PARTS.get_or_init(|| {
+        Regex::new(r"(?i)\b0x[0-9a-f]+\b|\b[0-9a-f]*\d[0-9a-f]*\b|\d+").expect("valid regex")
+    })
+}
+
+/// The symbol hash rustc appends to function names, e.g. `::h1a2b3c4d5e6f7a8b`.
+fn symbol_hash() -> &'static Regex {
+    static SYMBOL_HASH: OnceLock<Regex> = OnceLock::new();
+    SYMBOL_HASH.get_or_init(|| Regex::new(r"::h[0-9a-f]{16}$").expect("valid regex"))
+}
+
+/// A fingerprint for `event` from its type, its message with numbers and
+/// hex values replaced, and the function names of its innermost application
+/// frames (all frames if none are application code), as 16 hex digits.
+///
+/// File names and line numbers are left out, so the same error from the
+/// same code path gets the same fingerprint across builds, runs and hosts.
+/// Used for events captured without a fingerprint unless `fingerprint_fn`
+/// is set.
+pub fn default_fingerprint(event: &Event) -> String {
+    let message = event
+        .message
+        .as_deref()
+        .map(|message| variable_parts().replace_all(message, "<n>").into_owned())
+        .unwrap_or_default();
+
+    let frames = event.stacktrace.as_deref().unwrap_or_default();
+    let in_app = frames.iter().any(is_in_app);
+    let functions = frames
+        .iter()
+        .filter(|frame| !in_app || is_in_app(frame))
+        .filter_map(|frame| frame.function.as_deref())
+        .map(|function| symbol_hash().replace(function, "").into_owned())
+        .take(FINGERPRINT_FRAMES);
+
+    stable_hash(
+        [event.event_type.clone(), message]
+            .into_iter()
+            .chain(functions),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, PulseKit, StackFrame};
+
+    fn frame(function: &str, line: u32) -> StackFrame {
+        StackFrame {
+            file: Some(format!("src/build-{}/orders.rs", line)),
+            line: Some(line),
+            function: Some(function.to_string()),
+        }
+    }
+
+    fn error(message: &str, stacktrace: Vec<StackFrame>) -> Event {
+        Event {
+            event_type: "error".to_string(),
+            message: Some(message.to_string()),
+            stacktrace: Some(stacktrace),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_fingerprint_ignores_variable_parts() {
+        let first = error(
+            "order 1042 not found at 0x7ffd5a3c",
+            vec![
+                frame("std::panicking::begin_panic", 10),
+                frame("shop::orders::load::h0123456789abcdef", 42),
+                frame("shop::main", 7),
+            ],
+        );
+        let second = error(
+            "order 77 not found at 0x55e0c1d8",
+            vec![
+                frame("std::panicking::begin_panic", 12),
+                frame("shop::orders::load::hfedcba9876543210", 45),
+                frame("shop::main", 9),
+            ],
+        );
+        let other_path = error(
+            "order 77 not found at 0x55e0c1d8",
+            vec![frame("shop::orders::refund", 45), frame("shop::main", 9)],
+        );
+
+        assert_eq!(default_fingerprint(&first), default_fingerprint(&second));
+        assert_ne!(
+            default_fingerprint(&first),
+            default_fingerprint(&other_path)
+        );
+        assert_ne!(
+            default_fingerprint(&first),
+            default_fingerprint(&error("order not found", Vec::new()))
+        );
+        // Pinned so a change to the algorithm, which regroups every
+        // existing issue, is deliberate.
+        assert_eq!(default_fingerprint(&first), "9fc01a9d07364d85");
+
+        let client = PulseKit::new(Config::default());
+        client.capture(first.clone());
+        let event = client.delivery.take_events().pop().unwrap();
+        assert_eq!(event.fingerprint, Some(default_fingerprint(&first)));
+    }
+}
@@ -0,0 +1,106 @@
+//! Flattening of nested metadata into dotted keys.
+
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Whether and how `capture` flattens nested metadata objects into dotted
+/// keys, e.g. `{"user": {"id": 7}}` into `{"user.id": 7}`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FlattenMetadata {
+    /// Send metadata as captured.
+    #[default]
+    Off,
+    /// Flatten nested objects; arrays are kept as values.
+    KeepArrays,
+    /// Flatten nested objects and arrays, indexing array elements
+    /// (`items.0.id`).
+    IndexArrays,
+}
+
+pub(crate) fn flatten(
+    metadata: HashMap<String, Value>,
+    mode: FlattenMetadata,
+) -> HashMap<String, Value> {
+    if mode == FlattenMetadata::Off {
+        return metadata;
+    }
+    let mut flat = HashMap::new();
+    for (key, value) in metadata {
+        flatten_into(&mut flat, key, value, mode);
+    }
+    flat
+}
+
+fn flatten_into(
+    flat: &mut HashMap<String, Value>,
+    key: String,
+    value: Value,
+    mode: FlattenMetadata,
+) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (child, value) in map {
+                flatten_into(flat, format!("{}.{}", key, child), value, mode);
+            }
+        }
+        Value::Array(items) if mode == FlattenMetadata::IndexArrays && !items.is_empty() => {
+            for (index, value) in items.into_iter().enumerate() {
+                flatten_into(flat, format!("{}.{}", key, index), value, mode);
+            }
+        }
+        value => {
+            flat.insert(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level, PulseKit};
+    use serde_json::json;
+
+    fn nested() -> HashMap<String, Value> {
+        HashMap::from([
+            (
+                "user".to_string(),
+                json!({"id": 7, "address": {"city": "Oslo"}}),
+            ),
+            ("items".to_string(), json!([{"id": 1}, {"id": 2}])),
+        ])
+    }
+
+    #[test]
+    fn test_arrays_are_kept_or_indexed() {
+        let flat = flatten(nested(), FlattenMetadata::KeepArrays);
+        assert_eq!(flat["user.address.city"], "Oslo");
+        assert_eq!(flat["items"], json!([{"id": 1}, {"id": 2}]));
+
+        let flat = flatten(nested(), FlattenMetadata::IndexArrays);
+        assert_eq!(flat["items.0.id"], 1);
+        assert_eq!(flat["items.1.id"], 2);
+        assert!(!flat.contains_key("items"));
+    }
+
+    #[test]
+    fn test_capture_flattens_metadata_when_enabled() {
+        let client = PulseKit::new(Config {
+            flatten_metadata: FlattenMetadata::KeepArrays,
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..Default::default()
+        });
+        client.capture_message_with_options("checkout", Level::Info, None, Some(nested()));
+
+        let metadata = client
+            .delivery
+            .take_events()
+            .pop()
+            .unwrap()
+            .metadata
+            .unwrap();
+        let mut keys: Vec<_> = metadata.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["items", "user.address.city", "user.id"]);
+    }
+}
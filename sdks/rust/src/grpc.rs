@@ -0,0 +1,110 @@
+//! Reporting of gRPC failures from `tonic`.
+
+use crate::{Event, Level, Mechanism, PulseKit};
+use std::collections::HashMap;
+use tonic::metadata::KeyAndValueRef;
+use tonic::Status;
+
+/// Metadata entries whose names contain one of these are sent as
+/// `[Filtered]`.
+const SENSITIVE_KEYS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "token",
+    "secret",
+    "password",
+    "api-key",
+    "apikey",
+];
+
+impl PulseKit {
+    /// Capture a gRPC failure as an `Error` event.
+    ///
+    /// The status code is sent as the `grpc.code` tag (e.g. `"NotFound"`)
+    /// and the message as the event message. ASCII metadata and trailers go
+    /// into the `grpc_metadata` metadata object, with credentials such as
+    /// `authorization` replaced by `[Filtered]`; binary entries are skipped.
+    pub fn capture_grpc_error(&self, status: &Status) {
+        self.capture(grpc_event(status));
+    }
+}
+
+fn grpc_event(status: &Status) -> Event {
+    let code = format!("{:?}", status.code());
+    let message = if status.message().is_empty() {
+        status.code().description().to_string()
+    } else {
+        status.message().to_string()
+    };
+
+    let mut grpc_metadata = serde_json::Map::new();
+    for entry in status.metadata().iter() {
+        if let KeyAndValueRef::Ascii(key, value) = entry {
+            let value = if is_sensitive(key.as_str()) {
+                "[Filtered]".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_encoded_bytes()).into_owned()
+            };
+            grpc_metadata.insert(key.as_str().to_string(), serde_json::Value::from(value));
+        }
+    }
+
+    let mut metadata = HashMap::new();
+    if !grpc_metadata.is_empty() {
+        metadata.insert(
+            "grpc_metadata".to_string(),
+            serde_json::Value::Object(grpc_metadata),
+        );
+    }
+
+    Event {
+        event_type: "error".to_string(),
+        level: Some(Level::Error),
+        message: Some(message),
+        tags: Some(HashMap::from([
+            ("grpc.code".to_string(), code),
+            (
+                "grpc.code_num".to_string(),
+                (status.code() as i32).to_string(),
+            ),
+        ])),
+        metadata: (!metadata.is_empty()).then_some(metadata),
+        mechanism: Some(Mechanism::generic()),
+        ..Default::default()
+    }
+}
+
+fn is_sensitive(key: &str) -> bool {
+    SENSITIVE_KEYS
+        .iter()
+        .any(|sensitive| key.contains(sensitive))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use tonic::metadata::MetadataMap;
+    use tonic::Code;
+
+    #[test]
+    fn test_status_code_message_and_trailers_are_captured() {
+        let mut trailers = MetadataMap::new();
+        trailers.insert("x-request-id", "req-123".parse().unwrap());
+        trailers.insert("authorization", "Bearer abc".parse().unwrap());
+        let status = Status::with_metadata(Code::NotFound, "order 42 not found", trailers);
+
+        let client = PulseKit::new(Config::default());
+        client.capture_grpc_error(&status);
+
+        let event = client.delivery.take_events().pop().unwrap();
+        assert_eq!(event.level, Some(Level::Error));
+        assert_eq!(event.message.as_deref(), Some("order 42 not found"));
+        let tags = event.tags.unwrap();
+        assert_eq!(tags["grpc.code"], "NotFound");
+        assert_eq!(tags["grpc.code_num"], "5");
+        let grpc_metadata = &event.metadata.unwrap()["grpc_metadata"];
+        assert_eq!(grpc_metadata["x-request-id"], "req-123");
+        assert_eq!(grpc_metadata["authorization"], "[Filtered]");
+    }
+}
@@ -0,0 +1,162 @@
+//! Forwarding of JSON log lines as events.
+
+use crate::debug_log::debug_log;
+use crate::{Event, Level, PulseKit, PulseKitError};
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Names of the JSON log fields `capture_json_log` maps onto the event.
+/// Every other field goes into metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonLogFields {
+    /// Field holding the level, e.g. `"warn"`, `"ERROR"` or a pino/bunyan
+    /// number such as `50`
+    pub level: String,
+    /// Field holding the message
+    pub message: String,
+    /// Field holding the time the line was logged, sent as the event
+    /// timestamp if it is RFC 3339 and kept in metadata otherwise
+    pub timestamp: String,
+    /// Field holding the service name, sent as the `service` tag
+    pub service: String,
+}
+
+impl Default for JsonLogFields {
+    fn default() -> Self {
+        Self {
+            level: "level".to_string(),
+            message: "message".to_string(),
+            timestamp: "timestamp".to_string(),
+            service: "service".to_string(),
+        }
+    }
+}
+
+impl PulseKit {
+    /// Capture one line of a JSON log as a `log` event, mapping fields as
+    /// configured by `json_log_fields`.
+    ///
+    /// A line that isn't a JSON object is not captured; it is reported when
+    /// `debug` is on and returned as [`PulseKitError::Serialization`].
+    pub fn capture_json_log(&self, line: &str) -> Result<(), PulseKitError> {
+        let mut fields = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(fields)) => fields,
+            Ok(_) => return Err(self.unparseable_log("not a JSON object".to_string())),
+            Err(e) => return Err(self.unparseable_log(e.to_string())),
+        };
+
+        let names = &self.config.json_log_fields;
+        let level = fields
+            .remove(&names.level)
+            .and_then(|level| parse_level(&level));
+        let message = take_string(&mut fields, &names.message);
+        let timestamp = take_timestamp(&mut fields, &names.timestamp);
+        let tags = take_string(&mut fields, &names.service)
+            .map(|service| HashMap::from([("service".to_string(), service)]));
+
+        self.capture(Event {
+            event_type: "log".to_string(),
+            level,
+            message,
+            timestamp,
+            tags,
+            metadata: (!fields.is_empty()).then(|| fields.into_iter().collect()),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn unparseable_log(&self, reason: String) -> PulseKitError {
+        debug_log!(
+            self.config,
+            Warn,
+            "Skipping unparseable log line: {}",
+            reason
+        );
+        PulseKitError::Serialization(reason)
+    }
+}
+
+/// Remove `name` from `fields`, rendering non-string values as JSON.
+fn take_string(fields: &mut Map<String, Value>, name: &str) -> Option<String> {
+    match fields.remove(name)? {
+        Value::String(value) => Some(value),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+/// Remove `name` from `fields` if it holds an RFC 3339 timestamp.
+fn take_timestamp(fields: &mut Map<String, Value>, name: &str) -> Option<DateTime<Utc>> {
+    let timestamp = DateTime::parse_from_rfc3339(fields.get(name)?.as_str()?).ok()?;
+    fields.remove(name);
+    Some(timestamp.with_timezone(&Utc))
+}
+
+fn parse_level(level: &Value) -> Option<Level> {
+    if let Some(number) = level.as_u64() {
+        return Some(match number {
+            0..=29 => Level::Debug,
+            30..=39 => Level::Info,
+            40..=49 => Level::Warning,
+            50..=59 => Level::Error,
+            _ => Level::Fatal,
+        });
+    }
+    match level.as_str()?.to_ascii_lowercase().as_str() {
+        "trace" | "debug" => Some(Level::Debug),
+        "info" | "notice" => Some(Level::Info),
+        "warn" | "warning" => Some(Level::Warning),
+        "error" | "err" => Some(Level::Error),
+        "fatal" | "critical" | "crit" | "panic" => Some(Level::Fatal),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_log_line_is_mapped_onto_the_event() {
+        let client = PulseKit::new(Config {
+            json_log_fields: JsonLogFields {
+                message: "msg".to_string(),
+                ..Default::default()
+            },
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..Default::default()
+        });
+
+        client
+            .capture_json_log(
+                r#"{"level":"WARN","msg":"slow query","timestamp":"2024-05-01T12:00:00Z","service":"orders","duration_ms":1840,"query":{"table":"orders"}}"#,
+            )
+            .unwrap();
+        assert!(matches!(
+            client.capture_json_log("GET /health 200"),
+            Err(PulseKitError::Serialization(_))
+        ));
+        assert!(client.capture_json_log("[1, 2]").is_err());
+
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.event_type, "log");
+        assert_eq!(event.level, Some(Level::Warning));
+        assert_eq!(event.message.as_deref(), Some("slow query"));
+        assert_eq!(
+            event.timestamp.unwrap().to_rfc3339(),
+            "2024-05-01T12:00:00+00:00"
+        );
+        assert_eq!(event.tags.as_ref().unwrap()["service"], "orders");
+        let metadata = event.metadata.as_ref().unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata["duration_ms"], json!(1840));
+        assert_eq!(metadata["query"], json!({ "table": "orders" }));
+    }
+}
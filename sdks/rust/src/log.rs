@@ -0,0 +1,143 @@
+//! A `log::Log` implementation that captures `log` records.
+
+use crate::{ClientOverrides, Event, Level, PulseKit};
+use ::log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+
+thread_local! {
+    /// Set while a record is being captured, so records logged by the
+    /// capture itself (from `before_send`, or the HTTP stack during a flush)
+    /// are dropped instead of captured recursively.
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Captures `log` records at or above a minimum level as `log` events.
+///
+/// The formatted message becomes the message; the record's `target`,
+/// `module_path`, `file` and `line` go into metadata. Records from this
+/// crate are never captured.
+pub struct PulseKitLogger {
+    client: PulseKit,
+    level: LevelFilter,
+}
+
+impl PulseKitLogger {
+    /// A logger that captures through a sub-client of `client`, sharing its
+    /// queue.
+    pub fn new(client: &PulseKit, level: LevelFilter) -> Self {
+        Self {
+            client: client.sub_client(ClientOverrides::default()),
+            level,
+        }
+    }
+}
+
+impl fmt::Debug for PulseKitLogger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PulseKitLogger")
+            .field("level", &self.level)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Install a [`PulseKitLogger`] as the global logger and raise the global
+/// maximum level to `level`. Fails if a logger is already installed.
+///
+/// ```no_run
+/// let client = pulsekit::PulseKit::new(Default::default());
+/// pulsekit::log::init(&client, log::LevelFilter::Warn).unwrap();
+/// ```
+pub fn init(client: &PulseKit, level: LevelFilter) -> Result<(), SetLoggerError> {
+    ::log::set_boxed_logger(Box::new(PulseKitLogger::new(client, level)))?;
+    ::log::set_max_level(level);
+    Ok(())
+}
+
+fn to_level(level: ::log::Level) -> Level {
+    match level {
+        ::log::Level::Error => Level::Error,
+        ::log::Level::Warn => Level::Warning,
+        ::log::Level::Info => Level::Info,
+        ::log::Level::Debug | ::log::Level::Trace => Level::Debug,
+    }
+}
+
+impl Log for PulseKitLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level && !metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) || CAPTURING.with(Cell::get) {
+            return;
+        }
+        CAPTURING.with(|capturing| capturing.set(true));
+
+        let mut metadata = HashMap::from([(
+            "target".to_string(),
+            serde_json::Value::from(record.target()),
+        )]);
+        if let Some(module_path) = record.module_path() {
+            metadata.insert("module_path".to_string(), module_path.into());
+        }
+        if let Some(file) = record.file() {
+            metadata.insert("file".to_string(), file.into());
+        }
+        if let Some(line) = record.line() {
+            metadata.insert("line".to_string(), line.into());
+        }
+        self.client.capture(Event {
+            event_type: "log".to_string(),
+            level: Some(to_level(record.level())),
+            message: Some(record.args().to_string()),
+            metadata: Some(metadata),
+            ..Default::default()
+        });
+
+        CAPTURING.with(|capturing| capturing.set(false));
+    }
+
+    fn flush(&self) {
+        self.client.flush_blocking();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_records_are_captured_without_recursing() {
+        let client = PulseKit::new(Config {
+            batch_size: 100,
+            before_send: Some(Arc::new(|event: Event| {
+                ::log::error!("logged from before_send");
+                Some(event)
+            })),
+            ..Default::default()
+        });
+        init(&client, LevelFilter::Warn).unwrap();
+
+        ::log::info!("below the minimum level");
+        ::log::error!(target: "billing", "charge failed for {}", 42);
+
+        let events: Vec<_> = client
+            .delivery
+            .take_events()
+            .into_iter()
+            .filter(|event| event.event_type == "log")
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].level, Some(Level::Error));
+        assert_eq!(events[0].message.as_deref(), Some("charge failed for 42"));
+        let metadata = events[0].metadata.as_ref().unwrap();
+        assert_eq!(metadata["target"], "billing");
+        assert_eq!(metadata["module_path"], module_path!());
+        assert_eq!(metadata["file"], file!());
+        assert!(metadata["line"].is_u64());
+    }
+}
@@ -0,0 +1,65 @@
+//! Macros that record where they are called from.
+
+/// The path of the enclosing function, e.g. `my_app::billing::charge`.
+///
+/// Calls inside closures report the function the closure is defined in.
+#[macro_export]
+macro_rules! function {
+    () => {{
+        fn f() {}
+        fn type_name_of<T>(_: T) -> &'static str {
+            ::std::any::type_name::<T>()
+        }
+        let name = type_name_of(f);
+        name[..name.len() - "::f".len()].trim_end_matches("::{{closure}}")
+    }};
+}
+
+/// Capture a message whose only stack frame is the call site, including the
+/// enclosing function's name, without collecting a backtrace.
+///
+/// ```no_run
+/// # let client = pulsekit::PulseKit::new(Default::default());
+/// pulsekit::capture_here!(client, pulsekit::Level::Warning, "retrying {} more times", 3);
+/// ```
+#[macro_export]
+macro_rules! capture_here {
+    ($client:expr, $level:expr, $($arg:tt)+) => {
+        $client.capture($crate::Event {
+            event_type: "message".to_string(),
+            level: Some($level),
+            message: Some(::std::format!($($arg)+)),
+            stacktrace: Some(vec![$crate::StackFrame {
+                file: Some(file!().to_string()),
+                line: Some(line!()),
+                function: Some($crate::function!().to_string()),
+            }]),
+            ..::std::default::Default::default()
+        })
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, Level, PulseKit};
+
+    #[test]
+    fn test_capture_here_names_the_enclosing_function() {
+        let client = PulseKit::new(Config::default());
+        capture_here!(client, Level::Warning, "low disk: {}%", 5);
+        let in_closure = Some(()).map(|()| function!()).unwrap();
+
+        let event = client.delivery.take_events().pop().unwrap();
+        assert_eq!(event.message.as_deref(), Some("low disk: 5%"));
+        let frame = &event.stacktrace.unwrap()[0];
+        assert_eq!(
+            frame.function.as_deref(),
+            Some("pulsekit::macros::tests::test_capture_here_names_the_enclosing_function")
+        );
+        assert_eq!(frame.file.as_deref(), Some(file!()));
+        assert_eq!(
+            in_closure,
+            "pulsekit::macros::tests::test_capture_here_names_the_enclosing_function"
+        );
+    }
+}
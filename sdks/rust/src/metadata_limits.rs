@@ -0,0 +1,153 @@
+//! Limits on the shape and size of metadata, so deeply nested or huge
+//! values don't get the whole event rejected by the server.
+
+use crate::oversize::TRUNCATED;
+use crate::Config;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Apply `max_metadata_depth`, `max_metadata_string_length`,
+/// `max_metadata_array_length` and then `max_metadata_bytes`.
+pub(crate) fn limit(metadata: &mut HashMap<String, Value>, config: &Config) {
+    for value in metadata.values_mut() {
+        limit_value(value, 1, config);
+    }
+    if let Some(max) = config.max_metadata_bytes {
+        fit(metadata, max);
+    }
+}
+
+/// Limit `value`, found `depth` levels below the metadata map.
+fn limit_value(value: &mut Value, depth: usize, config: &Config) {
+    let too_deep = config.max_metadata_depth.is_some_and(|max| depth > max);
+    match value {
+        Value::String(string) => {
+            if let Some(max) = config.max_metadata_string_length {
+                truncate_string(string, max, &config.truncation_marker);
+            }
+        }
+        Value::Array(_) | Value::Object(_) if too_deep => *value = Value::from(TRUNCATED),
+        Value::Array(items) => {
+            if let Some(max) = config
+                .max_metadata_array_length
+                .filter(|max| items.len() > *max)
+            {
+                items.truncate(max);
+                items.push(Value::from(TRUNCATED));
+            }
+            for item in items {
+                limit_value(item, depth + 1, config);
+            }
+        }
+        Value::Object(fields) => {
+            for field in fields.values_mut() {
+                limit_value(field, depth + 1, config);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace top-level values with `"[Truncated]"`, largest first, until the
+/// metadata serializes to at most `max` bytes.
+fn fit(metadata: &mut HashMap<String, Value>, max: usize) {
+    let size = |metadata: &HashMap<String, Value>| {
+        serde_json::to_vec(metadata).map_or(0, |body| body.len())
+    };
+    if size(metadata) <= max {
+        return;
+    }
+    let mut by_size: Vec<(usize, String)> = metadata
+        .iter()
+        .map(|(key, value)| (value.to_string().len(), key.clone()))
+        .collect();
+    by_size.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    for (_, key) in by_size {
+        metadata.insert(key, Value::from(TRUNCATED));
+        if size(metadata) <= max {
+            return;
+        }
+    }
+}
+
+/// Cut `value` to `max` characters plus `marker`, returning its original
+/// length in characters if it was cut.
+pub(crate) fn truncate_string(value: &mut String, max: usize, marker: &str) -> Option<usize> {
+    let (cut_at, _) = value.char_indices().nth(max)?;
+    let length = value.chars().count();
+    value.truncate(cut_at);
+    value.push_str(marker);
+    Some(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Event, PulseKit};
+    use serde_json::json;
+
+    fn captured(config: Config, metadata: Value) -> HashMap<String, Value> {
+        let client = PulseKit::new(Config {
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..config
+        });
+        client.capture(Event {
+            event_type: "error".to_string(),
+            metadata: serde_json::from_value(metadata).ok(),
+            ..Default::default()
+        });
+        client.delivery.take_events().remove(0).metadata.unwrap()
+    }
+
+    #[test]
+    fn test_nesting_past_the_depth_limit_is_truncated() {
+        let metadata = captured(
+            Config {
+                max_metadata_depth: Some(1),
+                ..Default::default()
+            },
+            json!({
+                "request": {"user": {"id": 7}, "ids": [1, 2, 3], "path": "/orders"},
+                "flat": 1,
+            }),
+        );
+
+        assert_eq!(
+            metadata["request"],
+            json!({"user": "[Truncated]", "ids": "[Truncated]", "path": "/orders"})
+        );
+        assert_eq!(metadata["flat"], 1);
+
+        let metadata = captured(
+            Config {
+                max_metadata_array_length: Some(2),
+                ..Default::default()
+            },
+            json!({"ids": [1, 2, 3]}),
+        );
+        assert_eq!(metadata["ids"], json!([1, 2, "[Truncated]"]));
+    }
+
+    #[test]
+    fn test_giant_values_are_cut_to_fit() {
+        let metadata = captured(
+            Config {
+                max_metadata_string_length: Some(5),
+                ..Default::default()
+            },
+            json!({"log": {"tail": "x".repeat(10_000)}}),
+        );
+        assert_eq!(metadata["log"]["tail"], "xxxxx...[truncated]");
+
+        let metadata = captured(
+            Config {
+                max_metadata_bytes: Some(100),
+                ..Default::default()
+            },
+            json!({"dump": vec![1; 500], "order_id": "A-1"}),
+        );
+        assert_eq!(metadata["dump"], TRUNCATED);
+        assert_eq!(metadata["order_id"], "A-1");
+    }
+}
@@ -0,0 +1,208 @@
+//! On-disk copy of the event queue, so queued events survive a crash.
+
+use crate::Event;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Mirrors the queue in a newline-delimited JSON file.
+///
+/// Only the queue worker uses it, so the file always matches the queue
+/// plus any batch that is being sent.
+pub(crate) struct OfflineStore {
+    path: PathBuf,
+    max_bytes: u64,
+    len: AtomicU64,
+}
+
+impl OfflineStore {
+    pub(crate) fn new(path: PathBuf, max_bytes: u64) -> Self {
+        let len = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        Self {
+            path,
+            max_bytes,
+            len: AtomicU64::new(len),
+        }
+    }
+
+    /// Events left in the file by an earlier process. Lines that don't
+    /// parse, such as one cut short by a crash, are skipped.
+    pub(crate) fn load(&self) -> Vec<Event> {
+        fs::read_to_string(&self.path)
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter_map(|line| serde_json::from_str(line).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Append one event. Once the file has reached `max_bytes` events are
+    /// only kept in memory.
+    pub(crate) fn append(&self, event: &Event) -> io::Result<()> {
+        let mut line = Self::line(event)?.into_bytes();
+        line.push(b'\n');
+        let len = self.len.load(Ordering::Relaxed);
+        if len + line.len() as u64 > self.max_bytes {
+            return Err(io::Error::other("offline storage is full"));
+        }
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        self.len.store(len + line.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Remove one entry for each of `lines`, as serialized by
+    /// [`OfflineStore::line`], keeping the rest in order.
+    pub(crate) fn remove(&self, lines: &[String]) -> io::Result<()> {
+        let mut removed: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut kept = String::with_capacity(contents.len());
+        for line in contents.lines() {
+            if let Some(i) = removed.iter().position(|removed| *removed == line) {
+                removed.swap_remove(i);
+            } else {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, &kept)?;
+        fs::rename(&tmp, &self.path)?;
+        self.len.store(kept.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// How `event` is written to the file, without the newline.
+    pub(crate) fn line(event: &Event) -> serde_json::Result<String> {
+        serde_json::to_string(event)
+    }
+
+    /// Replace the file's contents with `events`, dropping entries for
+    /// events that have been sent.
+    pub(crate) fn replace<'a>(&self, events: impl Iterator<Item = &'a Event>) -> io::Result<()> {
+        let mut contents = Vec::new();
+        for event in events {
+            let mut line = serde_json::to_vec(event)?;
+            line.push(b'\n');
+            if (contents.len() + line.len()) as u64 > self.max_bytes {
+                break;
+            }
+            contents.extend(line);
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, &contents)?;
+        fs::rename(&tmp, &self.path)?;
+        self.len.store(contents.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level, MemoryTransport, PulseKit};
+
+    fn storage_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "pulsekit-offline-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_queued_events_survive_a_crash() {
+        let path = storage_path("crash");
+        let config = Config {
+            offline_storage: Some(path.clone()),
+            batch_size: 100,
+            ..Default::default()
+        };
+
+        let crashed = PulseKit::with_transport(config.clone(), MemoryTransport::new());
+        crashed.capture_message("first", Level::Info);
+        crashed.capture_message("second", Level::Info);
+        // Let the queue worker write both events out, then skip the flush
+        // on drop, as a killed process would.
+        crashed.delivery.queue.stop();
+        std::mem::forget(crashed);
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(br#"{"type":"err"#)
+            .unwrap();
+
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(config, transport.clone());
+        client.flush_blocking();
+
+        let messages: Vec<_> = transport
+            .events()
+            .into_iter()
+            .map(|event| event.message.unwrap())
+            .collect();
+        assert_eq!(messages, ["first", "second"]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_evicted_events_leave_the_file() {
+        let path = storage_path("evicted");
+        let client = PulseKit::with_transport(
+            Config {
+                offline_storage: Some(path.clone()),
+                max_queue_size: 2,
+                batch_size: 100,
+                ..Default::default()
+            },
+            MemoryTransport::new(),
+        );
+        client.capture_message("first", Level::Info);
+        client.capture_message("second", Level::Info);
+        client.capture_message("third", Level::Info);
+        client.delivery.queue.stop();
+
+        let messages: Vec<_> = OfflineStore::new(path.clone(), u64::MAX)
+            .load()
+            .into_iter()
+            .map(|event| event.message.unwrap())
+            .collect();
+        assert_eq!(messages, ["second", "third"]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_file_size_is_bounded() {
+        let path = storage_path("bounded");
+        let store = OfflineStore::new(path.clone(), 200);
+        let event = Event {
+            event_type: "log".to_string(),
+            message: Some("x".repeat(60)),
+            ..Default::default()
+        };
+
+        assert!(store.append(&event).is_ok());
+        assert!(store.append(&event).is_ok());
+        assert!(store.append(&event).is_err());
+        assert_eq!(store.load().len(), 2);
+        assert!(fs::metadata(&path).unwrap().len() <= 200);
+        let _ = fs::remove_file(&path);
+    }
+}
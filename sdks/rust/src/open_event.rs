@@ -0,0 +1,161 @@
+//! Events accumulated across several calls and captured once finished.
+
+use crate::time::{Duration, Instant};
+use crate::{Event, PulseKit};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+/// Most events that may be open at once; opening another one discards the
+/// oldest.
+const MAX_OPEN_EVENTS: usize = 256;
+
+/// Open events older than this are discarded without being captured.
+const OPEN_EVENT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Metadata key that collects the messages passed to `append_to`.
+const BREADCRUMBS_KEY: &str = "breadcrumbs";
+
+struct OpenEvent {
+    event: Event,
+    opened_at: Instant,
+}
+
+/// Events opened with `open_event` and not yet closed, by key.
+#[derive(Default)]
+pub(crate) struct OpenEvents {
+    events: Mutex<HashMap<String, OpenEvent>>,
+}
+
+impl OpenEvents {
+    fn expire(events: &mut HashMap<String, OpenEvent>, now: Instant) {
+        events.retain(|_, open| now.duration_since(open.opened_at) < OPEN_EVENT_TIMEOUT);
+    }
+}
+
+impl PulseKit {
+    /// Start accumulating `event` under `key`, replacing any event already
+    /// open under that key.
+    ///
+    /// Use [`PulseKit::append_to`] to add context as the operation proceeds
+    /// and [`PulseKit::close_event`] to capture the result. Events left open
+    /// for five minutes are discarded, as is the oldest open event once 256
+    /// are open.
+    pub fn open_event(&self, key: &str, event: Event) {
+        let now = self.delivery.clock.instant();
+        let mut events = self.delivery.open_events.events.lock();
+        OpenEvents::expire(&mut events, now);
+        if events.len() >= MAX_OPEN_EVENTS && !events.contains_key(key) {
+            let oldest = events
+                .iter()
+                .min_by_key(|(_, open)| open.opened_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                events.remove(&oldest);
+            }
+        }
+        events.insert(
+            key.to_string(),
+            OpenEvent {
+                event,
+                opened_at: now,
+            },
+        );
+    }
+
+    /// Add context to the event open under `key`: `message` is appended to
+    /// its `breadcrumbs` metadata list and `metadata` merged over its
+    /// metadata. Returns `false` if no event is open under `key`.
+    pub fn append_to(
+        &self,
+        key: &str,
+        message: &str,
+        metadata: HashMap<String, serde_json::Value>,
+    ) -> bool {
+        let mut events = self.delivery.open_events.events.lock();
+        OpenEvents::expire(&mut events, self.delivery.clock.instant());
+        let Some(open) = events.get_mut(key) else {
+            return false;
+        };
+
+        let fields = open.event.metadata.get_or_insert_with(HashMap::new);
+        fields.extend(metadata);
+        let breadcrumbs = fields
+            .entry(BREADCRUMBS_KEY.to_string())
+            .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+        if !breadcrumbs.is_array() {
+            *breadcrumbs = serde_json::Value::Array(Vec::new());
+        }
+        if let Some(breadcrumbs) = breadcrumbs.as_array_mut() {
+            breadcrumbs.push(serde_json::json!(message));
+        }
+        true
+    }
+
+    /// Capture the event open under `key` with everything appended to it.
+    /// Returns `false` if no event is open under `key`.
+    pub fn close_event(&self, key: &str) -> bool {
+        let open = {
+            let mut events = self.delivery.open_events.events.lock();
+            OpenEvents::expire(&mut events, self.delivery.clock.instant());
+            events.remove(key)
+        };
+        match open {
+            Some(open) => {
+                self.capture(open.event);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level};
+    use serde_json::json;
+
+    #[test]
+    fn test_appends_are_merged_into_one_event_on_close() {
+        let client = PulseKit::new(Config::default());
+        client.open_event(
+            "import-42",
+            Event {
+                event_type: "import.failed".to_string(),
+                level: Some(Level::Error),
+                message: Some("import failed".to_string()),
+                ..Default::default()
+            },
+        );
+
+        assert!(client.append_to(
+            "import-42",
+            "slow upstream response",
+            HashMap::from([("attempt".to_string(), json!(1))]),
+        ));
+        assert!(client.append_to(
+            "import-42",
+            "retrying after timeout",
+            HashMap::from([
+                ("attempt".to_string(), json!(2)),
+                ("rows".to_string(), json!(1200)),
+            ]),
+        ));
+        assert!(client.delivery.take_events().is_empty());
+
+        assert!(client.close_event("import-42"));
+        assert!(!client.close_event("import-42"));
+        assert!(!client.append_to("import-42", "too late", HashMap::new()));
+
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 1);
+        let metadata = events[0].metadata.as_ref().unwrap();
+        assert_eq!(events[0].message.as_deref(), Some("import failed"));
+        assert_eq!(metadata["attempt"], json!(2));
+        assert_eq!(metadata["rows"], json!(1200));
+        assert_eq!(
+            metadata["breadcrumbs"],
+            json!(["slow upstream response", "retrying after timeout"])
+        );
+    }
+}
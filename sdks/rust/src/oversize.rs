@@ -0,0 +1,166 @@
+//! Handling of single events too large for a request on their own.
+
+use crate::dead_letter::DropReason;
+use crate::debug_log::debug_log;
+use crate::{Event, PulseKit};
+use serde_json::Value;
+
+/// Replaces metadata values removed to make an event fit.
+pub(crate) const TRUNCATED: &str = "[Truncated]";
+
+/// What `capture` does with an event whose serialized size alone exceeds
+/// `max_request_bytes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OversizedEventPolicy {
+    /// Send it in a request of its own, over the limit.
+    #[default]
+    SendAnyway,
+    /// Drop it.
+    Drop,
+    /// Shrink it until it fits: replace metadata values with
+    /// `"[Truncated]"`, largest first, then drop outer stack frames, then
+    /// cut the message. Dropped if it still doesn't fit.
+    Truncate,
+}
+
+pub(crate) fn serialized_size(event: &Event) -> usize {
+    serde_json::to_vec(event).map_or(0, |body| body.len())
+}
+
+/// Shrink `event` to at most `max` serialized bytes, the same way every
+/// time. Returns whether it fits.
+fn truncate(event: &mut Event, max: usize) -> bool {
+    let fits = |event: &Event| serialized_size(event) <= max;
+
+    if let Some(metadata) = event.metadata.as_mut() {
+        let mut by_size: Vec<(usize, String)> = metadata
+            .iter()
+            .map(|(key, value)| (value.to_string().len(), key.clone()))
+            .collect();
+        by_size.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        for (_, key) in by_size {
+            if fits(event) {
+                return true;
+            }
+            if let Some(metadata) = event.metadata.as_mut() {
+                metadata.insert(key, Value::from(TRUNCATED));
+            }
+        }
+    }
+
+    while event
+        .stacktrace
+        .as_ref()
+        .is_some_and(|frames| !frames.is_empty())
+        && !fits(event)
+    {
+        if let Some(frames) = event.stacktrace.as_mut() {
+            frames.truncate(frames.len() / 2);
+        }
+    }
+
+    let size = serialized_size(event);
+    if size > max {
+        if let Some(message) = event.message.as_mut() {
+            let keep = message.len().saturating_sub(size - max);
+            let mut end = keep;
+            while !message.is_char_boundary(end) {
+                end -= 1;
+            }
+            message.truncate(end);
+        }
+    }
+    fits(event)
+}
+
+impl PulseKit {
+    /// Apply `oversized_event_policy` to an event larger than
+    /// `max_request_bytes`.
+    pub(crate) fn limit_size(&self, mut event: Event) -> Option<Event> {
+        let Some(max) = self.config.max_request_bytes else {
+            return Some(event);
+        };
+        let size = serialized_size(&event);
+        if size <= max {
+            return Some(event);
+        }
+        let keep = match self.config.oversized_event_policy {
+            OversizedEventPolicy::SendAnyway => true,
+            OversizedEventPolicy::Drop => false,
+            OversizedEventPolicy::Truncate => truncate(&mut event, max),
+        };
+        debug_log!(
+            self.config,
+            Warn,
+            "Event of {} bytes exceeds max_request_bytes ({}), {}",
+            size,
+            max,
+            if keep { "sending it" } else { "dropping it" }
+        );
+        if keep {
+            Some(event)
+        } else {
+            self.discard(DropReason::Oversized, event);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, StackFrame};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn oversized_event() -> Event {
+        Event {
+            event_type: "error".to_string(),
+            message: Some("export failed".to_string()),
+            metadata: Some(HashMap::from([
+                ("rows".to_string(), json!("x".repeat(20_000))),
+                ("job_id".to_string(), json!("exp_42")),
+            ])),
+            stacktrace: Some(vec![
+                StackFrame {
+                    file: Some("src/export.rs".to_string()),
+                    line: Some(42),
+                    function: Some("billing::export::run".to_string()),
+                };
+                8
+            ]),
+            ..Default::default()
+        }
+    }
+
+    fn client(policy: OversizedEventPolicy) -> PulseKit {
+        PulseKit::new(Config {
+            max_request_bytes: Some(4096),
+            oversized_event_policy: policy,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_oversized_event_policies() {
+        let send_anyway = client(OversizedEventPolicy::SendAnyway);
+        send_anyway.capture(oversized_event());
+        let event = send_anyway.delivery.take_events().pop().unwrap();
+        assert!(serialized_size(&event) > 4096);
+
+        let drop = client(OversizedEventPolicy::Drop);
+        drop.capture(oversized_event());
+        assert!(drop.delivery.take_events().is_empty());
+        assert_eq!(drop.stats().dropped_oversized, 1);
+
+        let truncate = client(OversizedEventPolicy::Truncate);
+        truncate.capture(oversized_event());
+        let event = truncate.delivery.take_events().pop().unwrap();
+        assert!(serialized_size(&event) <= 4096);
+        let metadata = event.metadata.unwrap();
+        assert_eq!(metadata["rows"], TRUNCATED);
+        assert_eq!(metadata["job_id"], "exp_42");
+        assert_eq!(event.stacktrace.unwrap().len(), 8);
+        assert_eq!(event.message.as_deref(), Some("export failed"));
+    }
+}
@@ -0,0 +1,290 @@
+//! Panic hook that reports panics as `Fatal` events.
+
+use crate::{Event, Level, Mechanism, PulseKit, StackFrame};
+use std::any::{type_name, Any};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::panic;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+
+impl PulseKit {
+    /// Install a panic hook that captures panics as `Fatal` events and
+    /// flushes them before chaining to the previously installed hook.
+    ///
+    /// Stack frames are collected with the `backtrace` crate, so panics in
+    /// release builds have stacks even when `RUST_BACKTRACE` is unset.
+    ///
+    /// The event is tagged with the panicking thread's name (`thread`), which
+    /// tells worker threads apart in multi-threaded programs. When no frames
+    /// can be collected, the panic location is sent as the only frame.
+    ///
+    /// The hook only holds a weak reference to the client's queue, so once
+    /// every handle is dropped it simply defers to the previous hook. Calling
+    /// this again for the same client, or one of its sub-clients, does
+    /// nothing, so a panic is never reported twice.
+    pub fn install_panic_hook(&self) {
+        if self
+            .delivery
+            .panic_hook_installed
+            .swap(true, Ordering::SeqCst)
+        {
+            return;
+        }
+        let config = self.config.clone();
+        let delivery = Arc::downgrade(&self.delivery);
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            if let Some(delivery) = delivery.upgrade() {
+                let client = PulseKit::with_delivery(config.clone(), delivery);
+                let (message, payload_type) = describe_payload(info.payload());
+                let mut frames = panic_frames(&client);
+                if frames.is_empty() {
+                    frames.extend(info.location().map(|location| StackFrame {
+                        file: Some(location.file().to_string()),
+                        line: Some(location.line()),
+                        function: None,
+                    }));
+                }
+                let thread = thread::current();
+                client.capture_prepared(Event {
+                    event_type: "error".to_string(),
+                    level: Some(Level::Fatal),
+                    message: Some(message),
+                    tags: Some(HashMap::from([(
+                        "thread".to_string(),
+                        thread.name().unwrap_or("<unnamed>").to_string(),
+                    )])),
+                    metadata: Some(HashMap::from([(
+                        "panic_payload_type".to_string(),
+                        serde_json::Value::from(payload_type),
+                    )])),
+                    stacktrace: Some(frames),
+                    mechanism: Some(Mechanism::panic()),
+                    ..Default::default()
+                });
+                client.flush_blocking();
+            }
+
+            previous(info);
+        }));
+    }
+}
+
+/// The panicking thread's frames, starting at the code that panicked rather
+/// than inside the hook or the standard library's panic machinery.
+fn panic_frames(client: &PulseKit) -> Vec<StackFrame> {
+    let frames = crate::capture_backtrace(
+        client.delivery.resolution_slots.as_ref(),
+        client.config.max_stack_frames,
+    );
+    let is_machinery =
+        |frame: &&StackFrame| frame.function.as_deref().is_some_and(is_panic_machinery);
+    match frames.iter().position(|frame| is_machinery(&frame)) {
+        Some(first) => frames[first..]
+            .iter()
+            .skip_while(is_machinery)
+            .cloned()
+            .collect(),
+        None => frames,
+    }
+}
+
+/// Symbol names may carry crate disambiguators (`std[1a2b]::panicking`), so
+/// only the crate prefix and path segments are matched.
+fn is_panic_machinery(function: &str) -> bool {
+    let in_std = function.starts_with("std") || function.starts_with("core");
+    (in_std && (function.contains("::panicking::") || function.contains("::panic::panic_any")))
+        || function.contains("__rust_end_short_backtrace")
+        || function.contains("rust_begin_unwind")
+}
+
+/// The panic message and the name of the payload's type.
+///
+/// `panic!` payloads are `&str` or `String`; `panic_any` can carry anything,
+/// so common displayable types are rendered too before falling back to a
+/// placeholder.
+pub(crate) fn describe_payload(payload: &(dyn Any + Send)) -> (String, &'static str) {
+    fn display<T: Display + 'static>(payload: &(dyn Any + Send)) -> Option<(String, &'static str)> {
+        payload
+            .downcast_ref::<T>()
+            .map(|value| (value.to_string(), type_name::<T>()))
+    }
+
+    display::<&str>(payload)
+        .or_else(|| display::<String>(payload))
+        .or_else(|| display::<Box<str>>(payload))
+        .or_else(|| display::<std::borrow::Cow<'static, str>>(payload))
+        .or_else(|| display::<i32>(payload))
+        .or_else(|| display::<i64>(payload))
+        .or_else(|| display::<u32>(payload))
+        .or_else(|| display::<u64>(payload))
+        .or_else(|| display::<usize>(payload))
+        .or_else(|| display::<isize>(payload))
+        .or_else(|| display::<f64>(payload))
+        .or_else(|| display::<bool>(payload))
+        .or_else(|| display::<char>(payload))
+        .unwrap_or_else(|| {
+            (
+                "panicked with a non-string payload".to_string(),
+                "Box<dyn Any>",
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::Config;
+
+    #[test]
+    fn test_panic_hook_marks_event_unhandled() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            ..Default::default()
+        });
+        client.install_panic_hook();
+
+        let result = thread::spawn(|| panic!("hook test panic")).join();
+        assert!(result.is_err());
+
+        let event = server
+            .events()
+            .into_iter()
+            .find(|event| event["message"] == "hook test panic")
+            .expect("panic event was not sent");
+        assert_eq!(event["level"], "fatal");
+        assert_eq!(event["mechanism"]["type"], "panic");
+        assert_eq!(event["mechanism"]["handled"], false);
+    }
+
+    #[test]
+    fn test_panic_hook_installs_once_and_tags_the_thread() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            ..Default::default()
+        });
+        client.install_panic_hook();
+        client.install_panic_hook();
+        client.sub_client(Default::default()).install_panic_hook();
+
+        let result = thread::Builder::new()
+            .name("importer".to_string())
+            .spawn(|| panic!("once test panic"))
+            .unwrap()
+            .join();
+        assert!(result.is_err());
+
+        let events: Vec<_> = server
+            .events()
+            .into_iter()
+            .filter(|event| event["message"] == "once test panic")
+            .collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["tags"]["thread"], "importer");
+    }
+
+    #[test]
+    fn test_panic_event_starts_at_the_panicking_frame() {
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            ..Default::default()
+        });
+        client.install_panic_hook();
+
+        let result = thread::spawn(|| panic!("frames test panic")).join();
+        assert!(result.is_err());
+
+        let event = server
+            .events()
+            .into_iter()
+            .find(|event| event["message"] == "frames test panic")
+            .expect("panic event was not sent");
+        let functions: Vec<_> = event["stacktrace"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|frame| frame["function"].as_str())
+            .collect();
+        assert!(!functions.is_empty());
+        assert!(!is_panic_machinery(functions[0]));
+        // Release builds may inline the closure into the thread entry point.
+        #[cfg(debug_assertions)]
+        assert!(functions[0].contains("test_panic_event_starts_at_the_panicking_frame"));
+    }
+
+    #[test]
+    fn test_capture_error_is_handled() {
+        let client = PulseKit::new(Config::default());
+        client.capture_error("handled failure");
+
+        let event = client.delivery.take_events().pop().unwrap();
+        assert_eq!(event.mechanism, Some(Mechanism::generic()));
+        assert!(event.mechanism.unwrap().handled);
+    }
+
+    #[test]
+    fn test_non_string_payloads_are_described() {
+        struct Custom;
+
+        assert_eq!(describe_payload(&"plain"), ("plain".to_string(), "&str"));
+        assert_eq!(describe_payload(&7u32), ("7".to_string(), "u32"));
+        assert_eq!(
+            describe_payload(&Custom),
+            (
+                "panicked with a non-string payload".to_string(),
+                "Box<dyn Any>"
+            )
+        );
+
+        let server = MockServer::start();
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            ..Default::default()
+        });
+        client.install_panic_hook();
+
+        let result = thread::spawn(|| std::panic::panic_any(404_404i32)).join();
+        assert!(result.is_err());
+
+        let event = server
+            .events()
+            .into_iter()
+            .find(|event| event["message"] == "404404")
+            .expect("panic event was not sent");
+        assert_eq!(event["metadata"]["panic_payload_type"], "i32");
+    }
+
+    #[test]
+    fn test_capture_from_drop_during_unwinding_is_queued() {
+        struct ReportOnDrop<'a>(&'a PulseKit);
+
+        impl Drop for ReportOnDrop<'_> {
+            fn drop(&mut self) {
+                assert!(thread::panicking());
+                self.0.capture_message("request aborted", Level::Warning);
+            }
+        }
+
+        let client = PulseKit::new(Config {
+            before_send: Some(Arc::new(|_| panic!("hooks are skipped while unwinding"))),
+            ..Default::default()
+        });
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let _guard = ReportOnDrop(&client);
+            panic!("handler failed");
+        }));
+
+        assert!(result.is_err());
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].message.as_deref(), Some("request aborted"));
+        assert!(events[0].environment.is_none());
+    }
+}
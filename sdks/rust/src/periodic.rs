@@ -0,0 +1,98 @@
+//! Status events captured at most once per interval.
+
+use crate::time::{Duration, Instant};
+use crate::{Event, PulseKit};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+/// Most keys whose last capture time is remembered; once reached, the key
+/// captured longest ago is forgotten.
+const MAX_PERIODIC_KEYS: usize = 1024;
+
+/// When each `capture_periodic` key last captured an event.
+#[derive(Default)]
+pub(crate) struct PeriodicKeys {
+    last_capture: Mutex<HashMap<String, Instant>>,
+}
+
+impl PeriodicKeys {
+    /// Record a capture for `key` unless it captured within `interval`.
+    fn try_claim(&self, key: &str, interval: Duration, now: Instant) -> bool {
+        let mut last_capture = self.last_capture.lock();
+        if let Some(at) = last_capture.get(key) {
+            if now.duration_since(*at) < interval {
+                return false;
+            }
+        } else if last_capture.len() >= MAX_PERIODIC_KEYS {
+            let oldest = last_capture
+                .iter()
+                .min_by_key(|(_, at)| **at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                last_capture.remove(&oldest);
+            }
+        }
+        last_capture.insert(key.to_string(), now);
+        true
+    }
+}
+
+impl PulseKit {
+    /// Capture the event built by `build` unless this `key` already captured
+    /// one within `interval`, e.g. for "processed N items" heartbeats from a
+    /// hot loop.
+    ///
+    /// `build` only runs when the event is captured. Returns whether it was.
+    pub fn capture_periodic(
+        &self,
+        key: &str,
+        interval: Duration,
+        build: impl FnOnce() -> Event,
+    ) -> bool {
+        let now = self.delivery.clock.instant();
+        if !self.delivery.periodic.try_claim(key, interval, now) {
+            return false;
+        }
+        self.capture(build());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level};
+
+    #[test]
+    fn test_at_most_one_event_per_interval_is_captured() {
+        let client = PulseKit::new(Config {
+            batch_size: 1000,
+            ..Default::default()
+        });
+        let interval = Duration::from_millis(50);
+        let started = Instant::now();
+
+        let mut processed = 0;
+        while started.elapsed() < Duration::from_millis(120) {
+            processed += 1;
+            client.capture_periodic("worker", interval, || Event {
+                event_type: "worker.status".to_string(),
+                level: Some(Level::Info),
+                message: Some(format!("processed {} items", processed)),
+                ..Default::default()
+            });
+        }
+        client.capture_periodic("other", interval, || Event {
+            event_type: "other.status".to_string(),
+            ..Default::default()
+        });
+
+        let events = client.delivery.take_events();
+        let worker = events
+            .iter()
+            .filter(|event| event.event_type == "worker.status")
+            .count();
+        assert!((2..=3).contains(&worker), "captured {} events", worker);
+        assert_eq!(events.len(), worker + 1);
+    }
+}
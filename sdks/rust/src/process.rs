@@ -0,0 +1,172 @@
+//! Resource usage and age of the current process and the capturing thread,
+//! and the platform it runs on, attached when
+//! `Config::attach_process_context`, `Config::attach_uptime`,
+//! `Config::capture_thread_info` or `Config::capture_runtime_context` is
+//! enabled.
+
+use crate::time::Instant;
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use std::sync::OnceLock;
+
+/// When the first client of this process was created, which stands in for
+/// the process start.
+static STARTED: OnceLock<(Instant, DateTime<Utc>)> = OnceLock::new();
+
+/// Record the process start if no client has yet.
+pub(crate) fn mark_started() {
+    STARTED.get_or_init(|| (Instant::now(), Utc::now()));
+}
+
+/// Seconds since [`mark_started`] and the wall-clock time it was called.
+pub(crate) fn uptime() -> (f64, DateTime<Utc>) {
+    let (started, started_at) = *STARTED.get_or_init(|| (Instant::now(), Utc::now()));
+    (started.elapsed().as_secs_f64(), started_at)
+}
+
+/// Build the `thread` metadata block: the current thread's numeric id, and
+/// its name if it has one.
+pub(crate) fn thread_context() -> Value {
+    let thread = std::thread::current();
+    let mut context = Map::new();
+    // `ThreadId` only exposes its number through `Debug`, as `ThreadId(7)`.
+    let id = format!("{:?}", thread.id());
+    let id = id
+        .trim_start_matches("ThreadId(")
+        .trim_end_matches(')')
+        .parse::<u64>()
+        .map_or_else(|_| Value::from(id.clone()), Value::from);
+    context.insert("id".to_string(), id);
+    if let Some(name) = thread.name() {
+        context.insert("name".to_string(), Value::from(name));
+    }
+    Value::Object(context)
+}
+
+/// The process id, which wasm32 has none of.
+pub(crate) fn pid() -> Option<u32> {
+    (!cfg!(target_arch = "wasm32")).then(std::process::id)
+}
+
+/// Build the `process` metadata block. Counts that cannot be read on this
+/// platform are left out.
+pub(crate) fn process_context() -> Value {
+    let mut context = Map::new();
+    if let Some(pid) = pid() {
+        context.insert("pid".to_string(), Value::from(pid));
+    }
+
+    if let Some(threads) = std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| parse_thread_count(&status))
+    {
+        context.insert("threads".to_string(), Value::from(threads));
+    }
+
+    if let Some(fds) = count_open_fds("/proc/self/fd") {
+        context.insert("open_fds".to_string(), Value::from(fds));
+    }
+
+    Value::Object(context)
+}
+
+/// Build the `runtime` metadata block: OS, architecture, hostname (when it
+/// can be found), pid and SDK version. None of these change while the
+/// process runs, so it is built once per client.
+pub(crate) fn runtime_context() -> Value {
+    let mut context = Map::new();
+    context.insert("os".to_string(), Value::from(std::env::consts::OS));
+    context.insert("arch".to_string(), Value::from(std::env::consts::ARCH));
+    if let Some(hostname) = hostname() {
+        context.insert("hostname".to_string(), Value::from(hostname));
+    }
+    if let Some(pid) = pid() {
+        context.insert("pid".to_string(), Value::from(pid));
+    }
+    context.insert(
+        "sdk_version".to_string(),
+        Value::from(env!("CARGO_PKG_VERSION")),
+    );
+    Value::Object(context)
+}
+
+/// `HOSTNAME`, or the kernel's hostname on Linux.
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Read the `Threads:` line of a `/proc/<pid>/status` file.
+fn parse_thread_count(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+/// Count the descriptors listed in `dir`, less the one the listing itself
+/// holds open while it runs.
+fn count_open_fds(dir: &str) -> Option<usize> {
+    std::fs::read_dir(dir)
+        .ok()
+        .map(|entries| entries.count().saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_thread_count() {
+        let status =
+            "Name:\tserver\nState:\tS (sleeping)\nTgid:\t4242\nThreads:\t17\nVmRSS:\t1024 kB\n";
+        assert_eq!(parse_thread_count(status), Some(17));
+        assert_eq!(parse_thread_count("Name:\tserver\n"), None);
+    }
+
+    #[test]
+    fn test_count_open_fds() {
+        let dir = std::env::temp_dir().join(format!("pulsekit-fds-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // stdin, stdout and stderr, plus the descriptor reading the listing
+        for name in ["0", "1", "2", "3"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        assert_eq!(count_open_fds(dir.to_str().unwrap()), Some(3));
+        assert_eq!(count_open_fds("/definitely/not/here"), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_runtime_context_is_attached_to_events() {
+        let client = crate::PulseKit::new(Default::default());
+        client.capture_message("hello", crate::Level::Info);
+
+        let metadata = client.delivery.take_events().remove(0).metadata.unwrap();
+        let runtime = &metadata["runtime"];
+        assert_eq!(runtime["os"], std::env::consts::OS);
+        assert_eq!(runtime["arch"], std::env::consts::ARCH);
+        assert_eq!(runtime["pid"], std::process::id());
+        assert_eq!(runtime["sdk_version"], env!("CARGO_PKG_VERSION"));
+
+        let client = crate::PulseKit::new(crate::Config {
+            capture_runtime_context: false,
+            ..Default::default()
+        });
+        client.capture_message("hello", crate::Level::Info);
+        let metadata = client.delivery.take_events().remove(0).metadata.unwrap();
+        assert!(!metadata.contains_key("runtime"));
+    }
+
+    #[test]
+    fn test_process_context_has_pid() {
+        let context = process_context();
+        assert_eq!(context["pid"], std::process::id());
+        #[cfg(target_os = "linux")]
+        assert!(context["threads"].as_u64().unwrap() >= 1);
+    }
+}
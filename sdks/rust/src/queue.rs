@@ -0,0 +1,591 @@
+//! The event queue, owned by a worker thread.
+//!
+//! Captures hand their events to the worker over a channel, so they never
+//! wait on a lock a flush holds or on the network. The worker adds them to
+//! the queue, keeps offline storage in line with it and sends the queue
+//! once a batch is full. Flushes ask it for the events to send and hand
+//! back the ones to retry.
+//!
+//! wasm32 has no threads to run the worker on, so there whoever sends it a
+//! command runs it, and a full batch is sent on a task.
+
+use crate::debug_log::debug_log;
+use crate::delivery::{Delivery, QueueOverflow, Queued};
+use crate::offline::OfflineStore;
+use crate::Config;
+#[cfg(not(target_arch = "wasm32"))]
+use std::cell::RefCell;
+#[cfg(not(target_arch = "wasm32"))]
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Weak};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+
+/// The client's side of the queue.
+pub(crate) struct Queue {
+    commands: mpsc::Sender<Command>,
+    #[cfg(not(target_arch = "wasm32"))]
+    worker: thread::ThreadId,
+    /// The worker and the commands it hasn't read yet
+    #[cfg(target_arch = "wasm32")]
+    worker: parking_lot::Mutex<(Worker, mpsc::Receiver<Command>)>,
+    /// Events queued or on their way to the worker. Captures count their
+    /// event in as they send it, so the length is known without asking.
+    len: Arc<AtomicUsize>,
+    max_len: usize,
+}
+
+enum Command {
+    /// Queue a captured event; `fits` is false if it found the queue full
+    Push { queued: Box<Queued>, fits: bool },
+    /// Hand over the whole queue, bringing offline storage in line with
+    /// the empty queue if `sync_offline` is set
+    Take {
+        sync_offline: bool,
+        reply: Reply<Vec<Queued>>,
+    },
+    /// Hand over the oldest events that fill whole batches of `size`
+    TakeBatches {
+        size: usize,
+        reply: Reply<Vec<Queued>>,
+    },
+    /// Put events back at the front of the queue
+    PutBack(Vec<Queued>),
+    /// Put events back at the front of the queue and bring offline storage
+    /// in line with it. Those past `limit` are handed back.
+    Restore {
+        queued: Vec<Queued>,
+        limit: Option<usize>,
+        reply: Reply<Vec<Queued>>,
+    },
+    /// Stop sending full batches, once the one in progress is sent
+    Stop(Reply<()>),
+}
+
+/// Where the worker sends the answer to a command.
+enum Reply<T> {
+    Blocking(mpsc::SyncSender<T>),
+    #[cfg(feature = "async")]
+    Async(tokio::sync::oneshot::Sender<T>),
+}
+
+impl<T> Reply<T> {
+    /// Hands `value` back if the caller has stopped waiting for it.
+    fn send(self, value: T) -> Result<(), T> {
+        match self {
+            Reply::Blocking(sender) => sender.send(value).map_err(|e| e.0),
+            #[cfg(feature = "async")]
+            Reply::Async(sender) => sender.send(value),
+        }
+    }
+}
+
+impl Queue {
+    /// Start the worker with the events restored from offline storage.
+    pub(crate) fn spawn(
+        delivery: Weak<Delivery>,
+        config: &Config,
+        offline: Option<OfflineStore>,
+        restored: Vec<Queued>,
+    ) -> Queue {
+        let (commands, received) = mpsc::channel();
+        let len = Arc::new(AtomicUsize::new(restored.len()));
+        let worker = Worker {
+            events: restored,
+            len: len.clone(),
+            config: config.clone(),
+            offline,
+            delivery,
+            stopped: false,
+            evicted_offline: Vec::new(),
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let worker = thread::spawn(move || worker.run(received)).thread().id();
+        #[cfg(target_arch = "wasm32")]
+        let worker = parking_lot::Mutex::new((worker, received));
+        Queue {
+            commands,
+            worker,
+            len,
+            max_len: config.max_queue_size,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Count an event in ahead of [`Queue::push`]. Returns false if the
+    /// queue is full, in which case it isn't counted.
+    pub(crate) fn reserve(&self) -> bool {
+        self.len
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |len| {
+                (len < self.max_len).then_some(len + 1)
+            })
+            .is_ok()
+    }
+
+    /// Queue an event, applying `queue_overflow` if [`Queue::reserve`]
+    /// found the queue full.
+    pub(crate) fn push(&self, queued: Queued, fits: bool) {
+        self.send(Command::Push {
+            queued: Box::new(queued),
+            fits,
+        });
+    }
+
+    /// Queue an event past `max_queue_size` rather than displace another.
+    pub(crate) fn push_unbounded(&self, queued: Queued) {
+        self.len.fetch_add(1, Ordering::AcqRel);
+        self.push(queued, true);
+    }
+
+    /// Take the whole queue, in the order the events were queued.
+    pub(crate) fn take(&self) -> Vec<Queued> {
+        self.request(|reply| Command::Take {
+            sync_offline: false,
+            reply,
+        })
+        .unwrap_or_default()
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) async fn take_async(&self) -> Vec<Queued> {
+        self.request_async(|reply| Command::Take {
+            sync_offline: false,
+            reply,
+        })
+        .await
+        .unwrap_or_default()
+    }
+
+    /// [`Queue::take`], also removing the events from offline storage.
+    pub(crate) fn drain(&self) -> Vec<Queued> {
+        self.request(|reply| Command::Take {
+            sync_offline: true,
+            reply,
+        })
+        .unwrap_or_default()
+    }
+
+    /// Take the oldest events that fill whole batches of `size`; none if
+    /// there isn't a full batch.
+    pub(crate) fn take_batches(&self, size: usize) -> Vec<Queued> {
+        self.request(|reply| Command::TakeBatches { size, reply })
+            .unwrap_or_default()
+    }
+
+    /// Put taken events back at the front of the queue, ahead of those
+    /// queued since.
+    pub(crate) fn put_back(&self, queued: Vec<Queued>) {
+        if !queued.is_empty() {
+            self.len.fetch_add(queued.len(), Ordering::AcqRel);
+            self.send(Command::PutBack(queued));
+        }
+    }
+
+    /// [`Queue::put_back`], then bring offline storage in line with the
+    /// queue so sent events are removed from it. Events that would take
+    /// the queue past `limit` are returned instead.
+    pub(crate) fn restore(&self, queued: Vec<Queued>, limit: Option<usize>) -> Vec<Queued> {
+        self.request(|reply| Command::Restore {
+            queued,
+            limit,
+            reply,
+        })
+        .unwrap_or_default()
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) async fn restore_async(&self, queued: Vec<Queued>) {
+        self.request_async(|reply| Command::Restore {
+            queued,
+            limit: None,
+            reply,
+        })
+        .await;
+    }
+
+    /// Stop sending full batches, waiting for one that is being sent.
+    pub(crate) fn stop(&self) {
+        self.request(Command::Stop);
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) async fn stop_async(&self) {
+        self.request_async(Command::Stop).await;
+    }
+
+    fn request<T>(&self, command: impl FnOnce(Reply<T>) -> Command) -> Option<T> {
+        let (reply, response) = mpsc::sync_channel(1);
+        self.send(command(Reply::Blocking(reply)));
+        // On wasm32 the command has been carried out by now, unless a
+        // callback the worker runs sent it, which can't wait for it.
+        if cfg!(target_arch = "wasm32") {
+            response.try_recv().ok()
+        } else {
+            response.recv().ok()
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn request_async<T>(&self, command: impl FnOnce(Reply<T>) -> Command) -> Option<T> {
+        let (reply, response) = tokio::sync::oneshot::channel();
+        self.send(command(Reply::Async(reply)));
+        response.await.ok()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn send(&self, command: Command) {
+        // From a callback the worker runs, such as an ack: it isn't reading
+        // the channel until that returns, so the command is handled here.
+        // Unless it comes from a diagnostic logged while the queue is in
+        // use further up the stack, which can only queue an event.
+        let here = thread::current().id() == self.worker
+            && WORKER.with(|worker| worker.try_borrow_mut().is_ok());
+        if here {
+            dispatch(command);
+        } else {
+            let _ = self.commands.send(command);
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+        // From a callback the worker runs, the command is left for the call
+        // running it, further up the stack, to read once the callback
+        // returns.
+        while let Some(mut guard) = self.worker.try_lock() {
+            let (worker, received) = &mut *guard;
+            let mut evicted = Vec::new();
+            let mut full = false;
+            for command in received.try_iter() {
+                let (dropped, batch) = worker.handle(command);
+                evicted.extend(dropped);
+                full |= batch;
+            }
+            worker.remove_evicted_offline();
+            let delivery = worker.delivery.clone();
+            drop(guard);
+
+            if evicted.is_empty() && !full {
+                return;
+            }
+            with_delivery(&delivery, |delivery| {
+                for queued in evicted {
+                    delivery.drop_overflow(queued);
+                }
+                if full {
+                    delivery.send_full_queue();
+                }
+            });
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+thread_local! {
+    /// The queue, on its worker's thread.
+    static WORKER: RefCell<Option<Worker>> = const { RefCell::new(None) };
+}
+
+struct Worker {
+    events: Vec<Queued>,
+    len: Arc<AtomicUsize>,
+    config: Config,
+    offline: Option<OfflineStore>,
+    delivery: Weak<Delivery>,
+    /// Set once the last client handle is gone; the final flush is left to
+    /// the thread dropping it
+    stopped: bool,
+    /// Offline storage lines of queued events evicted to make room, removed
+    /// from the file once a burst has been taken in
+    evicted_offline: Vec<String>,
+}
+
+impl Worker {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn run(self, received: mpsc::Receiver<Command>) {
+        let delivery = self.delivery.clone();
+        let batch_size = self.config.batch_size.max(1);
+        WORKER.with(|worker| *worker.borrow_mut() = Some(self));
+
+        while let Ok(command) = received.recv() {
+            let mut full = dispatch(command);
+            // Take in what else has arrived, so a burst of captures goes out
+            // as one flush.
+            for command in received.try_iter().take(batch_size) {
+                full |= dispatch(command);
+            }
+            WORKER.with(|worker| {
+                if let Some(worker) = worker.borrow_mut().as_mut() {
+                    worker.remove_evicted_offline();
+                }
+            });
+            if full {
+                with_delivery(&delivery, |delivery| {
+                    // A panicking callback loses its batch, not the queue.
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| delivery.send_full_queue()));
+                });
+            }
+        }
+        WORKER.with(|worker| worker.borrow_mut().take());
+    }
+
+    /// Carry out `command`. Returns an event evicted to make room, and
+    /// whether the queue now holds a full batch to send.
+    fn handle(&mut self, command: Command) -> (Option<Queued>, bool) {
+        if !matches!(command, Command::Push { .. }) {
+            // Whoever is waiting on a reply sees the file match the queue.
+            self.remove_evicted_offline();
+        }
+        match command {
+            Command::Push { queued, fits } => {
+                let (kept, evicted) = if fits {
+                    (Some(queued), None)
+                } else {
+                    match self.config.queue_overflow {
+                        QueueOverflow::DropOldest if !self.events.is_empty() => {
+                            (Some(queued), Some(self.events.remove(0)))
+                        }
+                        QueueOverflow::DropNewest if !self.events.is_empty() => {
+                            (Some(queued), self.events.pop())
+                        }
+                        _ => (None, Some(*queued)),
+                    }
+                };
+                // A queued event that was evicted is already in the file,
+                // unlike an incoming one that was never kept.
+                if let (Some(_), Some(evicted), Some(_)) = (&kept, &evicted, &self.offline) {
+                    match OfflineStore::line(&evicted.event) {
+                        Ok(line) => self.evicted_offline.push(line),
+                        Err(e) => debug_log!(
+                            self.config,
+                            Error,
+                            "Evicted event not removed from offline storage: {}",
+                            e
+                        ),
+                    }
+                }
+                if let Some(queued) = kept {
+                    self.append_offline(&queued);
+                    self.events.push(*queued);
+                }
+                let full = !self.stopped && self.events.len() >= self.config.batch_size;
+                return (evicted, full);
+            }
+            Command::Take {
+                sync_offline,
+                reply,
+            } => {
+                let taken = std::mem::take(&mut self.events);
+                self.len.fetch_sub(taken.len(), Ordering::AcqRel);
+                if sync_offline {
+                    self.sync_offline();
+                }
+                if let Err(taken) = reply.send(taken) {
+                    self.put_back(taken);
+                }
+            }
+            Command::TakeBatches { size, reply } => {
+                let full = self.events.len() - self.events.len() % size.max(1);
+                let taken: Vec<Queued> = self.events.drain(..full).collect();
+                self.len.fetch_sub(taken.len(), Ordering::AcqRel);
+                if let Err(taken) = reply.send(taken) {
+                    self.put_back(taken);
+                }
+            }
+            Command::PutBack(queued) => {
+                self.events.splice(0..0, queued);
+            }
+            Command::Restore {
+                mut queued,
+                limit,
+                reply,
+            } => {
+                let room = limit.map_or(queued.len(), |limit| {
+                    limit.saturating_sub(self.len.load(Ordering::Acquire))
+                });
+                let over = queued.split_off(room.min(queued.len()));
+                self.put_back(queued);
+                self.sync_offline();
+                let _ = reply.send(over);
+            }
+            Command::Stop(reply) => {
+                self.stopped = true;
+                let _ = reply.send(());
+            }
+        }
+        (None, false)
+    }
+
+    fn put_back(&mut self, queued: Vec<Queued>) {
+        self.len.fetch_add(queued.len(), Ordering::AcqRel);
+        self.events.splice(0..0, queued);
+    }
+
+    fn append_offline(&self, queued: &Queued) {
+        if let Some(offline) = &self.offline {
+            if let Err(e) = offline.append(&queued.event) {
+                debug_log!(
+                    self.config,
+                    Error,
+                    "Event not written to offline storage: {}",
+                    e
+                );
+            }
+        }
+    }
+
+    fn remove_evicted_offline(&mut self) {
+        if self.evicted_offline.is_empty() {
+            return;
+        }
+        let evicted = std::mem::take(&mut self.evicted_offline);
+        if let Some(offline) = &self.offline {
+            if let Err(e) = offline.remove(&evicted) {
+                debug_log!(
+                    self.config,
+                    Error,
+                    "Failed to update offline storage: {}",
+                    e
+                );
+            }
+        }
+    }
+
+    fn sync_offline(&self) {
+        if let Some(offline) = &self.offline {
+            if let Err(e) = offline.replace(self.events.iter().map(|q| &q.event)) {
+                debug_log!(
+                    self.config,
+                    Error,
+                    "Failed to update offline storage: {}",
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Carry out `command` on this thread's queue, returning whether a full
+/// batch is waiting.
+#[cfg(not(target_arch = "wasm32"))]
+fn dispatch(command: Command) -> bool {
+    let (evicted, full, delivery) = WORKER.with(|worker| {
+        let mut worker = worker.borrow_mut();
+        let worker = worker.as_mut().expect("queue used off its worker thread");
+        let (evicted, full) = worker.handle(command);
+        (evicted, full, worker.delivery.clone())
+    });
+    if let Some(evicted) = evicted {
+        with_delivery(&delivery, |delivery| delivery.drop_overflow(evicted));
+    }
+    full
+}
+
+/// Run `f` if the client is still around. Should the last handle go while
+/// it runs, the client is dropped on another thread, as its final flush
+/// needs this one to hand over the queue. On wasm32 it's dropped here,
+/// as there is neither another thread nor a blocking final flush.
+fn with_delivery(delivery: &Weak<Delivery>, f: impl FnOnce(&Arc<Delivery>)) {
+    let Some(delivery) = delivery.upgrade() else {
+        return;
+    };
+    f(&delivery);
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(delivery) = Arc::into_inner(delivery) {
+        thread::spawn(move || drop(delivery));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Config, Event, Level, MemoryTransport, PulseKit, PulseKitError, Transport,
+        TransportRequest, TransportResponse,
+    };
+    use parking_lot::Mutex;
+    use std::sync::{mpsc, Arc};
+    use std::time::{Duration, Instant};
+
+    /// Holds each send until the test lets it through.
+    struct GatedTransport {
+        gate: Mutex<mpsc::Receiver<()>>,
+        inner: MemoryTransport,
+    }
+
+    impl Transport for GatedTransport {
+        fn send(&self, request: &TransportRequest) -> Result<TransportResponse, PulseKitError> {
+            let _ = self.gate.lock().recv_timeout(Duration::from_secs(5));
+            self.inner.send(request)
+        }
+    }
+
+    #[test]
+    fn test_captures_do_not_wait_for_a_batch_being_sent() {
+        let (open, gate) = mpsc::channel();
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                batch_size: 1,
+                ..Default::default()
+            },
+            GatedTransport {
+                gate: Mutex::new(gate),
+                inner: transport.clone(),
+            },
+        );
+
+        client.capture_message("first", Level::Info);
+        let started = Instant::now();
+        for i in 0..100 {
+            client.capture_message(&format!("while sending {}", i), Level::Info);
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        for _ in 0..101 {
+            open.send(()).unwrap();
+        }
+        client.flush_blocking();
+        assert_eq!(transport.events().len(), 101);
+    }
+
+    #[test]
+    fn test_callbacks_run_by_the_worker_can_use_the_client() {
+        let transport = MemoryTransport::new();
+        let client = Arc::new(PulseKit::with_transport(
+            Config {
+                batch_size: 2,
+                ..Default::default()
+            },
+            transport.clone(),
+        ));
+        let (sender, receiver) = mpsc::channel();
+
+        let handle = client.clone();
+        client.capture_with_ack(
+            Event {
+                event_type: "audit.login".to_string(),
+                ..Default::default()
+            },
+            move |result| {
+                handle.capture_message("from the ack", Level::Info);
+                let outcome = handle.flush_blocking();
+                sender.send((result, outcome.sent)).unwrap();
+            },
+        );
+        client.capture_message("fills the batch", Level::Info);
+
+        let (result, sent) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(sent, 1);
+        assert_eq!(transport.events().len(), 3);
+    }
+}
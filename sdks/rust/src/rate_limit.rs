@@ -0,0 +1,132 @@
+//! Pausing sends while the server asks clients to back off.
+
+use crate::clock::Clock;
+use crate::time::{Duration, Instant};
+use crate::transport::TransportResponse;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// When sending may resume, set by a `429` response's `Retry-After` header.
+pub(crate) struct RateLimit {
+    paused_until: Mutex<Option<Instant>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RateLimit {
+    pub(crate) fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            paused_until: Mutex::new(None),
+            clock,
+        }
+    }
+
+    /// Pause sending if `response` is a `429` with a `Retry-After` header.
+    /// A later pause already in effect is kept.
+    pub(crate) fn observe(&self, response: &TransportResponse) {
+        if response.status != 429 {
+            return;
+        }
+        let Some(delay) = response
+            .header("Retry-After")
+            .and_then(|value| parse_retry_after(value, self.clock.now()))
+        else {
+            return;
+        };
+        let until = self.clock.instant() + delay;
+        let mut paused_until = self.paused_until.lock();
+        if paused_until.is_none_or(|current| current < until) {
+            *paused_until = Some(until);
+        }
+    }
+
+    /// How long sending stays paused, or `None` if it isn't.
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        let paused_until = (*self.paused_until.lock())?;
+        let remaining = paused_until.saturating_duration_since(self.clock.instant());
+        (!remaining.is_zero()).then_some(remaining)
+    }
+}
+
+/// Read a `Retry-After` value, either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - now).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use crate::{Config, Level, PulseKit, PulseKitError};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_retry_after_accepts_seconds_and_http_dates() {
+        let now = DateTime::parse_from_rfc2822("Sun, 06 Nov 1994 08:49:37 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:50:07 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:49:00 GMT", now),
+            None
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_429_pauses_sending_until_retry_after_passes() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let server = MockServer::with_handler({
+            let requests = requests.clone();
+            move |_| {
+                if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                    MockResponse::status(429).header("Retry-After", "2")
+                } else {
+                    MockResponse::status(200)
+                }
+            }
+        });
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            ..Default::default()
+        });
+
+        let started = Instant::now();
+        client.capture_message("first", Level::Error);
+        let outcome = client.flush_blocking();
+        assert_eq!(outcome.requeued, 1);
+
+        client.capture_message("second", Level::Error);
+        while server.requests().len() < 2 {
+            let outcome = client.flush_blocking();
+            if server.requests().len() < 2 {
+                assert!(matches!(
+                    outcome.first_error,
+                    Some(PulseKitError::RateLimited(_))
+                ));
+                assert_eq!(outcome.requeued, 2);
+            }
+            assert!(started.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        assert!(started.elapsed() >= Duration::from_millis(1900));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].events().len(), 2);
+        assert!(client.delivery.queue.is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+//! The inbound HTTP request an error happened while handling.
+
+use crate::PulseKit;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Metadata key the request is sent under.
+pub(crate) const REQUEST_KEY: &str = "request";
+
+/// Headers that are never sent, matched case-insensitively.
+const DENIED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "x-auth-token",
+    "x-csrf-token",
+    "x-pulsekit-key",
+];
+
+/// Query parameters whose names contain one of these are sent as
+/// `[Filtered]`.
+const SENSITIVE_PARAMS: &[&str] = &[
+    "token",
+    "secret",
+    "password",
+    "passwd",
+    "api_key",
+    "apikey",
+    "signature",
+    "auth",
+    "session",
+];
+
+/// An inbound HTTP request, sent as the `request` metadata object by
+/// [`PulseKit::capture_error_with_request`].
+///
+/// Credentials are left out when it is sent: `authorization`, `cookie` and
+/// similar headers are dropped and query parameters such as `token` or
+/// `password` are replaced by `[Filtered]`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestContext {
+    /// HTTP method, e.g. `GET`
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    /// Query string without the leading `?`
+    pub query: Option<String>,
+    /// Header values by lowercase name
+    pub headers: BTreeMap<String, String>,
+    /// Address of the client that sent the request
+    pub client_ip: Option<String>,
+}
+
+/// The shape a [`RequestContext`] is sent in.
+#[derive(Serialize)]
+struct SentRequest<'a> {
+    method: &'a str,
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<&'a str, &'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_ip: Option<&'a str>,
+}
+
+impl RequestContext {
+    pub fn new(method: impl Into<String>, path: impl Into<String>) -> Self {
+        Self {
+            method: method.into(),
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+
+    /// The request with credentials removed, as sent.
+    pub(crate) fn to_value(&self) -> serde_json::Value {
+        let headers = self
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                !DENIED_HEADERS
+                    .iter()
+                    .any(|denied| name.eq_ignore_ascii_case(denied))
+            })
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        serde_json::to_value(SentRequest {
+            method: &self.method,
+            path: &self.path,
+            query: self.query.as_deref().map(scrub_query),
+            headers,
+            client_ip: self.client_ip.as_deref(),
+        })
+        .unwrap_or_default()
+    }
+}
+
+/// Replace the values of sensitive query parameters with `[Filtered]`.
+fn scrub_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if is_sensitive(name) => format!("{}=[Filtered]", name),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn is_sensitive(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SENSITIVE_PARAMS.iter().any(|param| name.contains(param))
+}
+
+/// The first address in `X-Forwarded-For`, or `X-Real-IP`.
+#[cfg(feature = "http")]
+fn forwarded_ip(headers: &BTreeMap<String, String>) -> Option<String> {
+    let forwarded = headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.split(',').next())
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty());
+    forwarded
+        .or_else(|| headers.get("x-real-ip").map(|ip| ip.trim()))
+        .map(str::to_string)
+}
+
+#[cfg(feature = "http")]
+impl RequestContext {
+    /// Read the method, path, query and headers. Repeated headers are
+    /// joined with `", "`, and the client IP is taken from
+    /// `X-Forwarded-For` or `X-Real-IP`.
+    fn from_head(method: &http::Method, uri: &http::Uri, header_map: &http::HeaderMap) -> Self {
+        let mut headers: BTreeMap<String, String> = BTreeMap::new();
+        for (name, value) in header_map {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            headers
+                .entry(name.as_str().to_string())
+                .and_modify(|joined| {
+                    joined.push_str(", ");
+                    joined.push_str(&value);
+                })
+                .or_insert_with(|| value.into_owned());
+        }
+        Self {
+            method: method.to_string(),
+            path: uri.path().to_string(),
+            query: uri.query().map(str::to_string),
+            client_ip: forwarded_ip(&headers),
+            headers,
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+impl From<&http::request::Parts> for RequestContext {
+    fn from(parts: &http::request::Parts) -> Self {
+        Self::from_head(&parts.method, &parts.uri, &parts.headers)
+    }
+}
+
+#[cfg(feature = "http")]
+impl<B> From<&http::Request<B>> for RequestContext {
+    fn from(request: &http::Request<B>) -> Self {
+        Self::from_head(request.method(), request.uri(), request.headers())
+    }
+}
+
+impl PulseKit {
+    /// Capture an error with the request being handled as the `request`
+    /// metadata object: method, path, query, headers and client IP, with
+    /// credentials removed as described on [`RequestContext`].
+    pub fn capture_error_with_request(&self, message: &str, request: &RequestContext) {
+        let metadata = HashMap::from([(REQUEST_KEY.to_string(), request.to_value())]);
+        self.capture_error_with_options(message, None, Some(metadata));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use serde_json::json;
+
+    #[test]
+    fn test_request_is_sent_without_credentials() {
+        let client = PulseKit::new(Config::default());
+        let mut request = RequestContext::new("POST", "/checkout");
+        request.query = Some("step=2&access_token=abc123&SessionId=s1&flag".to_string());
+        request.client_ip = Some("203.0.113.7".to_string());
+        for (name, value) in [
+            ("content-type", "application/json"),
+            ("Authorization", "Bearer abc123"),
+            ("cookie", "sid=s1"),
+            ("user-agent", "curl/8.0"),
+        ] {
+            request.headers.insert(name.to_string(), value.to_string());
+        }
+
+        client.capture_error_with_request("checkout failed", &request);
+
+        let events = client.delivery.take_events();
+        assert_eq!(
+            events[0].metadata.as_ref().unwrap()[REQUEST_KEY],
+            json!({
+                "method": "POST",
+                "path": "/checkout",
+                "query": "step=2&access_token=[Filtered]&SessionId=[Filtered]&flag",
+                "headers": {
+                    "content-type": "application/json",
+                    "user-agent": "curl/8.0",
+                },
+                "client_ip": "203.0.113.7",
+            })
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_context_from_http_request() {
+        let request = http::Request::builder()
+            .method("GET")
+            .uri("https://shop.example/orders/7?page=1&token=t")
+            .header("accept", "text/html")
+            .header("accept", "application/json")
+            .header("x-forwarded-for", "198.51.100.4, 10.0.0.1")
+            .header("cookie", "sid=s1")
+            .body(())
+            .unwrap();
+
+        let context = RequestContext::from(&request);
+        assert_eq!(context.method, "GET");
+        assert_eq!(context.path, "/orders/7");
+        assert_eq!(context.query.as_deref(), Some("page=1&token=t"));
+        assert_eq!(context.headers["accept"], "text/html, application/json");
+        assert_eq!(context.client_ip.as_deref(), Some("198.51.100.4"));
+        assert_eq!(RequestContext::from(&request.into_parts().0), context);
+
+        let sent = context.to_value();
+        assert_eq!(sent["query"], "page=1&token=[Filtered]");
+        assert!(sent["headers"].get("cookie").is_none());
+    }
+}
@@ -0,0 +1,317 @@
+//! Random sampling of captured events.
+
+use crate::Event;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Reserved tag or metadata key that overrides sampling for one event:
+/// `"always"` keeps it whatever the rate, `"never"` drops it. The key is
+/// removed before the event is sent; a tag takes precedence over metadata.
+pub const SAMPLE_OVERRIDE_KEY: &str = "__pulsekit_sample";
+
+/// Sets the sample rate of the events it matches. Rules in
+/// `Config::sampling_rules` are tried in order and the first match wins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingRule {
+    /// Which events the rule applies to
+    pub matcher: SampleMatcher,
+    /// Fraction of matching events to send, from `0.0` to `1.0`
+    pub rate: f64,
+}
+
+/// What a [`SamplingRule`] looks at.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SampleMatcher {
+    /// Event type pattern, where `*` matches any run of characters, e.g.
+    /// `payment.*`
+    EventType(String),
+    /// Tag with this name and value
+    Tag { name: String, value: String },
+}
+
+impl SampleMatcher {
+    /// Whether `event` is matched.
+    pub fn matches(&self, event: &Event) -> bool {
+        match self {
+            SampleMatcher::EventType(pattern) => glob_match(pattern, &event.event_type),
+            SampleMatcher::Tag { name, value } => event
+                .tags
+                .as_ref()
+                .and_then(|tags| tags.get(name))
+                .is_some_and(|tag| tag == value),
+        }
+    }
+}
+
+/// The rate of the first rule in `rules` matching `event`.
+pub(crate) fn rule_rate(rules: &[SamplingRule], event: &Event) -> Option<f64> {
+    rules
+        .iter()
+        .find(|rule| rule.matcher.matches(event))
+        .map(|rule| rule.rate)
+}
+
+/// Match `text` against `pattern`, where `*` stands for any run of
+/// characters (including none).
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at.
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, tried)) = backtrack {
+            p = star + 1;
+            t = tried + 1;
+            backtrack = Some((star, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Remove [`SAMPLE_OVERRIDE_KEY`] from the event, returning whether it asks
+/// to keep (`Some(true)`) or drop (`Some(false)`) the event. Other values
+/// are ignored.
+pub(crate) fn take_override(event: &mut Event) -> Option<bool> {
+    let tag = event
+        .tags
+        .as_mut()
+        .and_then(|tags| tags.remove(SAMPLE_OVERRIDE_KEY));
+    let metadata = event
+        .metadata
+        .as_mut()
+        .and_then(|metadata| metadata.remove(SAMPLE_OVERRIDE_KEY))
+        .and_then(|value| value.as_str().map(str::to_string));
+    match tag.or(metadata)?.as_str() {
+        "always" => Some(true),
+        "never" => Some(false),
+        _ => None,
+    }
+}
+
+/// Decides which events survive `sample_rate`, using a SplitMix64 sequence
+/// so a fixed seed always keeps the same events.
+pub(crate) struct Sampler {
+    state: AtomicU64,
+}
+
+impl Sampler {
+    /// A sampler seeded with `seed`, or randomly when `None`.
+    pub(crate) fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| RandomState::new().build_hasher().finish());
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Whether an event sampled at `rate` is kept. Rates of `1.0` and above
+    /// keep everything, `0.0` and below (or NaN) nothing.
+    pub(crate) fn keep(&self, rate: f64) -> bool {
+        if rate >= 1.0 {
+            return true;
+        }
+        if rate.is_nan() || rate <= 0.0 {
+            return false;
+        }
+        let unit = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        unit < rate
+    }
+
+    fn next(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level, PulseKit};
+    use std::collections::HashMap;
+
+    fn sampled_client(sample_rate: f64) -> PulseKit {
+        PulseKit::new(Config {
+            sample_rate,
+            sample_seed: Some(7),
+            batch_size: 1000,
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_sample_rate_keeps_all_or_nothing_at_the_bounds() {
+        let none = sampled_client(0.0);
+        let all = sampled_client(1.0);
+        for client in [&none, &all] {
+            for _ in 0..100 {
+                client.capture_message("tick", Level::Info);
+            }
+        }
+        assert!(none.delivery.take_events().is_empty());
+        assert_eq!(none.stats().dropped_sampled, 100);
+        assert_eq!(all.delivery.take_events().len(), 100);
+
+        let seeded = |seed| Sampler::new(Some(seed));
+        let (first, second) = (seeded(1), seeded(1));
+        let kept: Vec<_> = (0..1000).map(|_| first.keep(0.25)).collect();
+        assert_eq!(
+            kept,
+            (0..1000).map(|_| second.keep(0.25)).collect::<Vec<_>>()
+        );
+        let count = kept.iter().filter(|kept| **kept).count();
+        assert!((150..350).contains(&count), "kept {}", count);
+    }
+
+    #[test]
+    fn test_event_type_rates_and_error_bypass() {
+        let client = PulseKit::new(Config {
+            sample_rate: 0.0,
+            sample_rates: HashMap::from([("checkout".to_string(), 1.0)]),
+            errors_bypass_sampling: true,
+            sample_seed: Some(7),
+            ..Default::default()
+        });
+
+        client.capture_message("dropped", Level::Info);
+        client.capture(crate::Event {
+            event_type: "checkout".to_string(),
+            ..Default::default()
+        });
+        client.capture_error("kept");
+
+        let mut types: Vec<_> = client
+            .delivery
+            .take_events()
+            .into_iter()
+            .map(|event| event.event_type)
+            .collect();
+        types.sort();
+        assert_eq!(types, ["checkout", "error"]);
+    }
+
+    #[test]
+    fn test_first_matching_rule_sets_the_rate() {
+        let client = PulseKit::new(Config {
+            sample_rate: 0.0,
+            sampling_rules: vec![
+                SamplingRule {
+                    matcher: SampleMatcher::EventType("payment.fail*".to_string()),
+                    rate: 1.0,
+                },
+                SamplingRule {
+                    matcher: SampleMatcher::EventType("payment.*".to_string()),
+                    rate: 0.0,
+                },
+                SamplingRule {
+                    matcher: SampleMatcher::Tag {
+                        name: "region".to_string(),
+                        value: "eu".to_string(),
+                    },
+                    rate: 1.0,
+                },
+            ],
+            default_tags: HashMap::from([("region".to_string(), "eu".to_string())]),
+            sample_seed: Some(7),
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..Default::default()
+        });
+        for event_type in ["payment.failed", "payment.retry", "signup"] {
+            client.capture(crate::Event {
+                event_type: event_type.to_string(),
+                ..Default::default()
+            });
+        }
+
+        let mut types: Vec<_> = client
+            .delivery
+            .take_events()
+            .into_iter()
+            .map(|event| event.event_type)
+            .collect();
+        types.sort();
+        assert_eq!(types, ["payment.failed", "signup"]);
+        assert!(glob_match("*.retry", "payment.retry"));
+        assert!(!glob_match("payment.*", "payments.retry"));
+    }
+
+    #[test]
+    fn test_unmatched_events_fall_back_to_the_sample_rate() {
+        let client = PulseKit::new(Config {
+            sample_rate: 0.0,
+            sampling_rules: vec![SamplingRule {
+                matcher: SampleMatcher::Tag {
+                    name: "tier".to_string(),
+                    value: "gold".to_string(),
+                },
+                rate: 1.0,
+            }],
+            sample_seed: Some(7),
+            ..Default::default()
+        });
+        client.capture_message("no tag", Level::Info);
+        client.capture_message_with_options(
+            "other tier",
+            Level::Info,
+            Some(HashMap::from([("tier".to_string(), "silver".to_string())])),
+            None,
+        );
+
+        assert!(client.delivery.take_events().is_empty());
+        assert_eq!(client.stats().dropped_sampled, 2);
+    }
+
+    #[test]
+    fn test_override_key_beats_the_sample_rate() {
+        let tagged = |value: &str| crate::Event {
+            event_type: value.to_string(),
+            tags: Some(HashMap::from([(
+                SAMPLE_OVERRIDE_KEY.to_string(),
+                value.to_string(),
+            )])),
+            ..Default::default()
+        };
+
+        let none = sampled_client(0.0);
+        none.capture(tagged("always"));
+        none.capture(crate::Event {
+            event_type: "metadata".to_string(),
+            metadata: Some(HashMap::from([(
+                SAMPLE_OVERRIDE_KEY.to_string(),
+                serde_json::json!("always"),
+            )])),
+            ..Default::default()
+        });
+        none.capture(tagged("sometimes"));
+        let mut kept = none.delivery.take_events();
+        kept.sort_by(|a, b| a.event_type.cmp(&b.event_type));
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].event_type, "always");
+        assert!(kept[0].tags.as_ref().unwrap().is_empty());
+        assert!(kept[1].metadata.as_ref().unwrap().is_empty());
+
+        let all = sampled_client(1.0);
+        all.capture(tagged("never"));
+        all.capture(tagged("sometimes"));
+        let kept = all.delivery.take_events();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].event_type, "sometimes");
+        assert_eq!(all.stats().dropped_sampled, 1);
+    }
+}
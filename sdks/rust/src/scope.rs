@@ -0,0 +1,478 @@
+//! User, tags and context set once on a client and added to every event.
+
+use crate::{Event, PulseKit};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Metadata key the scope's user is sent under.
+const USER_KEY: &str = "user";
+
+/// Metadata key the scope's feature flags are sent under.
+const FLAGS_KEY: &str = "flags";
+
+/// Most feature flags attached to an event; further flags are ignored,
+/// keeping the first in name order.
+const MAX_FEATURE_FLAGS: usize = 100;
+
+/// The user an event happened for.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Values merged into every event a client captures. Fields already set on
+/// the event win over the scope's.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scope {
+    user: Option<User>,
+    tags: HashMap<String, String>,
+    contexts: HashMap<String, serde_json::Value>,
+    flags: BTreeMap<String, String>,
+}
+
+impl Scope {
+    /// Set or clear the user, sent as the `user` metadata object.
+    pub fn set_user(&mut self, user: Option<User>) {
+        self.user = user;
+    }
+
+    pub fn set_tag(&mut self, key: &str, value: &str) {
+        self.tags.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn remove_tag(&mut self, key: &str) {
+        self.tags.remove(key);
+    }
+
+    /// Set a metadata entry, e.g. `server_name` or a `"runtime"` object.
+    pub fn set_context(&mut self, key: &str, value: impl Into<serde_json::Value>) {
+        self.contexts.insert(key.to_string(), value.into());
+    }
+
+    /// Replace the active feature flags, mapping flag name to the variant
+    /// served, sent as the `flags` metadata object. At most 100 are kept.
+    pub fn set_feature_flags(&mut self, flags: HashMap<String, String>) {
+        self.flags = flags.into_iter().collect();
+        while self.flags.len() > MAX_FEATURE_FLAGS {
+            self.flags.pop_last();
+        }
+    }
+
+    pub(crate) fn apply(&self, event: &mut Event) {
+        if !self.tags.is_empty() {
+            let tags = event.tags.get_or_insert_with(HashMap::new);
+            for (key, value) in &self.tags {
+                tags.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        if self.user.is_none() && self.contexts.is_empty() && self.flags.is_empty() {
+            return;
+        }
+        let metadata = event.metadata.get_or_insert_with(HashMap::new);
+        for (key, value) in &self.contexts {
+            metadata.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        if let Some(user) = &self.user {
+            metadata
+                .entry(USER_KEY.to_string())
+                .or_insert_with(|| serde_json::to_value(user).unwrap_or_default());
+        }
+        if !self.flags.is_empty() {
+            metadata
+                .entry(FLAGS_KEY.to_string())
+                .or_insert_with(|| serde_json::to_value(&self.flags).unwrap_or_default());
+        }
+    }
+}
+
+thread_local! {
+    /// Isolation scopes entered on this thread, innermost last, each with
+    /// the address of the delivery pipeline of the client that entered it.
+    static ISOLATION_SCOPES: RefCell<Vec<(usize, Scope)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Puts back the scope saved by `with_scope`, also when the closure panics.
+struct RestoreScope<'a> {
+    client: &'a PulseKit,
+    saved: Option<Scope>,
+}
+
+impl Drop for RestoreScope<'_> {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            self.client.configure_scope(|scope| *scope = saved);
+        }
+    }
+}
+
+/// Leaves the isolation scope entered by `with_isolation_scope`, also when
+/// the closure panics.
+struct LeaveIsolationScope {
+    entered: bool,
+}
+
+impl LeaveIsolationScope {
+    fn enter(owner: usize, scope: Scope) -> Self {
+        ISOLATION_SCOPES.with_borrow_mut(|scopes| scopes.push((owner, scope)));
+        Self { entered: true }
+    }
+
+    fn leave(mut self) -> Scope {
+        self.entered = false;
+        leave_isolation_scope()
+    }
+}
+
+impl Drop for LeaveIsolationScope {
+    fn drop(&mut self) {
+        if self.entered {
+            leave_isolation_scope();
+        }
+    }
+}
+
+fn leave_isolation_scope() -> Scope {
+    ISOLATION_SCOPES
+        .with_borrow_mut(Vec::pop)
+        .map(|(_, scope)| scope)
+        .unwrap_or_default()
+}
+
+/// Future returned by [`PulseKit::with_isolation_scope_async`].
+pub struct IsolationScoped<F> {
+    future: Pin<Box<F>>,
+    owner: usize,
+    scope: Option<Scope>,
+}
+
+impl<F: Future> Future for IsolationScoped<F> {
+    type Output = F::Output;
+
+    /// Enter the future's isolation scope for the length of each poll, so
+    /// it follows the future across threads.
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let scope = this.scope.take().unwrap_or_default();
+        let entered = LeaveIsolationScope::enter(this.owner, scope);
+        let poll = this.future.as_mut().poll(cx);
+        this.scope = Some(entered.leave());
+        poll
+    }
+}
+
+impl PulseKit {
+    /// Identifies this client's scopes, shared with its sub-clients.
+    fn scope_owner(&self) -> usize {
+        Arc::as_ptr(&self.delivery) as usize
+    }
+
+    /// Run `configure` on the innermost isolation scope this client entered
+    /// on the current thread, or on the client-wide scope outside of one.
+    fn configure_scope<R>(&self, configure: impl FnOnce(&mut Scope) -> R) -> R {
+        let owner = self.scope_owner();
+        ISOLATION_SCOPES.with_borrow_mut(|scopes| {
+            match scopes
+                .iter_mut()
+                .rev()
+                .find(|(entered_by, _)| *entered_by == owner)
+            {
+                Some((_, scope)) => configure(scope),
+                None => configure(&mut self.delivery.scope.lock()),
+            }
+        })
+    }
+
+    /// A new isolation scope starting from the enclosing one, if any.
+    pub(crate) fn fork_isolation_scope(&self) -> Scope {
+        let owner = self.scope_owner();
+        ISOLATION_SCOPES.with_borrow(|scopes| {
+            scopes
+                .iter()
+                .rev()
+                .find(|(entered_by, _)| *entered_by == owner)
+                .map(|(_, scope)| scope.clone())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Merge the active isolation scope, then the client-wide scope, into
+    /// `event`. Values already on the event win.
+    pub(crate) fn apply_scopes(&self, event: &mut Event) {
+        let owner = self.scope_owner();
+        ISOLATION_SCOPES.with_borrow(|scopes| {
+            if let Some((_, scope)) = scopes
+                .iter()
+                .rev()
+                .find(|(entered_by, _)| *entered_by == owner)
+            {
+                scope.apply(event);
+            }
+        });
+        self.delivery.scope.lock().apply(event);
+    }
+
+    /// Set or clear the user attached to every event. The scope is shared
+    /// with sub-clients; inside [`PulseKit::with_isolation_scope`] only the
+    /// isolation scope changes.
+    pub fn set_user(&self, user: Option<User>) {
+        self.configure_scope(|scope| scope.set_user(user));
+    }
+
+    /// Add a tag to every event; tags passed to `capture` take precedence.
+    pub fn set_tag(&self, key: &str, value: &str) {
+        self.configure_scope(|scope| scope.set_tag(key, value));
+    }
+
+    pub fn remove_tag(&self, key: &str) {
+        self.configure_scope(|scope| scope.remove_tag(key));
+    }
+
+    /// Add a metadata entry to every event; metadata passed to `capture`
+    /// takes precedence.
+    pub fn set_context(&self, key: &str, value: impl Into<serde_json::Value>) {
+        self.configure_scope(|scope| scope.set_context(key, value));
+    }
+
+    /// Attach these feature flag variants to every event, replacing any set
+    /// before, to break errors down by the variant users saw. Use
+    /// [`Scope::set_feature_flags`] inside `with_scope` to set them for a
+    /// single request or job.
+    pub fn set_feature_flags(&self, flags: HashMap<String, String>) {
+        self.configure_scope(|scope| scope.set_feature_flags(flags));
+    }
+
+    /// Run `f` in a fresh scope of its own, e.g. for one request of a
+    /// server. `set_user`, `set_tag` and the other scope setters called on
+    /// this thread while `f` runs change only that scope, which is
+    /// discarded when `f` returns.
+    ///
+    /// Events captured inside get the isolation scope's values, then the
+    /// client-wide scope's; values set on the event win over both. A nested
+    /// isolation scope starts as a copy of the enclosing one. Use
+    /// [`PulseKit::with_isolation_scope_async`] for futures, which may move
+    /// between threads.
+    pub fn with_isolation_scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        let _leave = LeaveIsolationScope::enter(self.scope_owner(), self.fork_isolation_scope());
+        f()
+    }
+
+    /// [`PulseKit::with_isolation_scope`] for a future: the isolation scope
+    /// is active whenever the future is polled, on whichever thread.
+    pub fn with_isolation_scope_async<F: Future>(&self, future: F) -> IsolationScoped<F> {
+        self.isolate(future, self.fork_isolation_scope())
+    }
+
+    /// Run `future` in the isolation scope `scope`.
+    pub(crate) fn isolate<F: Future>(&self, future: F, scope: Scope) -> IsolationScoped<F> {
+        IsolationScoped {
+            future: Box::pin(future),
+            owner: self.scope_owner(),
+            scope: Some(scope),
+        }
+    }
+
+    /// Run `f` with the additions made by `configure`, then restore the
+    /// scope as it was.
+    ///
+    /// Outside an isolation scope the scope is client-wide, so events
+    /// captured by other threads while `f` runs see the additions too, and
+    /// scope changes they make in the meantime are undone along with them.
+    ///
+    /// ```no_run
+    /// # let client = pulsekit::PulseKit::new(Default::default());
+    /// client.with_scope(
+    ///     |scope| scope.set_tag("job", "reindex"),
+    ///     || client.capture_message("job started", pulsekit::Level::Info),
+    /// );
+    /// ```
+    pub fn with_scope<R>(&self, configure: impl FnOnce(&mut Scope), f: impl FnOnce() -> R) -> R {
+        let saved = self.configure_scope(|scope| {
+            let saved = scope.clone();
+            configure(scope);
+            saved
+        });
+        let _restore = RestoreScope {
+            client: self,
+            saved: Some(saved),
+        };
+        f()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level};
+    use serde_json::json;
+
+    #[test]
+    fn test_scope_is_merged_and_with_scope_rolls_back() {
+        let client = PulseKit::new(Config::default());
+        client.set_user(Some(User {
+            id: Some("42".to_string()),
+            ..Default::default()
+        }));
+        client.set_tag("region", "eu");
+        client.set_tag("tier", "free");
+        client.set_context("server_name", "web-1");
+
+        client.with_scope(
+            |scope| scope.set_tag("job", "reindex"),
+            || {
+                client.capture_message_with_options(
+                    "job started",
+                    Level::Info,
+                    Some(HashMap::from([("tier".to_string(), "pro".to_string())])),
+                    None,
+                )
+            },
+        );
+        client.remove_tag("region");
+        client.capture_message("job finished", Level::Info);
+
+        let events = client.delivery.take_events();
+        let tags = events[0].tags.as_ref().unwrap();
+        assert_eq!(tags["region"], "eu");
+        assert_eq!(tags["tier"], "pro");
+        assert_eq!(tags["job"], "reindex");
+        let metadata = events[0].metadata.as_ref().unwrap();
+        assert_eq!(metadata["user"], json!({ "id": "42" }));
+        assert_eq!(metadata["server_name"], "web-1");
+
+        let tags = events[1].tags.as_ref().unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags["tier"], "free");
+        assert_eq!(events[1].metadata.as_ref().unwrap()["server_name"], "web-1");
+    }
+
+    #[test]
+    fn test_feature_flags_set_in_a_scope_are_attached() {
+        let client = PulseKit::new(Config::default());
+        client.set_feature_flags(HashMap::from([(
+            "new_checkout".to_string(),
+            "control".to_string(),
+        )]));
+
+        client.with_scope(
+            |scope| {
+                let flags = (0..150).map(|i| (format!("flag_{:03}", i), "on".to_string()));
+                scope.set_feature_flags(flags.collect());
+            },
+            || client.capture_error("payment declined"),
+        );
+        client.capture_error("cart expired");
+
+        let events = client.delivery.take_events();
+        let scoped = events[0].metadata.as_ref().unwrap()[FLAGS_KEY]
+            .as_object()
+            .unwrap();
+        assert_eq!(scoped.len(), MAX_FEATURE_FLAGS);
+        assert_eq!(scoped["flag_000"], "on");
+        assert!(!scoped.contains_key("flag_100"));
+        assert_eq!(
+            events[1].metadata.as_ref().unwrap()[FLAGS_KEY],
+            json!({ "new_checkout": "control" })
+        );
+    }
+
+    #[test]
+    fn test_isolation_scopes_keep_request_context_apart() {
+        let client = PulseKit::new(Config {
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..Default::default()
+        });
+        client.set_tag("region", "eu");
+
+        std::thread::scope(|threads| {
+            for request in ["a", "b"] {
+                let client = &client;
+                threads.spawn(move || {
+                    client.with_isolation_scope(|| {
+                        client.set_tag("request", request);
+                        client.set_user(Some(User {
+                            id: Some(request.to_string()),
+                            ..Default::default()
+                        }));
+                        client.with_isolation_scope(|| {
+                            client.set_tag("step", "nested");
+                            client.capture_message("nested", Level::Info);
+                        });
+                        client.capture_message(request, Level::Info);
+                    });
+                });
+            }
+        });
+        client.capture_message("outside", Level::Info);
+
+        let events = client.delivery.take_events();
+        for request in ["a", "b"] {
+            let event = events
+                .iter()
+                .find(|event| event.message.as_deref() == Some(request))
+                .unwrap();
+            let tags = event.tags.as_ref().unwrap();
+            assert_eq!(tags["request"], request);
+            assert_eq!(tags["region"], "eu");
+            assert!(!tags.contains_key("step"));
+            assert_eq!(
+                event.metadata.as_ref().unwrap()["user"],
+                json!({ "id": request })
+            );
+        }
+        let nested: Vec<_> = events
+            .iter()
+            .filter(|event| event.message.as_deref() == Some("nested"))
+            .collect();
+        assert_eq!(nested.len(), 2);
+        assert!(nested
+            .iter()
+            .all(|event| event.tags.as_ref().unwrap()["step"] == "nested"));
+
+        let outside = events
+            .iter()
+            .find(|event| event.message.as_deref() == Some("outside"))
+            .unwrap();
+        assert_eq!(outside.tags.as_ref().unwrap().len(), 1);
+        assert!(outside.metadata.is_none());
+    }
+
+    #[test]
+    fn test_isolation_scope_follows_its_future() {
+        let client = PulseKit::new(Config::default());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        let request = |name: &'static str| {
+            let client = &client;
+            client.with_isolation_scope_async(async move {
+                client.set_tag("request", name);
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                client.capture_message(name, Level::Info);
+            })
+        };
+        runtime.block_on(async { tokio::join!(request("a"), request("b")) });
+
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 2);
+        for event in events {
+            assert_eq!(
+                event.tags.as_ref().unwrap()["request"],
+                event.message.unwrap()
+            );
+        }
+        assert_eq!(*client.delivery.scope.lock(), Scope::default());
+    }
+}
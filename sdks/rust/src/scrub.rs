@@ -0,0 +1,242 @@
+//! Removal of sensitive values from events before they are queued.
+
+use crate::debug_log::debug_log;
+use crate::{Config, Event, ValueClassifier};
+use regex::{NoExpand, Regex};
+use serde_json::Value;
+
+/// Replacement for scrubbed values.
+const FILTERED: &str = "[Filtered]";
+
+/// What `capture` removes from events before queueing them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrubConfig {
+    /// Metadata keys (at any depth) and tag names whose values are replaced
+    /// by `"[Filtered]"`, matched case-insensitively
+    pub keys: Vec<String>,
+    /// Regular expressions; matches in the message, tag values and string
+    /// metadata values are replaced by `"[Filtered]"`
+    pub patterns: Vec<String>,
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            keys: [
+                "password",
+                "passwd",
+                "secret",
+                "authorization",
+                "api_key",
+                "apikey",
+                "access_token",
+                "refresh_token",
+                "cookie",
+            ]
+            .map(String::from)
+            .to_vec(),
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// A compiled [`ScrubConfig`].
+pub(crate) struct Scrubber {
+    keys: Vec<String>,
+    patterns: Vec<Regex>,
+    classifier: Option<ValueClassifier>,
+}
+
+impl Scrubber {
+    /// Compile `config.scrub`. Invalid patterns are skipped and reported
+    /// when `debug` is on.
+    pub(crate) fn new(config: &Config) -> Self {
+        let patterns = config
+            .scrub
+            .patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    debug_log!(config, Warn, "Ignoring invalid scrub pattern: {}", e);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            keys: config.scrub.keys.clone(),
+            patterns,
+            classifier: config.value_classifier.clone(),
+        }
+    }
+
+    pub(crate) fn scrub(&self, event: &mut Event) {
+        if let Some(message) = event.message.as_mut() {
+            self.scrub_string(message);
+        }
+        if let Some(tags) = event.tags.as_mut() {
+            for (key, value) in tags.iter_mut() {
+                if self.is_sensitive(key) || self.classify(key, value) {
+                    *value = FILTERED.to_string();
+                } else {
+                    self.scrub_string(value);
+                }
+            }
+        }
+        if let Some(metadata) = event.metadata.as_mut() {
+            for (key, value) in metadata.iter_mut() {
+                self.scrub_entry(key, value);
+            }
+        }
+    }
+
+    fn scrub_entry(&self, key: &str, value: &mut Value) {
+        if self.is_sensitive(key) {
+            *value = Value::from(FILTERED);
+        } else {
+            self.scrub_value(key, value);
+        }
+    }
+
+    /// Scrub `value`, found under `key` (the array's key for elements).
+    fn scrub_value(&self, key: &str, value: &mut Value) {
+        match value {
+            Value::String(string) if self.classify(key, string) => {
+                *value = Value::from(FILTERED);
+            }
+            Value::String(string) => self.scrub_string(string),
+            Value::Array(values) => values
+                .iter_mut()
+                .for_each(|value| self.scrub_value(key, value)),
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    self.scrub_entry(key, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn scrub_string(&self, string: &mut String) {
+        for pattern in &self.patterns {
+            if pattern.is_match(string) {
+                *string = pattern.replace_all(string, NoExpand(FILTERED)).into_owned();
+            }
+        }
+    }
+
+    fn classify(&self, key: &str, value: &str) -> bool {
+        self.classifier
+            .as_ref()
+            .is_some_and(|classifier| classifier(key, value))
+    }
+
+    fn is_sensitive(&self, key: &str) -> bool {
+        self.keys
+            .iter()
+            .any(|sensitive| key.eq_ignore_ascii_case(sensitive))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level, PulseKit};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_sensitive_keys_and_patterns_are_filtered() {
+        let client = PulseKit::new(Config {
+            scrub: ScrubConfig {
+                patterns: vec![r"[\w.+-]+@[\w-]+\.[\w.]+".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        client.capture(Event {
+            event_type: "signup.failed".to_string(),
+            level: Some(Level::Error),
+            message: Some("could not create ada@example.com".to_string()),
+            tags: Some(HashMap::from([
+                ("Authorization".to_string(), "Bearer abc".to_string()),
+                ("plan".to_string(), "pro".to_string()),
+            ])),
+            metadata: Some(HashMap::from([
+                ("password".to_string(), json!("hunter2")),
+                (
+                    "request".to_string(),
+                    json!({
+                        "headers": { "API_KEY": "pk_live_123", "accept": "json" },
+                        "recipients": ["bob@example.com", "support"],
+                    }),
+                ),
+            ])),
+            ..Default::default()
+        });
+
+        let event = client.delivery.take_events().pop().unwrap();
+        assert_eq!(
+            event.message.as_deref(),
+            Some("could not create [Filtered]")
+        );
+        let tags = event.tags.unwrap();
+        assert_eq!(tags["Authorization"], FILTERED);
+        assert_eq!(tags["plan"], "pro");
+        let metadata = event.metadata.unwrap();
+        assert_eq!(metadata["password"], FILTERED);
+        assert_eq!(
+            metadata["request"],
+            json!({
+                "headers": { "API_KEY": FILTERED, "accept": "json" },
+                "recipients": [FILTERED, "support"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_value_classifier_redacts_high_entropy_strings() {
+        // Bits per character; random tokens sit around 4-6, words below 3.5.
+        fn entropy(value: &str) -> f64 {
+            let mut counts = HashMap::new();
+            for c in value.chars() {
+                *counts.entry(c).or_insert(0.0) += 1.0;
+            }
+            let len = value.chars().count() as f64;
+            counts
+                .values()
+                .map(|count| -(count / len) * (count / len).log2())
+                .sum()
+        }
+        let client = PulseKit::new(Config {
+            value_classifier: Some(Arc::new(|_key: &str, value: &str| {
+                value.len() >= 20 && entropy(value) > 4.0
+            })),
+            ..Default::default()
+        });
+
+        client.capture(Event {
+            event_type: "sync.failed".to_string(),
+            tags: Some(HashMap::from([
+                ("source".to_string(), "github".to_string()),
+                ("ref".to_string(), "ghp_9fK2xQ7mLp4Rz8TvWb3Nc6Hy".to_string()),
+            ])),
+            metadata: Some(HashMap::from([(
+                "attempts".to_string(),
+                json!([{ "note": "remote rejected the push", "token": "sk_4eC39HqLyjWDarjtT1zdp7dc" }]),
+            )])),
+            ..Default::default()
+        });
+
+        let event = client.delivery.take_events().pop().unwrap();
+        let tags = event.tags.unwrap();
+        assert_eq!(tags["source"], "github");
+        assert_eq!(tags["ref"], FILTERED);
+        assert_eq!(
+            event.metadata.unwrap()["attempts"],
+            json!([{ "note": "remote rejected the push", "token": FILTERED }])
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Release health: how many sessions of each release ended crashed.
+
+use crate::{Level, PulseKit};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// How a session ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionStatus {
+    /// The session ended normally
+    #[default]
+    Healthy,
+    /// The session ended in a crash
+    Crashed,
+}
+
+/// Session counts for one release and environment, as sent to
+/// `Config::sessions_path`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub(crate) struct SessionAggregate {
+    release: Option<String>,
+    environment: Option<String>,
+    started: u64,
+    healthy: u64,
+    crashed: u64,
+}
+
+impl SessionAggregate {
+    fn merge(&mut self, other: &SessionAggregate) {
+        self.started += other.started;
+        self.healthy += other.healthy;
+        self.crashed += other.crashed;
+    }
+}
+
+struct OpenSession {
+    release: Option<String>,
+    environment: Option<String>,
+    crashed: bool,
+}
+
+/// The session in progress and the counts not yet sent.
+#[derive(Default)]
+pub(crate) struct Sessions {
+    current: Mutex<Option<OpenSession>>,
+    pending: Mutex<Vec<SessionAggregate>>,
+}
+
+impl Sessions {
+    fn start(&self, release: Option<String>, environment: Option<String>) {
+        let previous = self.current.lock().replace(OpenSession {
+            release: release.clone(),
+            environment: environment.clone(),
+            crashed: false,
+        });
+        if let Some(previous) = previous {
+            self.close(previous, SessionStatus::Healthy);
+        }
+        self.add(SessionAggregate {
+            release,
+            environment,
+            started: 1,
+            ..Default::default()
+        });
+    }
+
+    /// End the session in progress, if any. One that captured a `Fatal`
+    /// event ends crashed whatever `status` says.
+    pub(crate) fn end(&self, status: SessionStatus) {
+        let current = self.current.lock().take();
+        if let Some(current) = current {
+            self.close(current, status);
+        }
+    }
+
+    /// Mark the session in progress as crashed if `level` is `Fatal`.
+    pub(crate) fn observe(&self, level: Option<Level>) {
+        if level != Some(Level::Fatal) {
+            return;
+        }
+        if let Some(current) = self.current.lock().as_mut() {
+            current.crashed = true;
+        }
+    }
+
+    fn close(&self, session: OpenSession, status: SessionStatus) {
+        let crashed = session.crashed || status == SessionStatus::Crashed;
+        self.add(SessionAggregate {
+            release: session.release,
+            environment: session.environment,
+            healthy: u64::from(!crashed),
+            crashed: u64::from(crashed),
+            ..Default::default()
+        });
+    }
+
+    fn add(&self, counts: SessionAggregate) {
+        let mut pending = self.pending.lock();
+        match pending.iter_mut().find(|aggregate| {
+            aggregate.release == counts.release && aggregate.environment == counts.environment
+        }) {
+            Some(aggregate) => aggregate.merge(&counts),
+            None => pending.push(counts),
+        }
+    }
+
+    /// Take the counts to send.
+    pub(crate) fn take(&self) -> Vec<SessionAggregate> {
+        std::mem::take(&mut *self.pending.lock())
+    }
+
+    /// Put back counts that could not be sent, merging them with any
+    /// recorded since.
+    pub(crate) fn restore(&self, aggregates: Vec<SessionAggregate>) {
+        for aggregate in aggregates {
+            self.add(aggregate);
+        }
+    }
+}
+
+impl PulseKit {
+    /// Start a session for this client's release and environment, ending
+    /// the one in progress as healthy.
+    ///
+    /// Session counts are sent to `sessions_path` with each flush. A
+    /// `Fatal` event captured before [`PulseKit::end_session`], such as a
+    /// panic reported by the panic hook, marks the session crashed. A
+    /// session still open when the last client handle is dropped is ended
+    /// then.
+    pub fn start_session(&self) {
+        self.delivery
+            .sessions
+            .start(self.config.release.clone(), self.config.environment.clone());
+    }
+
+    /// End the session in progress with `status`.
+    pub fn end_session(&self, status: SessionStatus) {
+        self.delivery.sessions.end(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
+    use crate::{Config, Event};
+
+    #[test]
+    fn test_fatal_event_sends_a_crashed_session() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                release: Some("1.4.0".to_string()),
+                environment: Some("production".to_string()),
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        client.start_session();
+        client.end_session(SessionStatus::Healthy);
+        client.start_session();
+        client.capture(Event {
+            event_type: "panic".to_string(),
+            level: Some(Level::Fatal),
+            message: Some("worker crashed".to_string()),
+            ..Default::default()
+        });
+        client.end_session(SessionStatus::Healthy);
+        client.flush_blocking();
+
+        let requests = transport.requests();
+        let sessions = requests
+            .iter()
+            .find(|request| request.url.ends_with("/api/v1/sessions"))
+            .expect("no session request");
+        let body: serde_json::Value = serde_json::from_slice(&sessions.body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "aggregates": [{
+                    "release": "1.4.0",
+                    "environment": "production",
+                    "started": 2,
+                    "healthy": 1,
+                    "crashed": 1,
+                }]
+            })
+        );
+
+        client.flush_blocking();
+        assert_eq!(transport.requests().len(), requests.len());
+    }
+}
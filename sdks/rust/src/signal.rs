@@ -0,0 +1,172 @@
+//! Crash markers for fatal signals, reported on the next startup.
+//!
+//! A signal handler may only call async-signal-safe functions: no
+//! allocation, no locks, no formatting and no network I/O. The handler
+//! therefore does nothing but `open`/`write`/`close` a marker file whose
+//! path was prepared when the handler was installed, then re-raises the
+//! signal with the default action restored so the process still dies (and
+//! dumps core) as it would have without PulseKit.
+
+use crate::{Event, Level, Mechanism, PulseKit};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const SIGNALS: [libc::c_int; 3] = [libc::SIGSEGV, libc::SIGABRT, libc::SIGBUS];
+const MARKER_PREFIX: &str = "pulsekit-crash-";
+const MARKER_SUFFIX: &str = ".marker";
+
+/// Marker path for this process, set once before the handler is installed
+/// so the handler only has to read it.
+static MARKER_PATH: OnceLock<CString> = OnceLock::new();
+
+impl PulseKit {
+    /// Report crashes caused by `SIGSEGV`, `SIGABRT` and `SIGBUS`.
+    ///
+    /// When one of these signals arrives, a crash marker is written to
+    /// `spool_dir` and the process terminates as usual. Markers left by
+    /// earlier crashed runs are captured as `Fatal` events and removed when
+    /// this method is called, so call it early during startup.
+    ///
+    /// Only the first call in a process installs the handler.
+    pub fn install_signal_handler(&self, spool_dir: impl AsRef<Path>) {
+        let spool_dir = spool_dir.as_ref();
+        self.report_crash_markers(spool_dir);
+
+        let marker = spool_dir.join(format!(
+            "{}{}{}",
+            MARKER_PREFIX,
+            std::process::id(),
+            MARKER_SUFFIX
+        ));
+        let Ok(marker) = CString::new(marker.as_os_str().as_bytes()) else {
+            return;
+        };
+        if MARKER_PATH.set(marker).is_err() {
+            return;
+        }
+
+        for signal in SIGNALS {
+            // SAFETY: `on_signal` only calls async-signal-safe functions, and
+            // the zeroed sigaction is fully initialized before use.
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESETHAND;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, std::ptr::null_mut());
+            }
+        }
+    }
+
+    fn report_crash_markers(&self, spool_dir: &Path) {
+        for marker in crash_markers(spool_dir) {
+            let signal = std::fs::read_to_string(&marker).unwrap_or_default();
+            let signal = signal.trim();
+            self.capture(Event {
+                event_type: "error".to_string(),
+                level: Some(Level::Fatal),
+                message: Some(format!("Process terminated by {}", signal)),
+                mechanism: Some(Mechanism::signal()),
+                ..Default::default()
+            });
+            let _ = std::fs::remove_file(marker);
+        }
+    }
+}
+
+fn crash_markers(spool_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(spool_dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| {
+                    name.starts_with(MARKER_PREFIX) && name.ends_with(MARKER_SUFFIX)
+                })
+        })
+        .collect()
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    let name: &[u8] = match signal {
+        libc::SIGSEGV => b"SIGSEGV\n",
+        libc::SIGABRT => b"SIGABRT\n",
+        libc::SIGBUS => b"SIGBUS\n",
+        _ => b"signal\n",
+    };
+
+    if let Some(path) = MARKER_PATH.get() {
+        // SAFETY: open, write, close and raise are async-signal-safe, and
+        // `path` is a valid NUL-terminated string that is never freed.
+        unsafe {
+            let fd = libc::open(
+                path.as_ptr(),
+                libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+                0o644,
+            );
+            if fd >= 0 {
+                libc::write(fd, name.as_ptr().cast(), name.len());
+                libc::close(fd);
+            }
+        }
+    }
+
+    // SAFETY: SA_RESETHAND restored the default action, so this terminates
+    // the process once the handler returns.
+    unsafe {
+        libc::raise(signal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+
+    const CHILD_ENV: &str = "PULSEKIT_SIGNAL_CHILD_DIR";
+
+    #[test]
+    fn test_abort_leaves_a_marker_reported_on_next_start() {
+        if let Ok(dir) = std::env::var(CHILD_ENV) {
+            PulseKit::new(Config::default()).install_signal_handler(dir);
+            std::process::abort();
+        }
+
+        let dir = std::env::temp_dir().join(format!("pulsekit-spool-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let status = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "signal::tests::test_abort_leaves_a_marker_reported_on_next_start",
+            ])
+            .env(CHILD_ENV, &dir)
+            .output()
+            .unwrap()
+            .status;
+        assert_eq!(status.signal(), Some(libc::SIGABRT));
+
+        let markers = crash_markers(&dir);
+        assert_eq!(markers.len(), 1);
+        assert_eq!(std::fs::read_to_string(&markers[0]).unwrap(), "SIGABRT\n");
+
+        let client = PulseKit::new(Config::default());
+        client.report_crash_markers(&dir);
+        let event = client.delivery.take_events().pop().unwrap();
+        assert_eq!(event.level, Some(Level::Fatal));
+        assert_eq!(
+            event.message.as_deref(),
+            Some("Process terminated by SIGABRT")
+        );
+        assert_eq!(event.mechanism, Some(Mechanism::signal()));
+        assert!(crash_markers(&dir).is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
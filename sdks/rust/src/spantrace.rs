@@ -0,0 +1,73 @@
+//! Reporting errors with a `tracing_error::SpanTrace` instead of a machine
+//! backtrace.
+
+use crate::{Event, Level, Mechanism, PulseKit, StackFrame};
+use tracing_error::SpanTrace;
+
+impl PulseKit {
+    /// Capture an error whose stack trace is built from the spans that were
+    /// active when `span_trace` was captured, innermost span first.
+    pub fn capture_error_with_spantrace(&self, message: &str, span_trace: &SpanTrace) {
+        self.capture(Event {
+            event_type: "error".to_string(),
+            level: Some(Level::Error),
+            message: Some(message.to_string()),
+            stacktrace: Some(span_frames(span_trace)),
+            mechanism: Some(Mechanism::generic()),
+            ..Default::default()
+        });
+    }
+}
+
+/// One frame per span: the span's source location, with the function named
+/// `<module path>::<span name>`.
+fn span_frames(span_trace: &SpanTrace) -> Vec<StackFrame> {
+    let mut frames = Vec::new();
+    span_trace.with_spans(|metadata, _fields| {
+        let module = metadata.module_path().unwrap_or_else(|| metadata.target());
+        frames.push(StackFrame {
+            file: metadata.file().map(str::to_string),
+            line: metadata.line(),
+            function: Some(format!("{}::{}", module, metadata.name())),
+        });
+        true
+    });
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use tracing_error::ErrorLayer;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_span_trace_becomes_frames() {
+        let subscriber = tracing_subscriber::registry().with(ErrorLayer::default());
+        let span_trace = tracing::subscriber::with_default(subscriber, || {
+            let _request = tracing::info_span!("handle_request").entered();
+            let _query = tracing::info_span!("load_user").entered();
+            SpanTrace::capture()
+        });
+
+        let client = PulseKit::new(Config::default());
+        client.capture_error_with_spantrace("user lookup failed", &span_trace);
+
+        let event = client.delivery.take_events().pop().unwrap();
+        let frames = event.stacktrace.unwrap();
+        let functions: Vec<_> = frames
+            .iter()
+            .map(|f| f.function.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            functions,
+            [
+                "pulsekit::spantrace::tests::load_user",
+                "pulsekit::spantrace::tests::handle_request"
+            ]
+        );
+        assert!(frames[0].file.as_deref().unwrap().ends_with("spantrace.rs"));
+        assert!(frames[0].line.is_some());
+    }
+}
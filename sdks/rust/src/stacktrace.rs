@@ -0,0 +1,243 @@
+//! Helpers for inspecting captured stack frames.
+
+use crate::StackFrame;
+use parking_lot::{Condvar, Mutex};
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+/// Crates whose frames belong to the SDK or the runtime rather than to the
+/// application.
+const NON_APP_PREFIXES: &[&str] = &["pulsekit", "backtrace", "std", "core", "alloc"];
+
+/// Modules whose frames sit above the code that captured a backtrace: the
+/// SDK itself, the `backtrace` crate, and the standard library's panic and
+/// startup machinery.
+#[cfg(not(target_arch = "wasm32"))]
+const CAPTURE_PREFIXES: &[&str] = &[
+    "pulsekit",
+    "backtrace",
+    "core::panic",
+    "core::panicking",
+    "std::panic",
+    "std::panicking",
+    "std::rt",
+    "std::sys::backtrace",
+    "rust_begin_unwind",
+];
+
+/// Whether a function path such as `hyper::client::conn::send` belongs to
+/// the module `prefix`, matching whole path segments only.
+pub(crate) fn function_in_module(function: &str, prefix: &str) -> bool {
+    let function = function.trim_start_matches('<');
+    function == prefix
+        || function
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with("::"))
+}
+
+/// Whether a frame comes from application (or library) code rather than
+/// from the SDK itself or the standard library.
+pub(crate) fn is_in_app(frame: &StackFrame) -> bool {
+    match &frame.function {
+        Some(function) => !NON_APP_PREFIXES
+            .iter()
+            .any(|prefix| function_in_module(function, prefix)),
+        None => false,
+    }
+}
+
+/// Whether a frame belongs to the machinery that captured the backtrace
+/// rather than to the code that asked for it.
+#[cfg(not(target_arch = "wasm32"))]
+fn is_capture_frame(frame: &StackFrame) -> bool {
+    let Some(function) = frame.function.as_deref() else {
+        return false;
+    };
+    // Unit tests live in the crate but stand in for application code.
+    if cfg!(test) && function.contains("::tests::") {
+        return false;
+    }
+    let function = strip_disambiguators(function);
+    CAPTURE_PREFIXES
+        .iter()
+        .any(|prefix| function_in_module(&function, prefix))
+}
+
+/// `function` without the crate disambiguators some symbol manglings add,
+/// e.g. `std[1a2b3c]::panicking::begin_panic` becomes
+/// `std::panicking::begin_panic`.
+#[cfg(not(target_arch = "wasm32"))]
+fn strip_disambiguators(function: &str) -> String {
+    let mut stripped = String::with_capacity(function.len());
+    let mut rest = function;
+    while let Some(open) = rest.find('[') {
+        stripped.push_str(&rest[..open]);
+        match rest[open..].find(']') {
+            Some(close)
+                if rest[open + 1..open + close]
+                    .chars()
+                    .all(|c| c.is_ascii_hexdigit()) =>
+            {
+                rest = &rest[open + close + 1..];
+            }
+            _ => {
+                stripped.push('[');
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Drop the frames above the first one outside the SDK, the `backtrace`
+/// crate and the panic machinery, then keep at most `max_frames`. Frames
+/// are kept as they are if none would remain.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn trim_capture_frames(
+    mut frames: Vec<StackFrame>,
+    max_frames: Option<usize>,
+) -> Vec<StackFrame> {
+    if let Some(first) = frames.iter().position(|frame| !is_capture_frame(frame)) {
+        frames.drain(..first);
+    }
+    if let Some(max_frames) = max_frames {
+        frames.truncate(max_frames);
+    }
+    frames
+}
+
+/// The innermost frame outside the SDK and the standard library.
+pub(crate) fn top_in_app_frame(frames: &[StackFrame]) -> Option<&StackFrame> {
+    frames.iter().find(|frame| is_in_app(frame))
+}
+
+/// A stable hash of a stack trace's frames, as 16 hex digits (FNV-1a).
+pub(crate) fn trace_hash(frames: &[StackFrame]) -> String {
+    stable_hash(frames.iter().flat_map(|frame| {
+        [
+            frame
+                .file
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .to_vec(),
+            frame.line.unwrap_or_default().to_le_bytes().to_vec(),
+            frame
+                .function
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .to_vec(),
+        ]
+    }))
+}
+
+/// A hash of `parts` that is the same on every platform and Rust version,
+/// as 16 hex digits (FNV-1a, with a separator after each part).
+pub(crate) fn stable_hash(parts: impl IntoIterator<Item = impl AsRef<[u8]>>) -> String {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    let mut hash = OFFSET;
+    for part in parts {
+        for byte in part.as_ref().iter().chain([&0xff]) {
+            hash = (hash ^ u64::from(*byte)).wrapping_mul(PRIME);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// Bounds how many threads resolve backtrace symbols at once. Unused on
+/// wasm32, where no backtrace is captured.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub(crate) struct ResolutionSlots {
+    limit: usize,
+    in_use: Mutex<usize>,
+    released: Condvar,
+    #[cfg(test)]
+    pub(crate) peak: AtomicUsize,
+}
+
+impl ResolutionSlots {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            in_use: Mutex::new(0),
+            released: Condvar::new(),
+            #[cfg(test)]
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Take a slot, waiting up to `wait` for one to be released. Returns
+    /// `None` if every slot is still taken after that.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn acquire(&self, wait: Duration) -> Option<ResolutionSlot<'_>> {
+        let mut in_use = self.in_use.lock();
+        if *in_use >= self.limit {
+            let _ = self
+                .released
+                .wait_while_for(&mut in_use, |in_use| *in_use >= self.limit, wait);
+            if *in_use >= self.limit {
+                return None;
+            }
+        }
+        *in_use += 1;
+        #[cfg(test)]
+        self.peak.fetch_max(*in_use, Ordering::Relaxed);
+        Some(ResolutionSlot { slots: self })
+    }
+}
+
+/// A taken slot, released on drop.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct ResolutionSlot<'a> {
+    slots: &'a ResolutionSlots,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for ResolutionSlot<'_> {
+    fn drop(&mut self) {
+        *self.slots.in_use.lock() -= 1;
+        self.slots.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(function: &str) -> StackFrame {
+        StackFrame {
+            file: None,
+            line: None,
+            function: Some(function.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_function_in_module_matches_whole_segments() {
+        assert!(function_in_module("hyper::proto::h1::dispatch", "hyper"));
+        assert!(function_in_module(
+            "<hyper::Client as Service>::call",
+            "hyper"
+        ));
+        assert!(function_in_module("hyper::proto::h1", "hyper::proto"));
+        assert!(!function_in_module("hyperlocal::connect", "hyper"));
+    }
+
+    #[test]
+    fn test_top_in_app_frame_skips_sdk_and_std() {
+        let frames = vec![
+            frame("backtrace::capture::Backtrace::new"),
+            frame("pulsekit::PulseKit::capture_error"),
+            frame("my_app::handlers::checkout"),
+            frame("std::rt::lang_start"),
+        ];
+        let top = top_in_app_frame(&frames).unwrap();
+        assert_eq!(top.function.as_deref(), Some("my_app::handlers::checkout"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Client-side counters describing what happened to captured events.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A snapshot of the client's counters, returned by [`crate::PulseKit::stats`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Events passed to `capture` and the other capture methods
+    pub captured: u64,
+    /// Events in batches the server accepted
+    pub sent: u64,
+    /// Requests that failed, counting every retry attempt
+    pub send_failures: u64,
+    /// Events waiting in the queue when the snapshot was taken
+    pub queue_depth: u64,
+    /// Events dropped because they were below `min_level`
+    pub dropped_below_min_level: u64,
+    /// Events dropped by the `before_send` hook
+    pub dropped_by_before_send: u64,
+    /// Events dropped because they were below `startup_grace_level` while
+    /// the startup grace window was open
+    pub suppressed_during_startup: u64,
+    /// Events dropped because `max_events_per_window` was reached
+    pub dropped_over_budget: u64,
+    /// Events dropped as duplicates within `dedupe_window`
+    pub dropped_duplicates: u64,
+    /// Events dropped because the queue held `max_queue_size` events
+    pub dropped_queue_full: u64,
+    /// Events dropped by `sample_rate` or `sample_rates`
+    pub dropped_sampled: u64,
+    /// Events dropped because preparing them took longer than
+    /// `capture_deadline`
+    pub dropped_over_deadline: u64,
+    /// Events dropped by `oversized_event_policy`
+    pub dropped_oversized: u64,
+    /// Events that may still be sent in the current window, if
+    /// `max_events_per_window` is set
+    pub remaining_budget: Option<u32>,
+}
+
+/// Live counters shared by every handle of a client.
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub(crate) captured: AtomicU64,
+    pub(crate) sent: AtomicU64,
+    pub(crate) send_failures: AtomicU64,
+    pub(crate) dropped_below_min_level: AtomicU64,
+    pub(crate) dropped_by_before_send: AtomicU64,
+    pub(crate) suppressed_during_startup: AtomicU64,
+    pub(crate) dropped_over_budget: AtomicU64,
+    pub(crate) dropped_duplicates: AtomicU64,
+    pub(crate) dropped_queue_full: AtomicU64,
+    pub(crate) dropped_sampled: AtomicU64,
+    pub(crate) dropped_over_deadline: AtomicU64,
+    pub(crate) dropped_oversized: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add(counter: &AtomicU64, count: usize) {
+        counter.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            captured: self.captured.load(Ordering::Relaxed),
+            sent: self.sent.load(Ordering::Relaxed),
+            send_failures: self.send_failures.load(Ordering::Relaxed),
+            queue_depth: 0,
+            dropped_below_min_level: self.dropped_below_min_level.load(Ordering::Relaxed),
+            dropped_by_before_send: self.dropped_by_before_send.load(Ordering::Relaxed),
+            suppressed_during_startup: self.suppressed_during_startup.load(Ordering::Relaxed),
+            dropped_over_budget: self.dropped_over_budget.load(Ordering::Relaxed),
+            dropped_duplicates: self.dropped_duplicates.load(Ordering::Relaxed),
+            dropped_queue_full: self.dropped_queue_full.load(Ordering::Relaxed),
+            dropped_sampled: self.dropped_sampled.load(Ordering::Relaxed),
+            dropped_over_deadline: self.dropped_over_deadline.load(Ordering::Relaxed),
+            dropped_oversized: self.dropped_oversized.load(Ordering::Relaxed),
+            remaining_budget: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::{MockResponse, MockServer};
+    use crate::{Config, Event, Level, PulseKit, RetryConfig};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_stats_count_captures_sends_failures_and_drops() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let server = MockServer::with_handler({
+            let calls = calls.clone();
+            move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => MockResponse::status(503),
+                _ => MockResponse::status(200),
+            }
+        });
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            batch_size: 100,
+            sample_rates: HashMap::from([("noise".to_string(), 0.0)]),
+            before_send: Some(Arc::new(|event: Event| {
+                (event.event_type != "private").then_some(event)
+            })),
+            retry: RetryConfig {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        client.capture_message("verbose", Level::Debug);
+        for event_type in ["noise", "private", "order.paid", "order.refunded"] {
+            client.capture(Event {
+                event_type: event_type.to_string(),
+                ..Default::default()
+            });
+        }
+        client.flush_blocking();
+        client.capture_message("pending", Level::Info);
+
+        let stats = client.stats();
+        assert_eq!(stats.captured, 6);
+        assert_eq!(stats.sent, 2);
+        assert_eq!(stats.send_failures, 1);
+        assert_eq!(stats.queue_depth, 1);
+        assert_eq!(stats.dropped_below_min_level, 1);
+        assert_eq!(stats.dropped_sampled, 1);
+        assert_eq!(stats.dropped_by_before_send, 1);
+    }
+}
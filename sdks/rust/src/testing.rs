@@ -0,0 +1,138 @@
+//! Assertion helpers for tests that exercise code reporting to PulseKit.
+
+use crate::transport::MemoryTransport;
+use crate::{Config, Event, Level, PulseKit};
+use std::ops::Deref;
+
+/// A client that records delivered events in memory.
+///
+/// It dereferences to [`PulseKit`], so it can be handed to code under test
+/// as `&PulseKit`. Every inspection method flushes the queue first.
+///
+/// ```
+/// use pulsekit::testing::TestClient;
+/// use pulsekit::Level;
+///
+/// let client = TestClient::new();
+/// client.capture_error("payment declined");
+///
+/// client.assert_captured(Level::Error, |e| e.message.as_deref() == Some("payment declined"));
+/// assert_eq!(client.captured_count(), 1);
+/// ```
+pub struct TestClient {
+    client: PulseKit,
+    transport: MemoryTransport,
+}
+
+impl TestClient {
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        let transport = MemoryTransport::new();
+        Self {
+            client: PulseKit::with_transport(config, transport.clone()),
+            transport,
+        }
+    }
+
+    /// The underlying client.
+    pub fn client(&self) -> &PulseKit {
+        &self.client
+    }
+
+    /// All events captured so far.
+    pub fn captured(&self) -> Vec<Event> {
+        self.client.flush_blocking();
+        self.transport.events()
+    }
+
+    /// Number of events captured so far.
+    pub fn captured_count(&self) -> usize {
+        self.captured().len()
+    }
+
+    /// Return the captured events and forget them, so later assertions only
+    /// see new events.
+    pub fn take_captured(&self) -> Vec<Event> {
+        self.client.flush_blocking();
+        self.transport.take_events()
+    }
+
+    /// Assert that at least one event with `level` matching `predicate` was
+    /// captured, returning the first match.
+    #[track_caller]
+    pub fn assert_captured(&self, level: Level, predicate: impl Fn(&Event) -> bool) -> Event {
+        let captured = self.captured();
+        match captured
+            .iter()
+            .find(|event| event.level == Some(level) && predicate(event))
+        {
+            Some(event) => event.clone(),
+            None => panic!(
+                "no {:?} event matched the predicate; captured events: {:#?}",
+                level, captured
+            ),
+        }
+    }
+
+    /// Assert that no event with `level` matching `predicate` was captured.
+    #[track_caller]
+    pub fn assert_not_captured(&self, level: Level, predicate: impl Fn(&Event) -> bool) {
+        let captured = self.captured();
+        if let Some(event) = captured
+            .iter()
+            .find(|event| event.level == Some(level) && predicate(event))
+        {
+            panic!("unexpected {:?} event was captured: {:#?}", level, event);
+        }
+    }
+}
+
+impl Default for TestClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for TestClient {
+    type Target = PulseKit;
+
+    fn deref(&self) -> &PulseKit {
+        &self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assertion_helpers() {
+        let client = TestClient::new();
+        client.capture_message("cache warmed", Level::Info);
+        client.capture_error("payment declined");
+        client.capture_message("slow query", Level::Warning);
+
+        assert_eq!(client.captured_count(), 3);
+        let event = client.assert_captured(Level::Error, |e| {
+            e.message.as_deref() == Some("payment declined")
+        });
+        assert_eq!(event.event_type, "error");
+        client.assert_not_captured(Level::Error, |e| {
+            e.message.as_deref() == Some("cache warmed")
+        });
+
+        assert_eq!(client.take_captured().len(), 3);
+        assert_eq!(client.captured_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "no Fatal event matched")]
+    fn test_assert_captured_panics_without_match() {
+        let client = TestClient::new();
+        client.capture_error("recoverable");
+        client.assert_captured(Level::Fatal, |_| true);
+    }
+}
@@ -0,0 +1,44 @@
+//! Waiting in async sends: on Tokio's timer natively, and on the
+//! JavaScript event loop's on wasm32, where there is no Tokio runtime.
+
+use crate::time::{Duration, Instant};
+use std::future::Future;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn sleep(duration: Duration) {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_name = setTimeout)]
+        fn set_timeout(handler: &js_sys::Function, millis: i32);
+    }
+
+    let millis = i32::try_from(duration.as_millis()).unwrap_or(i32::MAX);
+    let elapsed = js_sys::Promise::new(&mut |resolve, _| set_timeout(&resolve, millis));
+    let _ = wasm_bindgen_futures::JsFuture::from(elapsed).await;
+}
+
+/// Run `future` until `deadline`, returning `None` if it hasn't finished
+/// by then.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn timeout_at<F: Future>(deadline: Instant, future: F) -> Option<F::Output> {
+    tokio::time::timeout_at(deadline.into(), future).await.ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn timeout_at<F: Future>(deadline: Instant, future: F) -> Option<F::Output> {
+    use futures_util::future::{self, Either};
+    use std::pin::pin;
+
+    let expired = sleep(deadline.saturating_duration_since(Instant::now()));
+    match future::select(pin!(future), pin!(expired)).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
@@ -0,0 +1,278 @@
+//! Event timestamps, with optional protection against a wrong local clock.
+
+use crate::clock::Clock;
+use crate::time::Instant;
+use crate::transport::TransportResponse;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+/// How `capture` computes event timestamps.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TimestampMode {
+    /// Read the system clock for every event.
+    #[default]
+    SystemClock,
+    /// Read the system clock once at startup and derive later timestamps
+    /// from the monotonic clock, so wall-clock jumps after startup (NTP
+    /// steps, manual changes) don't reorder events.
+    MonotonicAnchored,
+    /// Leave the timestamp unset so the server stamps events with the time
+    /// it receives them. For devices whose clock is known to be wrong, e.g.
+    /// embedded targets that boot at 1970 until NTP syncs; events delayed
+    /// in the queue or by retries are stamped late. Turn on
+    /// `attach_sequence` to keep their order. Timestamps set on the event
+    /// before `capture` are still sent.
+    Server,
+}
+
+/// Serde helpers writing `Event::timestamp` as the RFC 3339 string the
+/// server has always received, e.g. `2024-05-01T12:00:00.123456+00:00`.
+pub(crate) mod rfc3339 {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(
+        timestamp: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match timestamp {
+            Some(timestamp) => serializer.serialize_str(&timestamp.to_rfc3339()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|timestamp| {
+                DateTime::parse_from_rfc3339(&timestamp)
+                    .map(|timestamp| timestamp.with_timezone(&Utc))
+                    .map_err(serde::de::Error::custom)
+            })
+            .transpose()
+    }
+}
+
+/// Skews smaller than this are within the one-second resolution of the
+/// `Date` header and are ignored.
+const MIN_SKEW_MS: i64 = 2_000;
+
+/// Produces event timestamps and tracks the server's clock offset.
+pub(crate) struct Timestamps {
+    anchor_wall: DateTime<Utc>,
+    anchor_instant: Instant,
+    offset_ms: AtomicI64,
+    clock: Arc<dyn Clock>,
+}
+
+impl Timestamps {
+    pub(crate) fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            anchor_wall: clock.now(),
+            anchor_instant: clock.instant(),
+            offset_ms: AtomicI64::new(0),
+            clock,
+        }
+    }
+
+    /// The current time according to `mode`, corrected by any skew learned
+    /// from server responses, or `None` when the server assigns it.
+    pub(crate) fn now(&self, mode: TimestampMode) -> Option<DateTime<Utc>> {
+        let local = self.local(mode)?;
+        Some(local + ChronoDuration::milliseconds(self.offset_ms.load(Ordering::Relaxed)))
+    }
+
+    /// The current time according to `mode`, before any skew correction.
+    fn local(&self, mode: TimestampMode) -> Option<DateTime<Utc>> {
+        match mode {
+            TimestampMode::SystemClock => Some(self.clock.now()),
+            TimestampMode::MonotonicAnchored => {
+                let elapsed = self
+                    .clock
+                    .instant()
+                    .saturating_duration_since(self.anchor_instant);
+                let elapsed =
+                    ChronoDuration::from_std(elapsed).unwrap_or_else(|_| ChronoDuration::zero());
+                Some(self.anchor_wall + elapsed)
+            }
+            TimestampMode::Server => None,
+        }
+    }
+
+    /// Learn the clock offset from a response's `Date` header, measured
+    /// against the time `mode` stamps events with, as that is what the
+    /// offset corrects.
+    pub(crate) fn observe(&self, response: &TransportResponse, mode: TimestampMode) {
+        let Some(skew) = self
+            .local(mode)
+            .and_then(|local| server_skew_ms(response, local))
+        else {
+            return;
+        };
+        let offset = if skew.abs() < MIN_SKEW_MS { 0 } else { skew };
+        self.offset_ms.store(offset, Ordering::Relaxed);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn offset_ms(&self) -> i64 {
+        self.offset_ms.load(Ordering::Relaxed)
+    }
+}
+
+/// How far the server's clock, per the response's `Date` header, is ahead
+/// of the local time `now`.
+pub(crate) fn server_skew_ms(response: &TransportResponse, now: DateTime<Utc>) -> Option<i64> {
+    let server_time = DateTime::parse_from_rfc2822(response.header("Date")?).ok()?;
+    Some((server_time.with_timezone(&Utc) - now).num_milliseconds())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use crate::{Config, Level, PulseKit, SystemClock, TestClock};
+
+    fn response_with_date(date: DateTime<Utc>) -> TransportResponse {
+        TransportResponse {
+            status: 200,
+            headers: vec![("Date".to_string(), date.to_rfc2822())],
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_small_skew_is_ignored() {
+        let timestamps = Timestamps::new(Arc::new(SystemClock));
+        let mode = TimestampMode::SystemClock;
+        timestamps.observe(&response_with_date(Utc::now()), mode);
+        assert_eq!(timestamps.offset_ms(), 0);
+
+        timestamps.observe(
+            &response_with_date(Utc::now() - ChronoDuration::minutes(5)),
+            mode,
+        );
+        assert!((timestamps.offset_ms() + 300_000).abs() < 2_000);
+    }
+
+    #[test]
+    fn test_anchored_skew_is_measured_against_anchored_time() {
+        /// A wall clock that can be stepped while no time passes.
+        struct SteppedClock(parking_lot::Mutex<DateTime<Utc>>, Instant);
+        impl Clock for SteppedClock {
+            fn now(&self) -> DateTime<Utc> {
+                *self.0.lock()
+            }
+            fn instant(&self) -> Instant {
+                self.1
+            }
+        }
+
+        let anchored = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let clock = Arc::new(SteppedClock(
+            parking_lot::Mutex::new(anchored),
+            Instant::now(),
+        ));
+        let timestamps = Timestamps::new(clock.clone());
+        // NTP steps the wall clock back an hour; anchored time is unaffected.
+        *clock.0.lock() = anchored - ChronoDuration::hours(1);
+
+        let mode = TimestampMode::MonotonicAnchored;
+        timestamps.observe(&response_with_date(anchored), mode);
+        assert_eq!(timestamps.offset_ms(), 0);
+        assert_eq!(timestamps.now(mode), Some(anchored));
+    }
+
+    #[test]
+    fn test_monotonic_anchored_tracks_elapsed_time() {
+        let clock = TestClock::new();
+        let timestamps = Timestamps::new(Arc::new(clock.clone()));
+        let first = timestamps.now(TimestampMode::MonotonicAnchored).unwrap();
+        clock.advance(std::time::Duration::from_millis(20));
+        let second = timestamps.now(TimestampMode::MonotonicAnchored).unwrap();
+        assert_eq!(second - first, ChronoDuration::milliseconds(20));
+    }
+
+    #[test]
+    fn test_server_date_corrects_later_timestamps() {
+        let server = MockServer::with_handler(|_| {
+            let server_time = Utc::now() + ChronoDuration::hours(1);
+            MockResponse::status(200).header("Date", &server_time.to_rfc2822())
+        });
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            correct_clock_skew: true,
+            ..Default::default()
+        });
+
+        client.capture_message("learn offset", Level::Info);
+        client.flush_blocking();
+        client.capture_message("corrected", Level::Info);
+
+        let event = client.delivery.take_events().pop().unwrap();
+        let ahead = event.timestamp.unwrap() - Utc::now();
+        assert!(ahead > ChronoDuration::minutes(59) && ahead <= ChronoDuration::hours(1));
+    }
+
+    #[test]
+    fn test_skew_is_ignored_unless_enabled() {
+        let server = MockServer::with_handler(|_| {
+            let server_time = Utc::now() + ChronoDuration::hours(1);
+            MockResponse::status(200).header("Date", &server_time.to_rfc2822())
+        });
+        let client = PulseKit::new(Config {
+            endpoint: server.url(),
+            ..Default::default()
+        });
+
+        client.capture_message("learn offset", Level::Info);
+        client.flush_blocking();
+
+        assert_eq!(client.delivery.timestamps.offset_ms(), 0);
+    }
+
+    #[test]
+    fn test_server_mode_omits_the_timestamp() {
+        let transport = crate::MemoryTransport::new();
+        let client = PulseKit::with_transport(
+            Config {
+                timestamp_mode: TimestampMode::Server,
+                ..Default::default()
+            },
+            transport.clone(),
+        );
+
+        client.capture_message("stamped on arrival", Level::Info);
+        client.flush_blocking();
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&transport.requests()[0].body).unwrap();
+        assert_eq!(body["message"], "stamped on arrival");
+        assert!(body.get("timestamp").is_none());
+    }
+
+    #[test]
+    fn test_timestamp_is_sent_in_the_same_rfc3339_form() {
+        let timestamp = DateTime::parse_from_rfc3339("2023-11-14T09:30:00.250+02:00")
+            .unwrap()
+            .with_timezone(&Utc);
+        let event = crate::Event {
+            event_type: "backfill".to_string(),
+            timestamp: Some(timestamp),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["timestamp"], timestamp.to_rfc3339());
+        assert_eq!(json["timestamp"], "2023-11-14T07:30:00.250+00:00");
+        let parsed: crate::Event = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.timestamp, Some(timestamp));
+
+        let untimed = serde_json::json!({ "type": "backfill" });
+        let parsed: crate::Event = serde_json::from_value(untimed).unwrap();
+        assert!(parsed.timestamp.is_none());
+        let malformed = serde_json::json!({ "type": "backfill", "timestamp": "yesterday" });
+        assert!(serde_json::from_value::<crate::Event>(malformed).is_err());
+    }
+}
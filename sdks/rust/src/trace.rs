@@ -0,0 +1,102 @@
+//! W3C trace context (`traceparent`) parsing for correlating events.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// Trace and span ids carried by a `traceparent` header.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TraceContext {
+    pub(crate) trace_id: String,
+    pub(crate) span_id: String,
+}
+
+impl TraceContext {
+    /// Parse a `traceparent` header such as
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+    pub(crate) fn parse(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+
+        let valid = is_hex(version, 2)
+            && version != "ff"
+            && (version != "00" || parts.next().is_none())
+            && is_hex_id(trace_id, 32)
+            && is_hex_id(span_id, 16)
+            && is_hex(flags, 2);
+        valid.then(|| Self {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+        })
+    }
+
+    /// Fresh random ids, for events without a usable incoming context.
+    pub(crate) fn generate() -> Self {
+        Self {
+            trace_id: format!("{:016x}{:016x}", random_u64(), random_u64()),
+            span_id: format!("{:016x}", random_u64()),
+        }
+    }
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Lowercase hex of the given length that is not all zeros.
+fn is_hex_id(value: &str, len: usize) -> bool {
+    is_hex(value, len) && value.bytes().any(|b| b != b'0')
+}
+
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Event, PulseKit};
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_parse_rejects_malformed_headers() {
+        assert!(TraceContext::parse(TRACEPARENT).is_some());
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            assert_eq!(TraceContext::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_forwarded_traceparent_sets_trace_fields() {
+        let client = PulseKit::new(Config::default());
+        client.capture_with_traceparent(Event::default(), TRACEPARENT);
+        client.capture_with_traceparent(Event::default(), "garbage");
+
+        let events = client.delivery.take_events();
+        assert_eq!(
+            events[0].trace_id.as_deref(),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
+        );
+        assert_eq!(events[0].span_id.as_deref(), Some("00f067aa0ba902b7"));
+
+        let generated = TraceContext {
+            trace_id: events[1].trace_id.clone().unwrap(),
+            span_id: events[1].span_id.clone().unwrap(),
+        };
+        assert_ne!(generated.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert!(is_hex_id(&generated.trace_id, 32) && is_hex_id(&generated.span_id, 16));
+    }
+}
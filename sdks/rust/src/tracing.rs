@@ -0,0 +1,199 @@
+//! A `tracing_subscriber` layer that captures `tracing` events.
+
+use crate::{ClientOverrides, Event, Level, PulseKit};
+use ::tracing::field::{Field, Visit};
+use ::tracing::span::{Attributes, Id, Record};
+use ::tracing::Subscriber;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Captures `tracing` events at or above a minimum level (`ERROR` by
+/// default) as `log` events.
+///
+/// The event's `message` becomes the message, its other fields and those of
+/// the spans it was recorded in become metadata (event fields win over span
+/// fields, inner spans over outer ones), and its target is sent as the
+/// `target` tag.
+///
+/// ```no_run
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let client = pulsekit::PulseKit::new(Default::default());
+/// let subscriber = tracing_subscriber::registry()
+///     .with(pulsekit::tracing::PulseKitLayer::new(&client));
+/// tracing::subscriber::set_global_default(subscriber).unwrap();
+/// ```
+pub struct PulseKitLayer {
+    client: PulseKit,
+    min_level: ::tracing::Level,
+}
+
+impl PulseKitLayer {
+    /// A layer that captures through a sub-client of `client`, sharing its
+    /// queue.
+    pub fn new(client: &PulseKit) -> Self {
+        Self {
+            client: client.sub_client(ClientOverrides::default()),
+            min_level: ::tracing::Level::ERROR,
+        }
+    }
+
+    /// Also capture events less severe than `ERROR`, down to `level`.
+    pub fn with_min_level(mut self, level: ::tracing::Level) -> Self {
+        self.min_level = level;
+        self
+    }
+}
+
+impl fmt::Debug for PulseKitLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PulseKitLayer")
+            .field("min_level", &self.min_level)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Fields recorded on a span, kept in its extensions.
+#[derive(Default)]
+struct SpanFields(HashMap<String, Value>);
+
+/// Collects field values as JSON.
+struct FieldVisitor<'a>(&'a mut HashMap<String, Value>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0
+            .insert(field.name().to_string(), Value::from(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(
+            field.name().to_string(),
+            Value::from(format!("{:?}", value)),
+        );
+    }
+}
+
+fn to_level(level: ::tracing::Level) -> Level {
+    match level {
+        ::tracing::Level::ERROR => Level::Error,
+        ::tracing::Level::WARN => Level::Warning,
+        ::tracing::Level::INFO => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+impl<S> Layer<S> for PulseKitLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = SpanFields::default();
+        attrs.record(&mut FieldVisitor(&mut fields.0));
+        span.extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(fields) = extensions.get_mut::<SpanFields>() {
+            values.record(&mut FieldVisitor(&mut fields.0));
+        }
+    }
+
+    fn on_event(&self, event: &::tracing::Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > self.min_level {
+            return;
+        }
+
+        let mut fields = HashMap::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(span_fields) = span.extensions().get::<SpanFields>() {
+                    fields.extend(span_fields.0.clone());
+                }
+            }
+        }
+        event.record(&mut FieldVisitor(&mut fields));
+        let message = match fields.remove("message") {
+            Some(Value::String(message)) => message,
+            Some(other) => other.to_string(),
+            None => metadata.name().to_string(),
+        };
+
+        self.client.capture(Event {
+            event_type: "log".to_string(),
+            level: Some(to_level(*metadata.level())),
+            message: Some(message),
+            tags: Some(HashMap::from([(
+                "target".to_string(),
+                metadata.target().to_string(),
+            )])),
+            metadata: (!fields.is_empty()).then_some(fields),
+            ..Default::default()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, MemoryTransport};
+    use serde_json::json;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_error_events_reach_the_transport() {
+        let transport = MemoryTransport::new();
+        let client = PulseKit::with_transport(Config::default(), transport.clone());
+        let subscriber = tracing_subscriber::registry().with(PulseKitLayer::new(&client));
+
+        ::tracing::subscriber::with_default(subscriber, || {
+            let _checkout = ::tracing::info_span!("checkout", user = "ada", order_id = 1).entered();
+            ::tracing::info!("cart loaded");
+            ::tracing::error!(order_id = 42, retries = 3, "charge failed");
+        });
+        client.flush_blocking();
+
+        let events = transport.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, "log");
+        assert_eq!(events[0].level, Some(Level::Error));
+        assert_eq!(events[0].message.as_deref(), Some("charge failed"));
+        assert_eq!(events[0].tags.as_ref().unwrap()["target"], module_path!());
+        let metadata = events[0].metadata.as_ref().unwrap();
+        assert_eq!(metadata["user"], json!("ada"));
+        assert_eq!(metadata["order_id"], json!(42));
+        assert_eq!(metadata["retries"], json!(3));
+    }
+}
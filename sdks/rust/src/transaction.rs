@@ -0,0 +1,342 @@
+//! Timed transactions made of nested spans, sent as `transaction` events.
+
+use crate::clock::Clock;
+use crate::time::Instant;
+use crate::trace::TraceContext;
+use crate::{Event, Level, PulseKit, SAMPLE_OVERRIDE_KEY};
+use chrono::{DateTime, SecondsFormat, Utc};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// A span once finished, waiting for its transaction to be sent.
+#[derive(Debug)]
+struct FinishedSpan {
+    span_id: String,
+    parent_span_id: String,
+    op: String,
+    description: Option<String>,
+    start_timestamp: DateTime<Utc>,
+    end_timestamp: DateTime<Utc>,
+    duration_ms: f64,
+}
+
+/// When a span or transaction started, by wall clock and monotonic clock.
+#[derive(Debug, Clone, Copy)]
+struct Start {
+    timestamp: DateTime<Utc>,
+    instant: Instant,
+}
+
+impl Start {
+    fn now(clock: &dyn Clock) -> Self {
+        Self {
+            timestamp: clock.now(),
+            instant: clock.instant(),
+        }
+    }
+
+    /// The end timestamp and the duration in milliseconds, measured on the
+    /// monotonic clock so a wall clock change does not skew it.
+    fn end(&self, clock: &dyn Clock) -> (DateTime<Utc>, f64) {
+        let elapsed = clock.instant().saturating_duration_since(self.instant);
+        let end = self.timestamp + chrono::Duration::from_std(elapsed).unwrap_or_default();
+        (end, elapsed.as_secs_f64() * 1000.0)
+    }
+}
+
+/// A timed operation, such as handling a request, started by
+/// [`PulseKit::start_transaction`].
+///
+/// It is sent as a `transaction` event when finished or dropped, with its
+/// duration and the tree of child spans finished before it. Spans still
+/// open at that point are left out.
+pub struct Transaction<'a> {
+    client: &'a PulseKit,
+    name: String,
+    op: String,
+    context: TraceContext,
+    start: Start,
+    sampled: bool,
+    spans: Arc<Mutex<Vec<FinishedSpan>>>,
+}
+
+impl fmt::Debug for Transaction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("name", &self.name)
+            .field("op", &self.op)
+            .field("trace_id", &self.context.trace_id)
+            .field("sampled", &self.sampled)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Transaction<'_> {
+    /// Start a span directly under the transaction.
+    pub fn start_child(&self, op: &str, description: Option<&str>) -> Span {
+        Span::start(
+            &self.client.delivery.clock,
+            &self.spans,
+            &self.context.span_id,
+            op,
+            description,
+        )
+    }
+
+    /// Whether the transaction was kept by `traces_sample_rate`.
+    pub fn is_sampled(&self) -> bool {
+        self.sampled
+    }
+
+    /// The W3C trace id shared by the transaction and its spans.
+    pub fn trace_id(&self) -> &str {
+        &self.context.trace_id
+    }
+
+    /// Finish the transaction and capture it. Dropping it does the same.
+    pub fn finish(self) {}
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        let (end_timestamp, duration_ms) = self.start.end(&*self.client.delivery.clock);
+        let finished = std::mem::take(&mut *self.spans.lock());
+        let tags = HashMap::from([
+            ("transaction.op".to_string(), self.op.clone()),
+            (
+                SAMPLE_OVERRIDE_KEY.to_string(),
+                if self.sampled { "always" } else { "never" }.to_string(),
+            ),
+        ]);
+        let metadata = HashMap::from([
+            ("op".to_string(), Value::from(self.op.clone())),
+            (
+                "start_timestamp".to_string(),
+                Value::from(rfc3339(self.start.timestamp)),
+            ),
+            (
+                "end_timestamp".to_string(),
+                Value::from(rfc3339(end_timestamp)),
+            ),
+            ("duration_ms".to_string(), Value::from(duration_ms)),
+            (
+                "spans".to_string(),
+                span_tree(&finished, &self.context.span_id),
+            ),
+        ]);
+        self.client.capture(Event {
+            event_type: "transaction".to_string(),
+            level: Some(Level::Info),
+            message: Some(self.name.clone()),
+            tags: Some(tags),
+            metadata: Some(metadata),
+            trace_id: Some(self.context.trace_id.clone()),
+            span_id: Some(self.context.span_id.clone()),
+            ..Default::default()
+        });
+    }
+}
+
+/// A timed step within a [`Transaction`], started by
+/// [`Transaction::start_child`] or [`Span::start_child`]. It is recorded
+/// when finished or dropped.
+pub struct Span {
+    span_id: String,
+    parent_span_id: String,
+    op: String,
+    description: Option<String>,
+    start: Start,
+    clock: Arc<dyn Clock>,
+    spans: Arc<Mutex<Vec<FinishedSpan>>>,
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Span")
+            .field("span_id", &self.span_id)
+            .field("parent_span_id", &self.parent_span_id)
+            .field("op", &self.op)
+            .field("description", &self.description)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Span {
+    fn start(
+        clock: &Arc<dyn Clock>,
+        spans: &Arc<Mutex<Vec<FinishedSpan>>>,
+        parent_span_id: &str,
+        op: &str,
+        description: Option<&str>,
+    ) -> Self {
+        Self {
+            span_id: TraceContext::generate().span_id,
+            parent_span_id: parent_span_id.to_string(),
+            op: op.to_string(),
+            description: description.map(str::to_string),
+            start: Start::now(&**clock),
+            clock: clock.clone(),
+            spans: spans.clone(),
+        }
+    }
+
+    /// Start a span nested under this one.
+    pub fn start_child(&self, op: &str, description: Option<&str>) -> Span {
+        Span::start(&self.clock, &self.spans, &self.span_id, op, description)
+    }
+
+    /// The span's 16-digit hex id.
+    pub fn span_id(&self) -> &str {
+        &self.span_id
+    }
+
+    /// Finish the span. Dropping it does the same.
+    pub fn finish(self) {}
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let (end_timestamp, duration_ms) = self.start.end(&*self.clock);
+        self.spans.lock().push(FinishedSpan {
+            span_id: std::mem::take(&mut self.span_id),
+            parent_span_id: std::mem::take(&mut self.parent_span_id),
+            op: std::mem::take(&mut self.op),
+            description: self.description.take(),
+            start_timestamp: self.start.timestamp,
+            end_timestamp,
+            duration_ms,
+        });
+    }
+}
+
+fn rfc3339(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// The spans under `parent_span_id` in start order, each with its own
+/// children nested under `spans`.
+fn span_tree(finished: &[FinishedSpan], parent_span_id: &str) -> Value {
+    let mut children: Vec<&FinishedSpan> = finished
+        .iter()
+        .filter(|span| span.parent_span_id == parent_span_id)
+        .collect();
+    children.sort_by_key(|span| span.start_timestamp);
+    children
+        .into_iter()
+        .map(|span| {
+            json!({
+                "span_id": span.span_id,
+                "parent_span_id": span.parent_span_id,
+                "op": span.op,
+                "description": span.description,
+                "start_timestamp": rfc3339(span.start_timestamp),
+                "end_timestamp": rfc3339(span.end_timestamp),
+                "duration_ms": span.duration_ms,
+                "spans": span_tree(finished, &span.span_id),
+            })
+        })
+        .collect()
+}
+
+impl PulseKit {
+    /// Start timing a transaction named `name`, with `op` naming the kind
+    /// of work, e.g. `http.server`. Whether it is sent is decided now by
+    /// `traces_sample_rate`, independently of `sample_rate`.
+    ///
+    /// ```
+    /// # let client = pulsekit::PulseKit::new(pulsekit::Config::default());
+    /// let transaction = client.start_transaction("GET /orders", "http.server");
+    /// let query = transaction.start_child("db.query", Some("SELECT * FROM orders"));
+    /// query.finish();
+    /// transaction.finish();
+    /// ```
+    pub fn start_transaction(&self, name: &str, op: &str) -> Transaction<'_> {
+        Transaction {
+            client: self,
+            name: name.to_string(),
+            op: op.to_string(),
+            context: TraceContext::generate(),
+            start: Start::now(&*self.delivery.clock),
+            sampled: self.delivery.sampler.keep(self.config.traces_sample_rate),
+            spans: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_transaction_is_sent_with_its_span_tree() {
+        let client = PulseKit::new(Config {
+            capture_thread_info: false,
+            capture_runtime_context: false,
+            ..Default::default()
+        });
+        let transaction = client.start_transaction("GET /orders", "http.server");
+        let query = transaction.start_child("db.query", Some("SELECT * FROM orders"));
+        let fetch = query.start_child("db.fetch", None);
+        fetch.finish();
+        query.finish();
+        let render = transaction.start_child("render", None);
+        drop(render);
+        let trace_id = transaction.trace_id().to_string();
+        transaction.finish();
+
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.event_type, "transaction");
+        assert_eq!(event.message.as_deref(), Some("GET /orders"));
+        assert_eq!(event.trace_id.as_deref(), Some(trace_id.as_str()));
+        let tags = event.tags.as_ref().unwrap();
+        assert_eq!(tags["transaction.op"], "http.server");
+        assert!(!tags.contains_key(SAMPLE_OVERRIDE_KEY));
+
+        let metadata = event.metadata.as_ref().unwrap();
+        assert_eq!(metadata["op"], "http.server");
+        let duration = metadata["duration_ms"].as_f64().unwrap();
+        let spans = metadata["spans"].as_array().unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0]["op"], "db.query");
+        assert_eq!(spans[0]["description"], "SELECT * FROM orders");
+        assert_eq!(
+            spans[0]["parent_span_id"].as_str(),
+            event.span_id.as_deref()
+        );
+        assert!(spans[0]["duration_ms"].as_f64().unwrap() <= duration);
+        assert_eq!(spans[0]["spans"][0]["op"], "db.fetch");
+        assert_eq!(spans[0]["spans"][0]["parent_span_id"], spans[0]["span_id"]);
+        assert_eq!(spans[1]["op"], "render");
+        assert_eq!(spans[1]["spans"], json!([]));
+    }
+
+    #[test]
+    fn test_traces_sample_rate_is_independent_of_sample_rate() {
+        let client = PulseKit::new(Config {
+            sample_rate: 0.0,
+            traces_sample_rate: 1.0,
+            ..Default::default()
+        });
+        client.start_transaction("kept", "task").finish();
+        client.capture_message("sampled out", Level::Info);
+        assert_eq!(client.delivery.take_events().len(), 1);
+
+        let client = PulseKit::new(Config {
+            traces_sample_rate: 0.0,
+            ..Default::default()
+        });
+        let transaction = client.start_transaction("dropped", "task");
+        assert!(!transaction.is_sampled());
+        transaction.finish();
+        client.capture_message("kept", Level::Info);
+        let events = client.delivery.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, "message");
+    }
+}
@@ -0,0 +1,166 @@
+//! Transport that posts requests with JavaScript's `fetch`, for wasm32.
+
+use super::{
+    Transport, TransportFuture, TransportRequest, TransportResponse, MAX_RESPONSE_BODY_BYTES,
+};
+use crate::time::{Duration, Instant};
+use crate::{timer, Config, PulseKitError};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    /// The global `fetch`, so it works in a page, a worker or Node alike.
+    #[wasm_bindgen(js_name = fetch)]
+    fn fetch_request(request: &web_sys::Request) -> js_sys::Promise;
+}
+
+/// The transport clients use on wasm32, where there is no socket to hand
+/// `reqwest`'s blocking client.
+///
+/// Only async sends work: [`Transport::send`] fails with
+/// [`PulseKitError::InvalidRequest`], as the browser can't be waited on
+/// without returning to its event loop. Flush with
+/// [`PulseKit::flush`](crate::PulseKit::flush) instead of
+/// [`PulseKit::flush_blocking`](crate::PulseKit::flush_blocking).
+pub struct FetchTransport {
+    timeout: Option<Duration>,
+}
+
+impl FetchTransport {
+    /// A transport whose requests never time out.
+    pub fn new() -> Self {
+        Self::with_timeout(None)
+    }
+
+    /// A transport that aborts requests taking longer than `timeout`.
+    /// `None` or a zero duration means no timeout.
+    pub fn with_timeout(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout: timeout.filter(|timeout| !timeout.is_zero()),
+        }
+    }
+
+    /// The transport a client built from `config` uses.
+    ///
+    /// Fails with [`PulseKitError::InvalidRequest`] if `config` sets a
+    /// `proxy`, `root_certificates` or `danger_accept_invalid_certs`, which
+    /// are up to the browser rather than the page.
+    pub(crate) fn from_config(config: &Config) -> Result<Self, PulseKitError> {
+        let unsupported = if config.proxy.is_some() {
+            Some("proxy")
+        } else if !config.root_certificates.is_empty() {
+            Some("root_certificates")
+        } else if config.danger_accept_invalid_certs {
+            Some("danger_accept_invalid_certs")
+        } else {
+            None
+        };
+        if let Some(field) = unsupported {
+            return Err(PulseKitError::InvalidRequest(format!(
+                "`{}` is not supported on wasm32",
+                field
+            )));
+        }
+        Ok(Self::with_timeout(config.timeout))
+    }
+}
+
+impl Default for FetchTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for FetchTransport {
+    fn send(&self, _: &TransportRequest) -> Result<TransportResponse, PulseKitError> {
+        Err(PulseKitError::InvalidRequest(
+            "blocking sends are not available on wasm32, flush with `flush().await`".to_string(),
+        ))
+    }
+
+    fn send_async<'a>(&'a self, request: &'a TransportRequest) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let controller = web_sys::AbortController::new().map_err(js_error)?;
+            let signal = controller.signal();
+            let response = fetch(request, &signal);
+            let Some(timeout) = self.timeout else {
+                return response.await;
+            };
+            match timer::timeout_at(Instant::now() + timeout, response).await {
+                Some(response) => response,
+                None => {
+                    controller.abort();
+                    Err(PulseKitError::Network(format!(
+                        "request timed out after {:?}",
+                        timeout
+                    )))
+                }
+            }
+        })
+    }
+}
+
+async fn fetch(
+    request: &TransportRequest,
+    signal: &web_sys::AbortSignal,
+) -> Result<TransportResponse, PulseKitError> {
+    let headers = web_sys::Headers::new().map_err(js_error)?;
+    for (name, value) in &request.headers {
+        headers
+            .set(name, value)
+            .map_err(|_| PulseKitError::InvalidRequest(format!("header {:?} is invalid", name)))?;
+    }
+    let init = web_sys::RequestInit::new();
+    init.set_method("POST");
+    init.set_headers(&headers);
+    init.set_body(&js_sys::Uint8Array::from(&request.body[..]));
+    init.set_signal(Some(signal));
+    let prepared = web_sys::Request::new_with_str_and_init(&request.url, &init)
+        .map_err(|e| PulseKitError::InvalidRequest(describe(&e)))?;
+
+    let resp: web_sys::Response = JsFuture::from(fetch_request(&prepared))
+        .await
+        .map_err(js_error)?
+        .unchecked_into();
+    let status = resp.status();
+    let headers = collect_headers(&resp.headers());
+    // `fetch` only hands over the whole body, so the cap applies once it
+    // has arrived.
+    let mut body = match resp.array_buffer() {
+        Ok(buffer) => JsFuture::from(buffer)
+            .await
+            .map(|buffer| js_sys::Uint8Array::new(&buffer).to_vec())
+            .unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+    body.truncate(MAX_RESPONSE_BODY_BYTES);
+    Ok(TransportResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn collect_headers(headers: &web_sys::Headers) -> Vec<(String, String)> {
+    let Ok(Some(entries)) = js_sys::try_iter(headers) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let entry: js_sys::Array = entry.ok()?.dyn_into().ok()?;
+            Some((entry.get(0).as_string()?, entry.get(1).as_string()?))
+        })
+        .collect()
+}
+
+fn js_error(err: JsValue) -> PulseKitError {
+    PulseKitError::Network(describe(&err))
+}
+
+fn describe(err: &JsValue) -> String {
+    match err.dyn_ref::<js_sys::Error>() {
+        Some(err) => String::from(err.message()),
+        None => format!("{:?}", err),
+    }
+}
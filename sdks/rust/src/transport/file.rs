@@ -0,0 +1,73 @@
+//! Transport that appends batches to a local file.
+
+use super::{Transport, TransportRequest, TransportResponse};
+use crate::PulseKitError;
+use parking_lot::Mutex;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Appends every request body to a file, one JSON document per line.
+///
+/// Mostly useful as `Config::fallback_transport`, so batches the server
+/// could not accept are kept on disk instead of being lost. Writes are
+/// reported as status `202`.
+pub struct FileTransport {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileTransport {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl Transport for FileTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, PulseKitError> {
+        let _guard = self.lock.lock();
+        let mut line = request.body.clone();
+        line.push(b'\n');
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(&line))
+            .map_err(|e| PulseKitError::Network(format!("{}: {}", self.path.display(), e)))?;
+
+        Ok(TransportResponse {
+            status: 202,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_appends_one_line_per_request() {
+        let path = std::env::temp_dir().join(format!("pulsekit-file-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let transport = FileTransport::new(&path);
+
+        for body in [r#"{"message":"one"}"#, r#"{"message":"two"}"#] {
+            let request = TransportRequest {
+                url: String::new(),
+                headers: Vec::new(),
+                body: body.as_bytes().to_vec(),
+                event_count: 1,
+            };
+            assert_eq!(transport.send(&request).unwrap().status, 202);
+        }
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(written, "{\"message\":\"one\"}\n{\"message\":\"two\"}\n");
+        std::fs::remove_file(path).unwrap();
+    }
+}
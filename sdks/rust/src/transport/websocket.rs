@@ -0,0 +1,341 @@
+//! Persistent WebSocket transport for high-volume senders.
+
+use super::{Transport, TransportRequest, TransportResponse};
+use crate::PulseKitError;
+use parking_lot::Mutex;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+use tungstenite::client::IntoClientRequest;
+use tungstenite::http::HeaderValue;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// Streams serialized batches over one long-lived WebSocket connection to
+/// the server's `/ws/events` endpoint instead of issuing an HTTP request per
+/// batch.
+///
+/// The connection is opened lazily on the first send and re-established
+/// once if it has dropped. Writes block until the socket accepts the frame,
+/// so a slow server pushes back on the flush path instead of letting
+/// buffered frames pile up in memory. The server does not acknowledge
+/// individual frames; a successful write is reported as status `202`.
+/// JSON bodies go out as text frames, MessagePack and compressed bodies as
+/// binary frames.
+/// Connecting, the handshake and each write give up after the timeout.
+pub struct WebSocketTransport {
+    connection: Arc<Connection>,
+}
+
+struct Connection {
+    url: String,
+    timeout: Option<Duration>,
+    socket: Mutex<Option<Socket>>,
+}
+
+impl WebSocketTransport {
+    /// Create a transport for a PulseKit endpoint such as
+    /// `https://pulsekit.example.com`, which streams to
+    /// `wss://pulsekit.example.com/ws/events`, with a 5 second timeout.
+    pub fn new(endpoint: &str) -> Self {
+        Self::with_timeout(endpoint, Some(Duration::from_secs(5)))
+    }
+
+    /// Create a transport whose connects and writes give up after
+    /// `timeout`, or never with `None`.
+    pub fn with_timeout(endpoint: &str, timeout: Option<Duration>) -> Self {
+        let base = endpoint.trim_end_matches('/');
+        let base = if let Some(rest) = base.strip_prefix("https://") {
+            format!("wss://{}", rest)
+        } else if let Some(rest) = base.strip_prefix("http://") {
+            format!("ws://{}", rest)
+        } else {
+            base.to_string()
+        };
+
+        Self {
+            connection: Arc::new(Connection {
+                url: format!("{}/ws/events", base),
+                timeout,
+                socket: Mutex::new(None),
+            }),
+        }
+    }
+}
+
+impl Connection {
+    fn connect(&self, request: &TransportRequest) -> Result<Socket, PulseKitError> {
+        let mut handshake = self
+            .url
+            .as_str()
+            .into_client_request()
+            .map_err(|e| PulseKitError::InvalidRequest(e.to_string()))?;
+        if let Some(key) = request.header("X-PulseKit-Key") {
+            let value = HeaderValue::from_str(key)
+                .map_err(|e| PulseKitError::InvalidRequest(e.to_string()))?;
+            handshake.headers_mut().insert("X-PulseKit-Key", value);
+        }
+
+        let uri = handshake.uri();
+        let host = uri
+            .host()
+            .ok_or_else(|| PulseKitError::InvalidRequest(format!("no host in {}", self.url)))?;
+        let port = uri
+            .port_u16()
+            .unwrap_or(if uri.scheme_str() == Some("wss") { 443 } else { 80 });
+        let stream = self.open_stream(host, port)?;
+        stream
+            .set_read_timeout(self.timeout)
+            .and_then(|()| stream.set_write_timeout(self.timeout))
+            .map_err(|e| PulseKitError::Network(e.to_string()))?;
+
+        let (socket, _) = tungstenite::client_tls(handshake, stream)
+            .map_err(|e| PulseKitError::Network(e.to_string()))?;
+        Ok(socket)
+    }
+
+    /// Connect to the first address of `host` that accepts within the
+    /// timeout.
+    fn open_stream(&self, host: &str, port: u16) -> Result<TcpStream, PulseKitError> {
+        let mut last_error = None;
+        let addrs = (host, port)
+            .to_socket_addrs()
+            .map_err(|e| PulseKitError::Network(e.to_string()))?;
+        for addr in addrs {
+            let connected = match self.timeout {
+                Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                None => TcpStream::connect(addr),
+            };
+            match connected {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e.to_string()),
+            }
+        }
+        Err(PulseKitError::Network(last_error.unwrap_or_else(|| {
+            format!("{} did not resolve to any address", host)
+        })))
+    }
+
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, PulseKitError> {
+        let frame = frame(request);
+        let mut socket = self.socket.lock();
+        let mut last_error = None;
+
+        // A pooled connection may have been closed by the server since the
+        // last batch, so allow one reconnect before giving up.
+        for _ in 0..2 {
+            if socket.is_none() {
+                *socket = Some(self.connect(request)?);
+            }
+            let Some(ws) = socket.as_mut() else {
+                continue;
+            };
+
+            match ws.send(frame.clone()) {
+                Ok(()) => {
+                    return Ok(TransportResponse {
+                        status: 202,
+                        ..Default::default()
+                    })
+                }
+                Err(e) => {
+                    last_error = Some(e.to_string());
+                    *socket = None;
+                }
+            }
+        }
+
+        Err(PulseKitError::Network(
+            last_error.unwrap_or_else(|| "websocket connection lost".to_string()),
+        ))
+    }
+}
+
+fn frame(request: &TransportRequest) -> Message {
+    let json = request
+        .header("Content-Type")
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    if json && request.header("Content-Encoding").is_none() {
+        if let Ok(text) = String::from_utf8(request.body.clone()) {
+            return Message::Text(text);
+        }
+    }
+    Message::Binary(request.body.clone())
+}
+
+impl Transport for WebSocketTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, PulseKitError> {
+        self.connection.send(request)
+    }
+
+    /// Sends on Tokio's blocking pool, as the socket only does blocking
+    /// writes.
+    #[cfg(feature = "async")]
+    fn send_async<'a>(&'a self, request: &'a TransportRequest) -> super::TransportFuture<'a> {
+        let connection = self.connection.clone();
+        let request = request.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || connection.send(&request))
+                .await
+                .map_err(|e| PulseKitError::Network(e.to_string()))?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Level, PulseKit};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_batches_stream_over_one_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let frames = Arc::new(Mutex::new(Vec::new()));
+
+        let (accepted, received) = (connections.clone(), frames.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let received = received.clone();
+                thread::spawn(move || {
+                    let mut ws = tungstenite::accept(stream).unwrap();
+                    while let Ok(Message::Text(text)) = ws.read() {
+                        received.lock().push(text);
+                    }
+                });
+            }
+        });
+
+        let client = PulseKit::with_transport(
+            Config {
+                endpoint: endpoint.clone(),
+                api_key: "pk_test".to_string(),
+                ..Default::default()
+            },
+            WebSocketTransport::new(&endpoint),
+        );
+        client.capture_message("first", Level::Info);
+        client.capture_message("second", Level::Info);
+        client.flush_blocking();
+        client.capture_message("third", Level::Info);
+        client.flush_blocking();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while frames.lock().len() < 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let frames = frames.lock();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].contains("first") && frames[0].contains("second"));
+        assert!(frames[1].contains("third"));
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async_sends_run_off_the_runtime() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let received = frames.clone();
+        thread::spawn(move || {
+            let stream = listener.incoming().flatten().next().unwrap();
+            let mut ws = tungstenite::accept(stream).unwrap();
+            while let Ok(Message::Text(text)) = ws.read() {
+                received.lock().push(text);
+            }
+        });
+
+        let client = PulseKit::with_transport(
+            Config {
+                endpoint: endpoint.clone(),
+                ..Default::default()
+            },
+            WebSocketTransport::new(&endpoint),
+        );
+        client.capture_message("async", Level::Info);
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(client.flush());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while frames.lock().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(frames.lock()[0].contains("async"));
+    }
+
+    #[test]
+    fn test_handshake_gives_up_after_the_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        // Accepts the connection but never answers the handshake
+        let _silent = thread::spawn(move || listener.accept().map(|(stream, _)| stream));
+
+        let transport =
+            WebSocketTransport::with_timeout(&endpoint, Some(Duration::from_millis(100)));
+        let request = TransportRequest {
+            url: endpoint,
+            headers: Vec::new(),
+            body: b"{}".to_vec(),
+            event_count: 1,
+        };
+        let started = Instant::now();
+        assert!(matches!(
+            transport.send(&request),
+            Err(PulseKitError::Network(_))
+        ));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_only_plain_json_is_sent_as_text() {
+        let request = |headers: &[(&str, &str)], body: &[u8]| TransportRequest {
+            url: String::new(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: body.to_vec(),
+            event_count: 1,
+        };
+
+        let json = request(&[("Content-Type", "application/json")], b"{}");
+        assert_eq!(frame(&json), Message::Text("{}".to_string()));
+
+        let msgpack = request(&[("Content-Type", "application/msgpack")], &[0x80]);
+        assert_eq!(frame(&msgpack), Message::Binary(vec![0x80]));
+
+        let gzipped = request(
+            &[
+                ("Content-Type", "application/json"),
+                ("Content-Encoding", "gzip"),
+            ],
+            &[0x1f, 0x8b, 0xff],
+        );
+        assert_eq!(frame(&gzipped), Message::Binary(vec![0x1f, 0x8b, 0xff]));
+    }
+
+    #[test]
+    fn test_endpoint_is_mapped_to_ws_url() {
+        assert_eq!(
+            WebSocketTransport::new("https://pulse.example.com/").connection.url,
+            "wss://pulse.example.com/ws/events"
+        );
+        assert_eq!(
+            WebSocketTransport::new("http://localhost:4000").connection.url,
+            "ws://localhost:4000/ws/events"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! Smoke tests for wasm32, run with `wasm-bindgen-test-runner`:
+//!
+//! ```text
+//! cargo test --target wasm32-unknown-unknown --features wasm --test wasm
+//! ```
+#![cfg(target_arch = "wasm32")]
+
+use pulsekit::{
+    Config, FetchTransport, Level, MemoryTransport, PulseKit, PulseKitError, Transport,
+    TransportRequest,
+};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn request(url: &str) -> TransportRequest {
+    TransportRequest {
+        url: url.to_string(),
+        headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+        body: br#"{"message":"hello"}"#.to_vec(),
+        event_count: 1,
+    }
+}
+
+#[wasm_bindgen_test]
+async fn test_events_are_flushed_without_threads() {
+    let transport = MemoryTransport::new();
+    let client = PulseKit::with_transport(
+        Config {
+            batch_size: 2,
+            ..Default::default()
+        },
+        transport.clone(),
+    );
+
+    client.capture_message("first", Level::Info);
+    client.capture_error("second");
+    client.capture_message("third", Level::Warning);
+    let outcome = client.flush().await;
+
+    assert_eq!((outcome.sent, outcome.failed), (3, 0));
+    let mut messages: Vec<_> = transport
+        .events()
+        .into_iter()
+        .filter_map(|event| event.message)
+        .collect();
+    messages.sort();
+    assert_eq!(messages, ["first", "second", "third"]);
+}
+
+#[wasm_bindgen_test]
+async fn test_fetch_transport_reads_the_response() {
+    let transport = FetchTransport::new();
+    let response = transport
+        .send_async(&request("data:application/json,{\"accepted\":1}"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("content-type"), Some("application/json"));
+    assert_eq!(response.body, br#"{"accepted":1}"#);
+}
+
+#[wasm_bindgen_test]
+fn test_fetch_transport_refuses_blocking_sends() {
+    let result = FetchTransport::new().send(&request("https://example.com"));
+
+    assert!(matches!(result, Err(PulseKitError::InvalidRequest(_))));
+}
+
+#[wasm_bindgen_test]
+fn test_native_only_settings_are_rejected() {
+    let result = PulseKit::try_new(Config {
+        proxy: Some("http://proxy.internal:3128".to_string()),
+        ..Default::default()
+    });
+
+    assert!(matches!(result, Err(PulseKitError::InvalidRequest(_))));
+}